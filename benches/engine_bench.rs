@@ -0,0 +1,138 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Benchmarks of the execution engine, over synthetic configs
+//!
+//! Operations are mocked by closures that don't run git, so what is measured
+//! is seidr itself: selecting, resolving, and scheduling repos.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use seidr::git::{Category, Config, OnFailure, Repo, RepoFlags, RepoKinds, SeriesItem};
+use seidr::parallel::Parallelism;
+use seidr::selection::Selection;
+use std::collections::HashMap;
+
+const SIZES: [usize; 3] = [10, 100, 1000];
+
+/// Repos per category in synthetic configs
+const PER_CATEGORY: usize = 10;
+
+/// Makes a config of n repos, in categories of PER_CATEGORY repos
+fn synthetic(n: usize) -> Config {
+    let mut categories: HashMap<String, Category> = HashMap::new();
+    for i in 0..n {
+        let category = format!("c{}", i / PER_CATEGORY);
+        let repo = Repo {
+            name: Some(format!("r{i}")),
+            path: Some(format!("/tmp/seidr-bench/{category}/")),
+            url: Some(format!("git@example.com:bench/r{i}.git")),
+            kind: Some(RepoKinds::GitRepo),
+            flags: Some(vec![RepoFlags::Pull, RepoFlags::Push]),
+            ..Default::default()
+        };
+        categories
+            .entry(category)
+            .or_insert_with(|| Category {
+                repos: Some(HashMap::new()),
+                ..Default::default()
+            })
+            .repos
+            .as_mut()
+            .unwrap()
+            .insert(format!("r{i}"), repo);
+    }
+    Config {
+        categories,
+        ..Default::default()
+    }
+}
+
+/// Builds a series like quick, with mock operations
+fn series<'a>() -> Vec<SeriesItem<'a>> {
+    ["pull", "add", "commit", "push"]
+        .into_iter()
+        .map(|operation| SeriesItem {
+            operation,
+            closure: Box::new(|repo: &Repo| black_box(repo.name.is_some())),
+            on_failure: OnFailure::Continue,
+        })
+        .collect()
+}
+
+pub fn selection_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("selection");
+    for n in SIZES {
+        // Every other repo, by name
+        let targets: String = (0..n)
+            .step_by(2)
+            .map(|i| format!("c{}/r{i}\n", i / PER_CATEGORY))
+            .collect();
+        group.bench_with_input(BenchmarkId::new("apply", n), &n, |b, &n| {
+            b.iter_batched(
+                || synthetic(n),
+                |mut config| {
+                    Selection::parse(black_box(&targets))
+                        .unwrap()
+                        .apply(&mut config)
+                        .unwrap()
+                },
+                BatchSize::LargeInput,
+            )
+        });
+        let config = synthetic(n);
+        group.bench_with_input(BenchmarkId::new("path filter", n), &n, |b, _| {
+            b.iter(|| Selection::from_path_filter(&config, black_box("/tmp/seidr-bench/c1*/**")))
+        });
+    }
+    group.finish();
+}
+
+pub fn resolver_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("resolver");
+    for n in SIZES {
+        let config = synthetic(n);
+        group.bench_with_input(BenchmarkId::new("find repo", n), &n, |b, _| {
+            b.iter(|| config.find_repo(black_box("c0"), black_box("r1")))
+        });
+        // Misses are slower, as they look for a suggestion
+        group.bench_with_input(BenchmarkId::new("find missing repo", n), &n, |b, _| {
+            b.iter(|| config.find_repo(black_box("c0"), black_box("r01")))
+        });
+    }
+    group.finish();
+}
+
+pub fn series_benchmark(c: &mut Criterion) {
+    c.bench_function("series construction", |b| b.iter(series));
+}
+
+pub fn engine_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("engine");
+    for n in SIZES {
+        for workers in [1, 4] {
+            let mut config = synthetic(n);
+            config.settings.quiet = true;
+            config.parallelism = Some(Parallelism {
+                network: workers,
+                local: workers,
+            });
+            group.bench_with_input(
+                BenchmarkId::new(format!("{workers} workers"), n),
+                &n,
+                |b, _| b.iter(|| config.all_on_all(series(), false)),
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    selection_benchmark,
+    resolver_benchmark,
+    series_benchmark,
+    engine_benchmark
+);
+criterion_main!(benches);
@@ -120,10 +120,12 @@
           };
 
           # Run `nix build .#test` to run tests
+          # Repo tests run against local remotes, so only git is needed
           test = naersk'.buildPackage {
             src = ./.;
             mode = "test";
             inherit buildInputs;
+            nativeBuildInputs = with pkgs; [git];
           };
 
           # Run `nix build .#clippy` to lint code
@@ -0,0 +1,270 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Repos that are archives, downloaded and extracted instead of cloned
+//!
+//! A `UrlRepo` with `extract` set has the url of a `.tar`, `.tar.gz`, `.tgz`,
+//! `.tar.xz`, `.tar.bz2` or `.zip` archive, like a font pack or a prebuilt
+//! tool. Cloning downloads the archive and extracts it to the checkout dir,
+//! and pulling downloads it again, extracting it anew if it changed. With
+//! `hash` set, the archive has to have that sha256, as `sha256sum` writes it
+//! and upstreams publish it, or it isn't extracted.
+//!
+//! The hash of the archive last extracted to each checkout dir is kept in the
+//! state dir, per host like the hashes of copies, see the copy module. What
+//! was extracted before is moved to the trash when it's replaced, and a dir
+//! seidr didn't extract to is never replaced.
+
+use log::{debug, error, info, trace, warn};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, PoisonError};
+
+use crate::git::Repo;
+use crate::trash::Trash;
+use crate::utils::dir::{hostname, state_dir};
+use crate::utils::exec::Exec;
+use crate::utils::sha256::hash_file;
+
+/// How an archive is packed, by the extension of its url
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    /// Extracted with `tar`, which detects the compression itself
+    Tar,
+    /// Extracted with `unzip`
+    Zip,
+}
+
+impl Format {
+    /// Returns the format of the archive at url, None if it isn't one
+    pub fn of(url: &str) -> Option<Self> {
+        let name = url.rsplit('/').next().unwrap_or(url).to_lowercase();
+        if name.ends_with(".zip") {
+            Some(Format::Zip)
+        } else if [".tar", ".tar.gz", ".tgz", ".tar.xz", ".tar.bz2"]
+            .iter()
+            .any(|extension| name.ends_with(extension))
+        {
+            Some(Format::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+/// What syncing an archive did
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Synced {
+    /// The archive was extracted, as it wasn't, or changed
+    Extracted,
+    /// The archive was already extracted
+    Unchanged,
+}
+
+/// Held while the hashes are read or written, as archives are synced in
+/// parallel
+static HASHES: Mutex<()> = Mutex::new(());
+
+/// The hashes of the archives extracted on this host, by checkout dir
+pub struct Extracted {
+    path: PathBuf,
+    hashes: BTreeMap<String, String>,
+}
+
+impl Extracted {
+    /// Loads the hashes of this host from the state dir
+    pub fn load() -> io::Result<Self> {
+        Self::load_from(Self::path_in(&state_dir()))
+    }
+    /// Where the hashes of this host are kept in state_dir
+    pub fn path_in(state_dir: &Path) -> PathBuf {
+        state_dir
+            .join("archives")
+            .join(format!("{}.yaml", hostname()))
+    }
+    /// Loads the hashes from path, which doesn't have to exist yet
+    pub fn load_from(path: PathBuf) -> io::Result<Self> {
+        let _reading = HASHES.lock().unwrap_or_else(PoisonError::into_inner);
+        Self::read(path)
+    }
+    fn read(path: PathBuf) -> io::Result<Self> {
+        let hashes = match fs::read_to_string(&path) {
+            Ok(yaml) => serde_yaml::from_str(&yaml)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err),
+        };
+        Ok(Extracted { path, hashes })
+    }
+    /// The hash of the archive last extracted to dir, if any
+    pub fn get(&self, dir: &str) -> Option<&str> {
+        self.hashes.get(dir).map(String::as_str)
+    }
+    /// Records that an archive with hash was extracted to dir, and saves the
+    /// hashes
+    ///
+    /// The hashes are read anew first, so what was recorded for other dirs
+    /// since they were loaded, like by another repo pulled at the same time,
+    /// is kept.
+    pub fn record(&mut self, dir: &str, hash: String) -> io::Result<()> {
+        let _writing = HASHES.lock().unwrap_or_else(PoisonError::into_inner);
+        self.hashes = Self::read(self.path.clone())?.hashes;
+        self.hashes.insert(dir.to_string(), hash);
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let yaml = serde_yaml::to_string(&self.hashes)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        fs::write(&self.path, yaml)
+    }
+}
+
+/// Runs command, turning a failure into what it wrote to stderr
+fn run(command: &mut Command, what: &str) -> Result<(), String> {
+    let output = command
+        .exec_output()
+        .map_err(|err| format!("failed to {what}: {err}"))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "failed to {what}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+/// Downloads url to dest, copying it if it's local
+fn download(url: &str, dest: &Path) -> Result<(), String> {
+    let local = url
+        .strip_prefix("file://")
+        .or_else(|| (!url.contains("://")).then_some(url));
+    match local {
+        Some(path) => fs::copy(path, dest)
+            .map(|_| ())
+            .map_err(|err| format!("failed to copy {path}: {err}")),
+        None => run(
+            Command::new("curl")
+                .args(["--fail", "--silent", "--show-error", "--location"])
+                .arg("--output")
+                .arg(dest)
+                .arg(url),
+            &format!("download {url}"),
+        ),
+    }
+}
+
+/// Extracts the archive in format to dir, which has to exist
+fn extract(archive: &Path, format: Format, dir: &Path) -> Result<(), String> {
+    let what = format!("extract {}", archive.display());
+    match format {
+        Format::Tar => run(
+            Command::new("tar")
+                .arg("-xf")
+                .arg(archive)
+                .arg("-C")
+                .arg(dir),
+            &what,
+        ),
+        Format::Zip => run(
+            Command::new("unzip")
+                .args(["-q", "-o"])
+                .arg(archive)
+                .arg("-d")
+                .arg(dir),
+            &what,
+        ),
+    }
+}
+
+/// Whether dir has anything in it
+fn has_entries(dir: &str) -> bool {
+    fs::read_dir(dir).map_or(false, |mut entries| entries.next().is_some())
+}
+
+/// Downloads the archive of repo from url, and extracts it to the checkout
+/// dir of repo if it isn't already, recording its hash in extracted
+///
+/// A changed archive replaces what was extracted before, as a whole, so files
+/// it no longer has don't linger, moving it into trash. A checkout dir with
+/// something in it, but no recorded hash, wasn't extracted by seidr, and is
+/// refused.
+pub fn sync_with(
+    repo: &Repo,
+    url: &str,
+    extracted: &mut Extracted,
+    trash: &Trash,
+) -> Result<Synced, String> {
+    let format = Format::of(url).ok_or_else(|| format!("{url} isn't a tar or zip archive"))?;
+    let dir = repo.checkout_dir();
+    if extracted.get(&dir).is_none() && has_entries(&dir) {
+        return Err(format!(
+            "{dir} wasn't extracted by seidr, not replacing it, move it away first"
+        ));
+    }
+    let download_to = PathBuf::from(format!("{dir}.seidr-download"));
+    let extract_to = PathBuf::from(format!("{dir}.seidr-extract"));
+    let _ = fs::remove_file(&download_to);
+    let _ = fs::remove_dir_all(&extract_to);
+    let result = (|| {
+        download(url, &download_to)?;
+        let hash = hash_file(&download_to).map_err(|err| err.to_string())?;
+        if let Some(pinned) = &repo.hash {
+            let pinned = pinned.trim_start_matches("sha256:").to_lowercase();
+            if hash != pinned {
+                return Err(format!("{url} has hash {hash}, expected {pinned}"));
+            }
+        }
+        if Path::new(&dir).is_dir() && extracted.get(&dir) == Some(&hash) {
+            debug!("{url} is already extracted to {dir}");
+            return Ok(Synced::Unchanged);
+        }
+        fs::create_dir_all(&extract_to)
+            .map_err(|err| format!("failed to create {}: {err}", extract_to.display()))?;
+        extract(&download_to, format, &extract_to)?;
+        if has_entries(&dir) {
+            let trashed = trash
+                .put(Path::new(&dir))
+                .map_err(|err| format!("failed to move {dir} to the trash: {err}"))?;
+            info!("moved what was extracted to {dir} to the trash");
+            debug!("trashed as {}", trashed.stored);
+        } else {
+            match fs::remove_dir(&dir) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                    return Err(format!("failed to remove {dir}: {err}"))
+                }
+                _ => (),
+            }
+        }
+        fs::rename(&extract_to, &dir).map_err(|err| format!("failed to move to {dir}: {err}"))?;
+        extracted
+            .record(&dir, hash)
+            .map_err(|err| format!("failed to record the hash of {url}: {err}"))?;
+        Ok(Synced::Extracted)
+    })();
+    let _ = fs::remove_file(&download_to);
+    let _ = fs::remove_dir_all(&extract_to);
+    result
+}
+
+/// Like `sync_with`, with the hashes in the state dir, logging any error
+pub fn sync(repo: &Repo, url: &str) -> bool {
+    let result = Extracted::load()
+        .map_err(|err| format!("failed to read the extracted archives: {err}"))
+        .and_then(|mut extracted| sync_with(repo, url, &mut extracted, &Trash::default()));
+    match result {
+        Ok(synced) => {
+            debug!("{}: {synced:?}", repo.display_name());
+            true
+        }
+        Err(err) => {
+            error!("{}: {err}", repo.display_name());
+            false
+        }
+    }
+}
@@ -0,0 +1,194 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! An async front to the run engine, for embedding seidr in async
+//! applications, behind the `async` feature
+//!
+//! Git is run as blocking processes, so runs are queued for a few threads of
+//! the engine, and are awaited as futures that the thread running them wakes
+//! when they are done. That keeps the executor free while git runs, and works
+//! on any executor, tokio or otherwise, without pulling one into the default
+//! build, which spawning git through tokio would. The engine is exactly as
+//! async as the runs are, whose git is blocking either way.
+//!
+//! How many runs go at once is limited by the engine, on top of the
+//! parallelism of the config within each run. There is a thread for each run
+//! going, and runs beyond the limit wait in the queue, never on a thread of
+//! their own, or on the executor.
+
+use log::{debug, error, info, trace, warn};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crate::git::Config;
+use crate::report::RunReport;
+
+/// The result of a run, or what it panicked with, and who to wake when it is
+/// there
+struct Slot<T> {
+    result: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+}
+
+/// A queued run, resolving to what it returns
+///
+/// If the run panics, so does polling it.
+pub struct Run<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+impl<T> Future for Run<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut slot = self.slot.lock().unwrap_or_else(PoisonError::into_inner);
+        match slot.result.take() {
+            Some(Ok(result)) => Poll::Ready(result),
+            Some(Err(panic)) => {
+                drop(slot);
+                panic::resume_unwind(panic)
+            }
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// The runs waiting for a turn, and how many threads are running them
+struct Queue {
+    jobs: VecDeque<Job>,
+    threads: usize,
+    max_threads: usize,
+}
+
+/// The turn of a thread running queued runs, given back when dropped, even
+/// if the thread panics
+struct Turn(Arc<Mutex<Queue>>);
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        let mut queue = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        queue.threads -= 1;
+        // Runs left behind by a thread that panicked get another one
+        if thread::panicking() && !queue.jobs.is_empty() {
+            queue.threads += 1;
+            drop(queue);
+            work(Arc::clone(&self.0));
+        }
+    }
+}
+
+/// Starts a thread running queued runs until there are none left, which has
+/// to have been counted in the threads of queue already
+fn work(queue: Arc<Mutex<Queue>>) {
+    thread::spawn(move || {
+        let turn = Turn(queue);
+        loop {
+            let job = turn
+                .0
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .jobs
+                .pop_front();
+            match job {
+                Some(job) => {
+                    trace!("async run got its turn");
+                    job();
+                }
+                None => break,
+            }
+        }
+    });
+}
+
+/// Runs operations of a config as futures, at most some at once
+#[derive(Clone)]
+pub struct AsyncEngine {
+    config: Arc<Config>,
+    queue: Arc<Mutex<Queue>>,
+}
+
+impl AsyncEngine {
+    /// An engine over config, running at most max_runs at once, and at least
+    /// one
+    pub fn new(config: Config, max_runs: usize) -> Self {
+        AsyncEngine {
+            config: Arc::new(config),
+            queue: Arc::new(Mutex::new(Queue {
+                jobs: VecDeque::new(),
+                threads: 0,
+                max_threads: max_runs.max(1),
+            })),
+        }
+    }
+    /// The config the engine runs on
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+    /// Runs f on the config, once it is its turn
+    pub fn run<T, F>(&self, f: F) -> Run<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Config) -> T + Send + 'static,
+    {
+        let slot = Arc::new(Mutex::new(Slot {
+            result: None,
+            waker: None,
+        }));
+        let filled = Arc::clone(&slot);
+        let config = Arc::clone(&self.config);
+        let job: Job = Box::new(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| f(&config)));
+            let mut slot = filled.lock().unwrap_or_else(PoisonError::into_inner);
+            slot.result = Some(result);
+            if let Some(waker) = slot.waker.take() {
+                waker.wake();
+            }
+        });
+        let mut queue = self.queue.lock().unwrap_or_else(PoisonError::into_inner);
+        queue.jobs.push_back(job);
+        if queue.threads < queue.max_threads {
+            queue.threads += 1;
+            drop(queue);
+            work(Arc::clone(&self.queue));
+        } else {
+            debug!("async run queued, {} waiting", queue.jobs.len());
+        }
+        Run { slot }
+    }
+    /// See `Config::clone_all`
+    pub fn clone_all(&self) -> Run<RunReport> {
+        self.run(Config::clone_all)
+    }
+    /// See `Config::pull_all`
+    pub fn pull_all(&self) -> Run<RunReport> {
+        self.run(Config::pull_all)
+    }
+    /// See `Config::push_all`
+    pub fn push_all(&self, all_branches: bool, tags: bool) -> Run<RunReport> {
+        self.run(move |config| config.push_all(all_branches, tags))
+    }
+    /// See `Config::link_all`
+    pub fn link_all(&self) -> Run<RunReport> {
+        self.run(Config::link_all)
+    }
+    /// See `Config::quick`
+    pub fn quick(&self, msg: Option<String>) -> Run<RunReport> {
+        self.run(move |config| config.quick(msg.as_deref()))
+    }
+    /// See `Config::fast`
+    pub fn fast(&self, msg: Option<String>) -> Run<RunReport> {
+        self.run(move |config| config.fast(msg.as_deref()))
+    }
+}
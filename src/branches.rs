@@ -0,0 +1,77 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! The default branches of remotes, as detected when repos were cloned
+//!
+//! Repos without a `branch` are cloned on the default branch of their remote,
+//! which is kept in the state dir by checkout, so later pulls and prunes
+//! use that branch rather than whatever git assumes locally.
+
+use log::{debug, error, info, trace, warn};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::git::Config;
+use crate::utils::dir::state_dir;
+
+/// The default branch of every cloned repo without a branch, by checkout
+pub struct DefaultBranches {
+    path: PathBuf,
+    branches: BTreeMap<String, String>,
+}
+
+impl DefaultBranches {
+    /// Loads the default branches from the state dir
+    pub fn load() -> io::Result<Self> {
+        Self::load_from(state_dir().join("branches.yaml"))
+    }
+    /// Loads the default branches from path, which doesn't have to exist yet
+    pub fn load_from(path: PathBuf) -> io::Result<Self> {
+        let branches = match fs::read_to_string(&path) {
+            Ok(yaml) => serde_yaml::from_str(&yaml)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err),
+        };
+        Ok(DefaultBranches { path, branches })
+    }
+    /// Returns the default branch recorded for the repo checked out in
+    /// checkout
+    pub fn get(&self, checkout: &str) -> Option<&String> {
+        self.branches.get(checkout)
+    }
+    /// Records the default branch of every cloned repo in config without a
+    /// branch, that isn't recorded yet, and saves them
+    pub fn record(&mut self, config: &Config) -> io::Result<()> {
+        let before = self.branches.clone();
+        for repo in config
+            .categories
+            .values()
+            .flat_map(|category| category.repos.iter().flatten())
+            .map(|(_, repo)| repo)
+            .filter(|repo| repo.branch.is_none())
+        {
+            let checkout = repo.checkout_dir();
+            if self.branches.contains_key(&checkout) {
+                continue;
+            }
+            if let Some(branch) = repo.remote_head() {
+                debug!("{} defaults to {branch}", repo.display_name());
+                self.branches.insert(checkout, branch);
+            }
+        }
+        if self.branches == before {
+            return Ok(());
+        }
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let yaml = serde_yaml::to_string(&self.branches)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        fs::write(&self.path, yaml)
+    }
+}
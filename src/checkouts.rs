@@ -0,0 +1,126 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Matching the checkouts on disk to the repos in the config by their remote
+//!
+//! A repo is checked out to a dir named after it, so renaming it in the
+//! config, or moving it to another category, leaves its checkout behind,
+//! looking unmanaged, while the repo looks like it was never cloned. Its
+//! remote doesn't change with its name, so checkouts are identified by the
+//! identity of their origin, see `GitUrl::identity`, and matched to the repos
+//! whose remotes have the same identity.
+//!
+//! Only the dirs repos are checked out to are searched, one level deep.
+
+use log::{debug, error, info, trace, warn};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::git::{Config, RepoStatus};
+use crate::utils::exec::Exec;
+use crate::utils::url::identity;
+
+/// A git checkout on disk, and the url of its origin
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Checkout {
+    pub path: PathBuf,
+    pub url: String,
+}
+
+/// A checkout that isn't where the config has any repo
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Found {
+    /// A checkout of a repo that isn't cloned where the config has it
+    Moved {
+        category: String,
+        name: String,
+        path: PathBuf,
+    },
+    /// A checkout of a remote no repo in the config has
+    Unmanaged { path: PathBuf, url: String },
+}
+
+/// Returns the url of origin of the checkout at path
+fn origin_url(path: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .current_dir(path)
+        .args(["remote", "get-url", "origin"])
+        .exec_output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Lists the checkouts directly in dirs that have an origin, sorted by path
+pub fn scan(dirs: &BTreeSet<PathBuf>) -> Vec<Checkout> {
+    let mut checkouts: Vec<Checkout> = dirs
+        .iter()
+        .filter_map(|dir| dir.read_dir().ok())
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.join(".git").exists())
+        .filter_map(|path| {
+            let url = origin_url(&path)?;
+            Some(Checkout { path, url })
+        })
+        .collect();
+    checkouts.sort_by(|a, b| a.path.cmp(&b.path));
+    checkouts
+}
+
+/// Matches the checkouts in the dirs of the repos of config to its repos
+///
+/// Checkouts where the config has a repo are left out, as are checkouts of a
+/// repo already cloned where the config has it, as those are just copies.
+pub fn find(config: &Config) -> Vec<Found> {
+    let mut dirs = BTreeSet::new();
+    let mut managed = BTreeSet::new();
+    // The repos by the identities of their remotes, with whether they're
+    // cloned where the config has them
+    let mut repos: BTreeMap<String, (String, String, bool)> = BTreeMap::new();
+    for (category, cat) in config.categories.iter() {
+        for (name, repo) in cat.repos.iter().flatten() {
+            let Some(path) = &repo.path else {
+                continue;
+            };
+            dirs.insert(PathBuf::from(path));
+            managed.insert(PathBuf::from(repo.checkout_dir()));
+            let cloned = repo.status() != RepoStatus::Missing;
+            let urls = [
+                repo.url.clone(),
+                repo.pull_remote.clone(),
+                config.remote_url(repo),
+            ];
+            for id in urls.iter().flatten().filter_map(|url| identity(url)) {
+                repos.insert(id, (category.clone(), name.clone(), cloned));
+            }
+        }
+    }
+    let mut found = vec![];
+    for checkout in scan(&dirs) {
+        if managed.contains(&checkout.path) {
+            continue;
+        }
+        match identity(&checkout.url).and_then(|id| repos.get(&id)) {
+            Some((_, _, true)) => {
+                debug!("{} is a copy of a cloned repo", checkout.path.display())
+            }
+            Some((category, name, false)) => found.push(Found::Moved {
+                category: category.clone(),
+                name: name.clone(),
+                path: checkout.path,
+            }),
+            None => found.push(Found::Unmanaged {
+                path: checkout.path,
+                url: checkout.url,
+            }),
+        }
+    }
+    found
+}
@@ -5,10 +5,19 @@
 
 //! Handles command line input
 
+use crate::daemon::{Control, Series};
+use crate::graph::GraphFormat;
+use crate::progress::ProgressStyle;
+use crate::scaffold::Template;
+use crate::summarize::SummaryFormat;
 use crate::utils::dir::home_dir;
 use crate::utils::strings::INTERACTIVE_NOTICE;
+use crate::utils::term::ColorChoice;
 
-use clap::{ArgAction, CommandFactory, Parser, Subcommand};
+use clap::{ArgAction, CommandFactory, Parser, Subcommand, ValueEnum};
+
+use std::iter;
+use std::time::Duration;
 
 const CONFIG_FILE: &str = "/.config/seidr/config.yaml";
 
@@ -32,7 +41,6 @@ const HELP_TEMPLATE: &str = "\
     about="GitOps for the masses",
     long_about="A Rust GitOps and linkfarm orchestrator inspired by GNU Stow",
     subcommand_required=false,
-    arg_required_else_help=true,
     help_template=HELP_TEMPLATE.to_owned()+INTERACTIVE_NOTICE,
 )]
 pub struct Args {
@@ -41,6 +49,21 @@ pub struct Args {
     #[arg(short, long, default_value_t = home_dir() + CONFIG_FILE)]
     pub config: String,
 
+    /// Fail on fields in the config that seidr doesn't know, like typos, which
+    /// are otherwise ignored
+    #[arg(long)]
+    pub strict_config: bool,
+
+    /// Run on copies of the repos in a temp dir, with links and state moved
+    /// there too, to try out a run without touching anything
+    #[arg(long)]
+    pub sandbox: bool,
+
+    /// Keep the parsed config in the state dir, which loads large configs
+    /// faster, and is thrown away whenever the config changes
+    #[arg(long)]
+    pub config_cache: bool,
+
     /// Print license information
     #[arg(long)]
     pub license: bool,
@@ -53,10 +76,24 @@ pub struct Args {
     #[arg(long)]
     pub code_of_conduct: bool,
 
+    /// Be more verbose, -v shows info logs, -vv also prints every git
+    /// command before running it, and its exit code after
+    #[arg(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+
     /// Try to be as quiet as possible (unix philosophy) (not imlemented)
     #[arg(short, long)]
     pub quiet: bool,
 
+    /// When to write colors, auto follows NO_COLOR, CLICOLOR and
+    /// CLICOLOR_FORCE, and otherwise only colors terminals
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t)]
+    pub color: ColorChoice,
+
+    /// How to show progress, a spinner per repo, or a single status line
+    #[arg(long, value_enum, value_name = "STYLE", default_value_t)]
+    pub progress: ProgressStyle,
+
     /// No emoji (not imlemented)
     #[arg(short, long)]
     pub no_emoji: bool,
@@ -65,17 +102,96 @@ pub struct Args {
     #[arg(short, long)]
     pub unlink: bool,
 
-    /// (not imlemented)
+    /// Move files in the way of links to the trash, and link anyway
     #[arg(short, long)]
     pub force: bool,
 
+    /// Operate on system links, those with system set, instead of user links
+    #[arg(long)]
+    pub system: bool,
+
+    /// Rerun seidr with sudo when operating on system links as a regular user
+    #[arg(long, requires = "system")]
+    pub sudo: bool,
+
+    /// Commit message, overriding default_commit_message of repos and
+    /// categories
     #[arg(short, long)]
     pub message: Option<String>,
 
+    /// Only operate on the categories and repos listed in this file, one
+    /// "category" or "category repo" per line, or in stdin if it is "-"
+    #[arg(long, value_name = "FILE")]
+    pub repos_from: Option<String>,
+
+    /// Only operate on the categories and repos listed in stdin, the same as
+    /// --repos-from -, so questions can't be answered without --force
+    #[arg(long, conflicts_with = "repos_from")]
+    pub stdin: bool,
+
+    /// Only operate on repos checked out under this path, or matching it if it
+    /// is a glob, like '~/src/work/**'
+    #[arg(long, value_name = "PATH")]
+    pub path_filter: Option<String>,
+
+    /// Interactively pick the categories and repos to operate on
+    #[arg(long)]
+    pub pick: bool,
+
+    /// Run at most N operations at once, overriding the parallelism config;
+    /// 1 runs everything in order on a single thread
+    #[arg(short, long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    pub jobs: Option<u16>,
+
+    /// Stop starting operations on repos after this long, like 5m or 1h30m,
+    /// letting those running finish, and report what was skipped
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub max_duration: Option<Duration>,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
 
+/// Parses a duration like 90s, 5m or 1h30m, where a number without a unit is
+/// seconds
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let mut seconds = 0;
+    let mut number = String::new();
+    for c in duration.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(format!("unknown unit {c:?}, use s, m, h or d")),
+        };
+        let value: u64 = number
+            .parse()
+            .map_err(|_| format!("{c:?} needs a number before it"))?;
+        seconds += value * unit;
+        number.clear();
+    }
+    if duration.trim().is_empty() {
+        return Err(String::from("expected a duration like 5m"));
+    }
+    if !number.is_empty() {
+        seconds += number.parse::<u64>().map_err(|err| err.to_string())?;
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+impl Args {
+    /// Parses the `default_command` of a config, split on whitespace, as if
+    /// it was given on the command line
+    pub fn from_default_command(command: &str) -> Result<Self, clap::Error> {
+        Args::try_parse_from(iter::once("seidr").chain(command.split_whitespace()))
+    }
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     /// Link all... links
@@ -85,13 +201,22 @@ pub enum Commands {
     /// Do quick pull-commit-push with msg for commit
     #[command(visible_alias = "q")]
     Quick {
+        /// Only run on repos in this category
         category: Option<String>,
+        /// Only run on this repo in the category
         repo: Option<String>,
+        /// Show what would be committed, and ask before running
+        #[arg(long)]
+        preview: bool,
     },
 
     /// Do fast pull-commit-push with msg for commit, skipping repo on failure
     #[command(visible_alias = "f")]
-    Fast {},
+    Fast {
+        /// Show what would be committed, and ask before running
+        #[arg(long)]
+        preview: bool,
+    },
 
     /// Clone all repositories
     #[command(visible_alias = "c")]
@@ -101,13 +226,78 @@ pub enum Commands {
     #[command(visible_alias = "p")]
     Pull {},
 
+    /// Push all repositories
+    #[command(visible_alias = "ps")]
+    Push {
+        /// Push all branches, not just the current one
+        #[arg(long)]
+        all_branches: bool,
+        /// Also push all tags
+        #[arg(long)]
+        tags: bool,
+    },
+
+    /// Check out the branch of repositories with a detached HEAD, unless HEAD
+    /// has commits no branch has
+    FixHead {},
+
+    /// Fetch the history shallow clones of repositories are missing
+    Deepen {
+        /// Only fetch history back to this date, instead of all of it
+        #[arg(long)]
+        since: Option<String>,
+    },
+
+    /// Remove untracked files in repositories with the clean flag
+    Clean {},
+
+    /// Set core.hooksPath in repositories of categories with a hooks_dir
+    Hooks {},
+
+    /// Apply a patch, or patch series, to all repositories
+    Apply {
+        patch: String,
+        /// Only apply to repositories in this category
+        #[arg(long)]
+        category: Option<String>,
+    },
+
+    /// Manage branches across repositories
+    #[command(subcommand)]
+    Branch(BranchCommands),
+
+    /// Merge what quick and fast pushed to review branches
+    #[command(subcommand)]
+    Review(ReviewCommands),
+
+    /// Show how copied links differ from what they are copies of
+    Diff {},
+
+    /// Inspect links
+    #[command(subcommand)]
+    Links(LinksCommands),
+
+    /// Run a workflow of seidr operations and commands from a yaml file
+    Script {
+        file: String,
+        /// Only print the steps that would run
+        #[arg(long)]
+        dry_run: bool,
+    },
+
     /// Add all files in repositories
     #[command(visible_alias = "a")]
     Add {},
 
     /// Perform a git commit in all repositories
     #[command(visible_alias = "ct")]
-    Commit {},
+    Commit {
+        /// Make a commit per top-level directory with staged changes, instead
+        /// of one, with the commit message as a template where {dir} is the
+        /// directory
+        #[arg(long)]
+        split: bool,
+    },
 
     /// Perform a git commit in all repositories, with predefined message
     #[command(visible_alias = "m")]
@@ -116,6 +306,191 @@ pub enum Commands {
     /// Jump to a given object
     #[command(subcommand, visible_alias = "j")]
     Jump(JumpCommands),
+
+    /// Find which repos and links manage a path
+    #[command(visible_alias = "w")]
+    Which { path: String },
+
+    /// Interactive dashboard of categories and repos
+    Tui {},
+
+    /// Manage files seidr has moved to the trash
+    #[command(subcommand)]
+    Trash(TrashCommands),
+
+    /// Diagnose problems with the environment and config
+    Doctor {
+        /// Repair what can be repaired first, creating missing dirs, cloning
+        /// missing repos, linking missing and broken links, and setting
+        /// origin to the url in the config after asking
+        #[arg(long)]
+        fix: bool,
+    },
+
+    /// Verify that what seidr manages is safe to lose the disk of, running
+    /// every check if none is given
+    Verify {
+        /// Check that the files links point at are committed, as they are, in
+        /// the repos they're in
+        #[arg(long)]
+        links: bool,
+    },
+
+    /// List unpushed commits, and uncommitted changes to links, older than
+    /// some days
+    Stale {
+        /// How many days old work has to be to be listed
+        #[arg(long, default_value_t = 7)]
+        days: u64,
+    },
+
+    /// Inspect the config
+    #[command(subcommand)]
+    Config(ConfigCommands),
+
+    /// Run a git series, and optionally reconcile links, periodically
+    ///
+    /// With a command, controls the daemon that is running instead.
+    #[command(args_conflicts_with_subcommands = true)]
+    Daemon {
+        #[command(subcommand)]
+        control: Option<Control>,
+        /// Seconds between runs
+        #[arg(long, default_value_t = 300)]
+        interval: u64,
+        /// The git series to run
+        #[arg(long, value_enum, default_value_t)]
+        series: Series,
+        /// Also recreate removed links, and report drift
+        #[arg(long)]
+        links: bool,
+        /// Seconds a run can take before the daemon counts as wedged, see
+        /// `seidr daemon health`
+        #[arg(long, default_value_t = 3600)]
+        stall_after: u64,
+    },
+
+    /// Add a category for a common setup to the config, with commented
+    /// examples to fill in, creating the config if there is none
+    Init {
+        #[arg(long, value_enum)]
+        template: Template,
+    },
+
+    /// Remove all links, and optionally repos, and the state of seidr
+    Uninit {
+        /// Also remove cloned repositories
+        #[arg(long)]
+        repos: bool,
+        /// Archive repositories as tarballs in this directory before removing
+        /// them
+        #[arg(long, value_name = "DIR", requires = "repos")]
+        archive: Option<String>,
+    },
+
+    /// Show past runs, and what failed in them
+    History {
+        /// Only show the last N runs
+        #[arg(long, value_name = "N")]
+        last: Option<usize>,
+        /// Only show runs on this repo, as name or category/name, and all its
+        /// outcomes
+        #[arg(long)]
+        repo: Option<String>,
+    },
+
+    /// Render the links for other tools
+    #[command(subcommand)]
+    Export(ExportCommands),
+
+    /// Render the categories, repos and links as a graph, with links pointing
+    /// at the repos their tx is in
+    Graph {
+        #[arg(long, value_enum, default_value_t)]
+        format: GraphFormat,
+    },
+
+    /// Summarize the commits made in every repo over a stretch of time
+    Summarize {
+        /// Where the stretch starts, a date git understands, like 2024-01-01
+        /// or "1 month ago", or the id of a run in the history
+        #[arg(long)]
+        since: String,
+        /// Where the stretch ends, now if not given
+        #[arg(long)]
+        until: Option<String>,
+        #[arg(long, value_enum, default_value_t)]
+        format: SummaryFormat,
+    },
+
+    /// Show the status of every repo, refreshed every SECONDS with --watch,
+    /// 2 if not given
+    Status {
+        #[arg(long, value_name = "SECONDS", num_args = 0..=1, default_missing_value = "2")]
+        watch: Option<u64>,
+        /// Also count remote-tracking refs of branches gone from origin,
+        /// asking every remote
+        #[arg(long)]
+        stale: bool,
+    },
+
+    /// Suggest shallow clones, blob filters or LFS for repos taking a lot of
+    /// disk, with what each would save
+    Slim {
+        /// How many MiB the objects of a repo have to take to be looked at
+        #[arg(long, default_value_t = 100)]
+        min_size: u64,
+        /// How far back shallow clones are suggested to go, a date git
+        /// understands
+        #[arg(long, default_value = "1.year.ago")]
+        since: String,
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Any other command runs the plugin seidr-<command> on PATH, see the
+    /// plugin module
+    #[command(external_subcommand)]
+    External(Vec<String>),
+}
+
+impl Commands {
+    /// Whether the command only reads, and so never changes the state
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Commands::Jump(_)
+                | Commands::Which { .. }
+                | Commands::Doctor { fix: false }
+                | Commands::Stale { .. }
+                | Commands::Verify { .. }
+                | Commands::Links(LinksCommands::List { .. })
+                | Commands::Config(_)
+                | Commands::Export(_)
+                | Commands::Graph { .. }
+                | Commands::Summarize { .. }
+                | Commands::Slim { .. }
+                | Commands::Status { .. }
+                | Commands::History { .. }
+                | Commands::Diff {}
+                | Commands::Daemon {
+                    control: Some(_),
+                    ..
+                }
+        )
+    }
+    /// The category the command is limited to, if it takes one
+    pub fn category(&self) -> Option<&str> {
+        match self {
+            Commands::Quick { category, .. }
+            | Commands::Apply { category, .. }
+            | Commands::Branch(BranchCommands::Rename { category, .. })
+            | Commands::Branch(BranchCommands::Prune { category, .. })
+            | Commands::Review(ReviewCommands::Merge { category }) => category.as_deref(),
+            Commands::Links(LinksCommands::Mv { category, .. }) => Some(category),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Subcommand, Debug)]
@@ -126,5 +501,143 @@ pub enum JumpCommands {
 
     /// Jump to link
     #[command(visible_alias = "l")]
-    Link { category: String, name: String },
+    Link {
+        category: String,
+        name: String,
+        /// Which end of the link to print
+        #[arg(long, value_enum, default_value_t)]
+        end: LinkEnd,
+    },
+
+    /// List the repos and links that can be jumped to, for shell completion
+    List {},
+}
+
+/// An end of a link
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, ValueEnum)]
+pub enum LinkEnd {
+    /// Where the link is created
+    Rx,
+    /// What the link points to
+    #[default]
+    Tx,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum BranchCommands {
+    /// Rename a local branch in every repository that has it
+    Rename {
+        old: String,
+        new: String,
+        /// Only rename in repositories in this category
+        #[arg(long)]
+        category: Option<String>,
+    },
+
+    /// Remove remote-tracking refs of branches gone from the remote
+    Prune {
+        /// Also delete local branches merged into the default branch
+        #[arg(long)]
+        merged: bool,
+        /// Only prune in repositories in this category
+        #[arg(long)]
+        category: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ReviewCommands {
+    /// Fast-forward repositories to the seidr/<hostname> branches of every
+    /// host on origin, and push them
+    Merge {
+        /// Only merge in repositories in this category
+        #[arg(long)]
+        category: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum LinksCommands {
+    /// List links, with what is at their rx
+    ///
+    /// The filters combine, so --broken --missing lists links that are either.
+    #[command(visible_alias = "ls")]
+    List {
+        /// Only list symlinks to something that doesn't exist
+        #[arg(long)]
+        broken: bool,
+        /// Only list symlinks to something outside the tx of every link
+        #[arg(long)]
+        foreign: bool,
+        /// Only list links with nothing at their rx
+        #[arg(long)]
+        missing: bool,
+        /// Print the links as json
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Move the rx of a link, like when a program moved where it reads its
+    /// config from, relinking it there and updating the config
+    Mv {
+        category: String,
+        name: String,
+        /// Where the link is created from now on, as written in the config
+        rx: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TrashCommands {
+    /// List trashed files by run
+    #[command(visible_alias = "ls")]
+    List {},
+
+    /// Restore files trashed by a run
+    Restore {
+        run: String,
+        /// Only restore the file originally at this path
+        path: Option<String>,
+    },
+
+    /// Permanently delete everything in the trash
+    Empty {},
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommands {
+    /// Check that every repo is valid for its kind
+    Validate {},
+
+    /// Rewrite the config in canonical order, so merges make minimal diffs
+    ///
+    /// Comments aren't kept, so a config with comments is only rewritten with
+    /// --force.
+    Fmt {
+        /// Only check if the config is formatted, failing if it isn't
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Print a JSON Schema of the config, for editors to validate and complete
+    /// configs with
+    Schema {},
+
+    /// List the backups seidr made of the config before writing it
+    Backups {},
+
+    /// Restore a backup of the config, the newest unless one is given
+    ///
+    /// The config being replaced is backed up too, so this can be undone.
+    Rollback { backup: Option<String> },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ExportCommands {
+    /// Create a GNU Stow directory, with a package per category
+    Stow { dir: String },
+
+    /// Print a home-manager module with a home.file per link
+    #[command(visible_alias = "hm")]
+    HomeManager {},
 }
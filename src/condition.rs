@@ -0,0 +1,109 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Conditions on the machine, deciding whether a link is linked on it
+//!
+//! A condition holds when every predicate given in it holds, and `all`, `any`
+//! and `not` combine conditions further:
+//!
+//! ```yaml
+//! condition:
+//!   program_exists: nvim
+//!   file_absent: ~/.vimrc
+//!   any:
+//!     - hostname: "work-*"
+//!     - env:
+//!         SEIDR_PROFILE: work
+//! ```
+//!
+//! Hostnames are matched with globs, like link groups are named, rather than
+//! regexes.
+
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::path::Path;
+
+use crate::utils::dir::{expand_tilde, hostname, is_executable};
+use crate::utils::glob;
+
+/// Predicates on the machine, which all have to hold
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct Condition {
+    /// A program of this name is on PATH
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub program_exists: Option<String>,
+    /// Something exists at this path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_exists: Option<String>,
+    /// Nothing exists at this path, not even a broken symlink
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_absent: Option<String>,
+    /// The hostname matches this glob
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// Environment variables have these values
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<BTreeMap<String, String>>,
+    /// Every one of these holds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub all: Option<Vec<Condition>>,
+    /// At least one of these holds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub any: Option<Vec<Condition>>,
+    /// This doesn't hold
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub not: Option<Box<Condition>>,
+}
+
+impl Condition {
+    /// Whether the condition holds on this machine
+    pub fn holds(&self) -> bool {
+        self.holds_with(&|var| env::var(var).ok(), &hostname())
+    }
+    /// Whether the condition holds with the environment variables var gives,
+    /// on a machine named host
+    pub fn holds_with<F>(&self, var: &F, host: &str) -> bool
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let exists = |path: &str| Path::new(&expand_tilde(path)).symlink_metadata().is_ok();
+        let holds = self.program_exists.as_deref().map_or(true, |program| {
+            var("PATH").map_or(false, |path| {
+                env::split_paths(&path).any(|dir| is_executable(&dir.join(program)))
+            })
+        }) && self.file_exists.as_deref().map_or(true, exists)
+            && self
+                .file_absent
+                .as_deref()
+                .map_or(true, |path| !exists(path))
+            && self
+                .hostname
+                .as_deref()
+                .map_or(true, |pattern| glob::matches(pattern, Path::new(host)))
+            && self
+                .env
+                .iter()
+                .flatten()
+                .all(|(name, value)| var(name).map_or(false, |actual| actual == *value))
+            && self
+                .all
+                .iter()
+                .flatten()
+                .all(|condition| condition.holds_with(var, host))
+            && self.any.as_ref().map_or(true, |conditions| {
+                conditions
+                    .iter()
+                    .any(|condition| condition.holds_with(var, host))
+            })
+            && self
+                .not
+                .as_ref()
+                .map_or(true, |condition| !condition.holds_with(var, host));
+        trace!("{self:?} holds: {holds}");
+        holds
+    }
+}
@@ -0,0 +1,148 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! A cache of the parsed config in the state dir, for large configs
+//!
+//! Parsing yaml is most of the time it takes to load a config of hundreds of
+//! repos, so the parsed config is kept as json, which is much faster to read,
+//! next to a hash of the yaml it came from. The cache is only used while that
+//! hash matches, so any change to the config, or to seidr, invalidates it.
+//! Each config file has a cache of its own, so switching between configs with
+//! `--config` doesn't throw the cache of the other away.
+//!
+//! A run narrowed down to some categories, like with `--repos-from`, only
+//! resolves the paths of those, as it never looks at the rest.
+
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::git::Config;
+use crate::schedule::FileOrder;
+use crate::utils::dir::{absolute_path, state_dir};
+
+/// The cached config, with the key of the yaml it was parsed from
+#[derive(Serialize, Deserialize)]
+struct Cached<C> {
+    key: String,
+    config: C,
+    /// The order of the yaml, which the config itself doesn't serialize
+    order: FileOrder,
+}
+
+/// Returns the hash of text, as hex
+fn hash(text: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Returns the key of yaml, which changes with the yaml and with the version
+/// of seidr, as that may parse the same yaml differently
+fn key(yaml: &str) -> String {
+    format!("{}-{}", env!("CARGO_PKG_VERSION"), hash(yaml))
+}
+
+/// The cache of a config in the state dir
+pub struct ConfigCache {
+    path: PathBuf,
+}
+
+impl ConfigCache {
+    /// The cache kept at path
+    pub fn at(path: PathBuf) -> Self {
+        ConfigCache { path }
+    }
+    /// Where the cache is kept
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// The cache of the config at config, in the state dir, keyed by the
+    /// absolute path of config
+    pub fn of(config: &str) -> Self {
+        let absolute = absolute_path(Path::new(config));
+        ConfigCache::at(
+            state_dir()
+                .join("config-cache")
+                .join(format!("{}.json", hash(&absolute.to_string_lossy()))),
+        )
+    }
+    /// Loads the config at path like `Config::new`, from the cache if it is
+    /// of the same yaml, and updating the cache if it isn't
+    ///
+    /// With scope, only the top level categories named in it are kept, and
+    /// have their paths resolved, unless one of them isn't in the config, so
+    /// the error for it can suggest another. A cache that can't be read or
+    /// written is only warned about, as the config can always be parsed.
+    pub fn load(&self, path: &String, scope: Option<&[String]>) -> Config {
+        let yaml = fs::read_to_string(path).unwrap_or_else(|_| {
+            panic!("Should have been able to read the file: path -> {:?}", path,)
+        });
+        let key = key(&yaml);
+        let mut config = match self.get(&key) {
+            Some(config) => {
+                debug!("loaded config from {}", self.path.display());
+                config
+            }
+            None => {
+                let config = Config::from_yaml(&yaml, path);
+                if let Err(err) = self.put(key, &config) {
+                    warn!("failed to cache config in {}: {err}", self.path.display());
+                }
+                config
+            }
+        };
+        if let Some(scope) = scope {
+            if scope
+                .iter()
+                .all(|name| config.categories.contains_key(name))
+            {
+                config.categories.retain(|name, _| scope.contains(name));
+                debug!("resolving the paths of {scope:?} only");
+            }
+        }
+        config.interpolate_paths().unwrap_or_else(|err| {
+            panic!("Should have been able to interpolate config paths: path -> {path:?}: {err}")
+        });
+        config.flatten_categories();
+        config
+    }
+    /// The cached config, if it was parsed from yaml with key
+    fn get(&self, key: &str) -> Option<Config> {
+        let json = fs::read_to_string(&self.path).ok()?;
+        match serde_json::from_str::<Cached<Config>>(&json) {
+            Ok(cached) if cached.key == key => {
+                let mut config = cached.config;
+                config.order = cached.order;
+                Some(config)
+            }
+            Ok(_) => {
+                debug!("{} is of another config", self.path.display());
+                None
+            }
+            Err(err) => {
+                warn!("ignoring {}: {err}", self.path.display());
+                None
+            }
+        }
+    }
+    /// Caches config, parsed from yaml with key
+    fn put(&self, key: String, config: &Config) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string(&Cached {
+            key,
+            config,
+            order: config.order.clone(),
+        })
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        fs::write(&self.path, json)
+    }
+}
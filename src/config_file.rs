@@ -0,0 +1,199 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Writing the config without ever losing it
+//!
+//! The config is written to a temporary file next to it, which is then renamed
+//! over it, so a crash never leaves half a config. Writers take a lock before
+//! they read the config they change, and hold it until it's written, so two
+//! runs of seidr can't lose each other's changes, and the config as it was
+//! before each write is kept as a backup in the state dir, for `seidr config
+//! rollback`.
+
+use log::{debug, error, info, trace, warn};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::utils::dir::state_dir;
+
+/// How many backups are kept, when the config doesn't say
+pub const DEFAULT_BACKUPS: usize = 10;
+
+/// How long to wait for another run of seidr to release the lock
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The config file, with its lock and backups
+pub struct ConfigFile {
+    path: PathBuf,
+    /// Where the lock and the backups are kept
+    dir: PathBuf,
+}
+
+/// A lock on the config, released when dropped
+pub struct ConfigLock {
+    path: PathBuf,
+}
+
+impl Drop for ConfigLock {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            error!("failed to release {}: {err}", self.path.display());
+        }
+    }
+}
+
+/// Writes contents to tmp, and renames it over target, keeping the
+/// permissions of target
+fn replace(tmp: &Path, target: &Path, contents: &str) -> io::Result<()> {
+    let mut file = File::create(tmp)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    if let Ok(metadata) = fs::metadata(target) {
+        fs::set_permissions(tmp, metadata.permissions())?;
+    }
+    fs::rename(tmp, target)
+}
+
+impl ConfigFile {
+    /// The config at path, with its backups in the state dir
+    pub fn new(path: &Path) -> Self {
+        ConfigFile::at(path, state_dir().join("config"))
+    }
+    /// The config at path, with its backups in dir
+    pub fn at(path: &Path, dir: PathBuf) -> Self {
+        ConfigFile {
+            path: path.to_path_buf(),
+            dir,
+        }
+    }
+    /// Takes the lock, waiting for another run holding it
+    ///
+    /// A lock left behind by a crashed run has to be removed by hand, as the
+    /// error says.
+    pub fn lock(&self) -> io::Result<ConfigLock> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join("lock");
+        let started = Instant::now();
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    write!(file, "{}", std::process::id())?;
+                    return Ok(ConfigLock { path });
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    if started.elapsed() > LOCK_TIMEOUT {
+                        let holder = fs::read_to_string(&path).unwrap_or_default();
+                        return Err(io::Error::new(
+                            io::ErrorKind::WouldBlock,
+                            format!(
+                                "the config is locked by process {holder}, remove {} if it isn't running",
+                                path.display()
+                            ),
+                        ));
+                    }
+                    thread::sleep(Duration::from_millis(100));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+    /// The file to write, which is where path links to if it is a symlink,
+    /// so a config kept in a repo stays there
+    fn target(&self) -> PathBuf {
+        fs::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone())
+    }
+    /// Writes contents to the config, with lock held since the config
+    /// contents were made from was read, keeping keep backups
+    pub fn write(&self, lock: &ConfigLock, contents: &str, keep: usize) -> io::Result<()> {
+        debug_assert_eq!(lock.path, self.dir.join("lock"), "lock of another config");
+        self.backup()?;
+        let target = self.target();
+        let name = target.file_name().map_or_else(
+            || String::from("config"),
+            |name| name.to_string_lossy().to_string(),
+        );
+        let tmp = target.with_file_name(format!(".{name}.{}.tmp", std::process::id()));
+        if let Err(err) = replace(&tmp, &target, contents) {
+            let _ = fs::remove_file(&tmp);
+            return Err(err);
+        }
+        debug!("wrote {}", target.display());
+        self.prune(keep)
+    }
+    /// Copies the config to a backup of its own, as it is before a write
+    ///
+    /// Backups are named by when they were made, then the process making
+    /// them, so they sort chronologically.
+    fn backup(&self) -> io::Result<()> {
+        if !self.path.exists() {
+            return Ok(());
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let backup = self.dir.join(format!(
+            "{:012}-{:09}-{}.yaml",
+            now.as_secs(),
+            now.subsec_nanos(),
+            std::process::id()
+        ));
+        fs::copy(&self.path, &backup)?;
+        debug!("backed up {} to {}", self.path.display(), backup.display());
+        Ok(())
+    }
+    /// Returns the ids of the backups, oldest first
+    pub fn backups(&self) -> io::Result<Vec<String>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+        let mut backups = vec![];
+        for entry in entries {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            if let Some(id) = name.strip_suffix(".yaml") {
+                backups.push(id.to_string());
+            }
+        }
+        backups.sort();
+        Ok(backups)
+    }
+    /// Removes all but the newest keep backups
+    fn prune(&self, keep: usize) -> io::Result<()> {
+        let backups = self.backups()?;
+        for id in &backups[..backups.len().saturating_sub(keep)] {
+            fs::remove_file(self.dir.join(format!("{id}.yaml")))?;
+        }
+        Ok(())
+    }
+    /// Restores the backup with id, or the newest, returning its id
+    ///
+    /// The config being replaced is backed up too, so a rollback can be
+    /// rolled back.
+    pub fn rollback(&self, id: Option<&str>, keep: usize) -> io::Result<String> {
+        let lock = self.lock()?;
+        let backups = self.backups()?;
+        let id = match id {
+            Some(id) if backups.iter().any(|backup| backup == id) => id.to_string(),
+            Some(id) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no backup {id}"),
+                ))
+            }
+            None => backups
+                .last()
+                .cloned()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no backups"))?,
+        };
+        let contents = fs::read_to_string(self.dir.join(format!("{id}.yaml")))?;
+        self.write(&lock, &contents, keep)?;
+        info!("rolled back {} to {id}", self.path.display());
+        Ok(id)
+    }
+}
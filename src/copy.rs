@@ -0,0 +1,265 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Links that copy their tx instead of symlinking it
+//!
+//! Some programs refuse to read symlinked files, or rewrite them in place, so
+//! a link can instead be a copy. Template links are copies too, of tx rendered
+//! by the template module. Unlike a symlink, a copy can be changed without
+//! changing tx, so the hash of every copy seidr writes is kept in the state
+//! dir. That tells a copy that is merely out of date, which is safe to
+//! overwrite, apart from one that was modified locally, which is only
+//! overwritten with the FORCE flag.
+//!
+//! Hashes are git blob hashes, and are kept per host, as the state dir is
+//! shared between machines that each have their own copies.
+
+use log::{debug, error, info, trace, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::Ordering;
+
+use crate::git::{Config, Link, LinkError, LinkKind, Linked};
+use crate::settings;
+use crate::template;
+use crate::utils::dir::{hostname, state_dir};
+use crate::utils::exec::Exec;
+use crate::utils::strings::result_str;
+
+/// Returns the git blob hash of the file at path
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let output = Command::new("git")
+        .arg("hash-object")
+        .arg("--no-filters")
+        .arg(path)
+        .exec_output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "failed to hash {}: {}",
+                path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The hashes of the copies seidr wrote on this host, by rx
+pub struct Hashes {
+    path: PathBuf,
+    hashes: BTreeMap<String, String>,
+}
+
+impl Hashes {
+    /// Loads the hashes of this host from the state dir
+    pub fn load() -> io::Result<Self> {
+        Self::load_from(
+            state_dir()
+                .join("copies")
+                .join(format!("{}.yaml", hostname())),
+        )
+    }
+    /// Loads the hashes from path, which doesn't have to exist yet
+    pub fn load_from(path: PathBuf) -> io::Result<Self> {
+        let hashes = match fs::read_to_string(&path) {
+            Ok(yaml) => serde_yaml::from_str(&yaml)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err),
+        };
+        Ok(Hashes { path, hashes })
+    }
+    /// Every rx a copy was written to
+    pub fn paths(&self) -> impl Iterator<Item = &String> {
+        self.hashes.keys()
+    }
+    /// The hash of the copy last written to rx, if any
+    pub fn get(&self, rx: &str) -> Option<&str> {
+        self.hashes.get(rx).map(String::as_str)
+    }
+    /// Records that a copy with hash was written to rx, and saves the hashes
+    pub fn record(&mut self, rx: &str, hash: String) -> io::Result<()> {
+        self.hashes.insert(rx.to_string(), hash);
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let yaml = serde_yaml::to_string(&self.hashes)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        fs::write(&self.path, yaml)
+    }
+}
+
+/// How a copy relates to its tx
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CopyStatus {
+    /// There is nothing at rx
+    Missing,
+    /// rx is the same as tx
+    InSync,
+    /// rx is what seidr last copied, but tx has changed since
+    OutOfDate,
+    /// rx was changed after seidr copied it, or wasn't copied by seidr at all
+    Modified,
+}
+
+impl fmt::Display for CopyStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CopyStatus::Missing => write!(f, "missing"),
+            CopyStatus::InSync => write!(f, "in sync"),
+            CopyStatus::OutOfDate => write!(f, "out of date"),
+            CopyStatus::Modified => write!(f, "modified locally"),
+        }
+    }
+}
+
+/// Returns what the copy of link should contain
+///
+/// That is tx itself, or for template links, tx rendered with vars.
+pub fn source(link: &Link, vars: &HashMap<String, String>) -> Result<Vec<u8>, LinkError> {
+    if !Path::new(&link.tx).is_file() {
+        return Err(LinkError::FailedCreatingLink(
+            link.tx.clone(),
+            link.rx.clone(),
+        ));
+    }
+    match link.kind {
+        LinkKind::Template => template::render(&fs::read_to_string(&link.tx)?, vars)
+            .map(String::into_bytes)
+            .map_err(|err| LinkError::TemplateError(link.tx.clone(), err)),
+        _ => Ok(fs::read(&link.tx)?),
+    }
+}
+
+/// Finds the status of the copy of link, that should contain source
+pub fn status(link: &Link, source: &[u8], hashes: &Hashes) -> io::Result<CopyStatus> {
+    let rx = Path::new(&link.rx);
+    if !rx.exists() && !rx.is_symlink() {
+        return Ok(CopyStatus::Missing);
+    }
+    if !rx.is_file() || rx.is_symlink() {
+        return Ok(CopyStatus::Modified);
+    }
+    if fs::read(rx)? == source {
+        Ok(CopyStatus::InSync)
+    } else if hashes.get(&link.rx) == Some(hash_file(rx)?.as_str()) {
+        Ok(CopyStatus::OutOfDate)
+    } else {
+        Ok(CopyStatus::Modified)
+    }
+}
+
+/// Whether rx is unchanged since seidr last wrote it
+pub fn is_written(link: &Link) -> bool {
+    let rx = Path::new(&link.rx);
+    if !rx.is_file() || rx.is_symlink() {
+        return false;
+    }
+    match (Hashes::load(), hash_file(rx)) {
+        (Ok(hashes), Ok(hash)) => hashes.get(&link.rx) == Some(hash.as_str()),
+        _ => false,
+    }
+}
+
+/// Writes source to rx, with the permissions of tx, and records its hash
+fn write_copy(link: &Link, source: &[u8], hashes: &mut Hashes) -> Result<(), LinkError> {
+    fs::write(&link.rx, source)?;
+    fs::set_permissions(&link.rx, fs::metadata(&link.tx)?.permissions())?;
+    hashes.record(&link.rx, hash_file(Path::new(&link.rx))?)?;
+    Ok(())
+}
+
+/// Creates or updates the copy of link, rendering templates with vars
+///
+/// A copy that was modified locally is a conflict, handled by the conflict
+/// strategy of the link with force, see `Link::conflict_strategy`.
+pub fn link(link: &Link, vars: &HashMap<String, String>, force: bool) -> Result<Linked, LinkError> {
+    let source = source(link, vars)?;
+    let mut hashes = Hashes::load()?;
+    match status(link, &source, &hashes)? {
+        CopyStatus::InSync => {
+            // Record it anyway, so changes to rx from now on are noticed
+            if hashes.get(&link.rx).is_none() {
+                hashes.record(&link.rx, hash_file(Path::new(&link.rx))?)?;
+            }
+            Err(LinkError::AlreadyLinked(link.tx.clone(), link.rx.clone()))
+        }
+        CopyStatus::Missing | CopyStatus::OutOfDate => {
+            write_copy(link, &source, &mut hashes)?;
+            Ok(Linked::Created)
+        }
+        CopyStatus::Modified => link.resolve_conflict(
+            LinkError::ModifiedCopy(link.tx.clone(), link.rx.clone()),
+            force,
+            || write_copy(link, &source, &mut hashes),
+        ),
+    }
+}
+
+/// Prints every copy in config that isn't in sync, with a diff from what it
+/// should contain
+///
+/// Returns whether all copies are in sync.
+pub fn diff(config: &Config) -> Result<bool, LinkError> {
+    let hashes = Hashes::load()?;
+    let vars = config.template_vars();
+    let mut in_sync = true;
+    let mut categories: Vec<_> = config.categories.iter().collect();
+    categories.sort_by_key(|(name, _)| *name);
+    for (category, cat) in categories {
+        let mut links: Vec<_> = cat
+            .links
+            .iter()
+            .flatten()
+            .filter(|(_, link)| !link.kind.is_symlink() && config.is_link_enabled(link))
+            .collect();
+        links.sort_by_key(|(name, _)| *name);
+        for (name, link) in links {
+            let source = source(link, &vars)?;
+            let status = status(link, &source, &hashes)?;
+            if status == CopyStatus::InSync {
+                continue;
+            }
+            in_sync = false;
+            println!("{} {category} {name}: {status}", result_str(false));
+            if status == CopyStatus::Missing {
+                continue;
+            }
+            // Templates are diffed against their rendering, not tx itself
+            let rendered =
+                std::env::temp_dir().join(format!("seidr-{}-{name}", std::process::id()));
+            let expected = if link.kind == LinkKind::Template {
+                fs::write(&rendered, &source)?;
+                rendered.as_path()
+            } else {
+                Path::new(&link.tx)
+            };
+            let diffed = Command::new("git")
+                .arg("--no-pager")
+                .arg("diff")
+                .arg(if settings::COLOR.load(Ordering::Relaxed) {
+                    "--color=always"
+                } else {
+                    "--color=never"
+                })
+                .arg("--no-index")
+                .arg("--")
+                .arg(expected)
+                .arg(&link.rx)
+                .exec_status();
+            if link.kind == LinkKind::Template {
+                fs::remove_file(&rendered)?;
+            }
+            diffed?;
+        }
+    }
+    Ok(in_sync)
+}
@@ -0,0 +1,434 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Running seidr periodically in the background
+//!
+//! Every tick the daemon runs a git series on all repos, and optionally
+//! reconciles the link farm, recreating links that were removed and reporting
+//! drift. Reconciling only prints links that changed or drifted, so it stays
+//! quiet while the link farm is as configured.
+//!
+//! A running daemon is controlled over a unix socket in the state dir, which
+//! takes a single command per connection, like `pause`, and answers with a
+//! single line.
+//!
+//! For monitors, like a systemd timer, `health` answers with json of when the
+//! daemon last ran and how many operations failed, and whether it is wedged,
+//! in a run for longer than it should ever take. `seidr daemon health` fails
+//! if the daemon is wedged, or doesn't answer within `CONTROL_TIMEOUT`.
+
+use clap::{Subcommand, ValueEnum};
+use log::{debug, error, info, trace, warn};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::git::{Config, Link};
+use crate::history::format_time;
+use crate::report::RunReport;
+use crate::utils::dir::state_dir;
+use crate::utils::strings::result_str;
+
+/// The git series the daemon runs every tick
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, ValueEnum)]
+pub enum Series {
+    /// Don't touch the repos
+    None,
+    /// Pull all repos
+    #[default]
+    Pull,
+    /// Pull, add, commit and push, like the quick subcommand
+    Quick,
+    /// Pull, add, commit and push, like the fast subcommand
+    Fast,
+}
+
+/// A command to a running daemon
+#[derive(Subcommand, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Control {
+    /// Skip runs until resumed
+    Pause,
+    /// Run again every interval
+    Resume,
+    /// Run right away, even if paused
+    RunNow,
+    /// Show whether the daemon is paused, and when it ran
+    Status,
+    /// Show when the daemon last ran, and how many operations failed, as
+    /// json, failing if it's wedged
+    Health,
+}
+
+impl Control {
+    /// The command as it is sent over the socket
+    fn name(&self) -> &'static str {
+        match self {
+            Control::Pause => "pause",
+            Control::Resume => "resume",
+            Control::RunNow => "run-now",
+            Control::Status => "status",
+            Control::Health => "health",
+        }
+    }
+    fn parse(name: &str) -> Option<Self> {
+        [
+            Control::Pause,
+            Control::Resume,
+            Control::RunNow,
+            Control::Status,
+            Control::Health,
+        ]
+        .into_iter()
+        .find(|control| control.name() == name)
+    }
+}
+
+/// Returns the path of the control socket
+pub fn socket_path() -> PathBuf {
+    state_dir().join("daemon.sock")
+}
+
+/// How long either end of a control connection waits for the other
+pub const CONTROL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sends command to the daemon listening on socket, returning its answer
+///
+/// A daemon that doesn't answer within `CONTROL_TIMEOUT` is an error, so
+/// monitors find a wedged daemon instead of waiting on it too.
+pub fn send(socket: &Path, command: Control) -> io::Result<String> {
+    send_within(socket, command, CONTROL_TIMEOUT)
+}
+
+/// Like `send`, giving up on a daemon that doesn't answer within timeout
+pub fn send_within(socket: &Path, command: Control, timeout: Duration) -> io::Result<String> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let timed_out = |err: io::Error| match err.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("the daemon didn't answer within {}s", timeout.as_secs_f32()),
+        ),
+        _ => err,
+    };
+    writeln!(stream, "{}", command.name()).map_err(timed_out)?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+    let mut answer = String::new();
+    stream.read_to_string(&mut answer).map_err(timed_out)?;
+    Ok(answer.trim_end().to_string())
+}
+
+/// How long a run can take by default before the daemon counts as wedged
+pub const DEFAULT_STALL_AFTER: Duration = Duration::from_secs(3600);
+
+/// The answer to `health`
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Health {
+    /// Whether the daemon isn't wedged
+    pub healthy: bool,
+    pub paused: bool,
+    /// How long the run going on has taken so far, in seconds
+    pub running_for: Option<u64>,
+    pub runs: u64,
+    /// When the last run finished, in seconds since the epoch
+    pub last_run: Option<u64>,
+    /// How many operations failed in the last run
+    pub failures: usize,
+    /// How many runs in a row, up to the last, had failures
+    pub failed_runs: u64,
+}
+
+impl Health {
+    /// Whether answer is the health of a daemon that isn't wedged
+    pub fn answer_is_healthy(answer: &str) -> bool {
+        serde_json::from_str::<Health>(answer).map_or(false, |health| health.healthy)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+#[derive(Default)]
+struct State {
+    paused: bool,
+    run_now: bool,
+    running: bool,
+    runs: u64,
+    /// When the run going on started, in seconds since the epoch
+    run_started: Option<u64>,
+    /// When the last run finished, in seconds since the epoch
+    last_run: Option<u64>,
+    /// How many operations failed in the last run
+    failures: usize,
+    /// How many runs in a row, up to the last, had failures
+    failed_runs: u64,
+    /// When the next run is due, unless paused
+    next_run: Option<Instant>,
+}
+
+/// What the daemon is doing, shared with the control socket
+pub struct Controls {
+    state: Mutex<State>,
+    wake: Condvar,
+    /// How long a run can take before the daemon counts as wedged
+    stall_after: Duration,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Controls::new(DEFAULT_STALL_AFTER)
+    }
+}
+
+impl Controls {
+    pub fn new(stall_after: Duration) -> Self {
+        Controls {
+            state: Mutex::default(),
+            wake: Condvar::new(),
+            stall_after,
+        }
+    }
+    /// Applies command, returning the answer to it
+    pub fn handle(&self, command: Control) -> String {
+        let mut state = self.state.lock().expect("daemon state poisoned");
+        let answer = match command {
+            Control::Pause => {
+                state.paused = true;
+                String::from("paused")
+            }
+            Control::Resume => {
+                state.paused = false;
+                String::from("resumed")
+            }
+            Control::RunNow => {
+                state.run_now = true;
+                String::from("running now")
+            }
+            Control::Status => {
+                let mut status = vec![String::from(if state.paused { "paused" } else { "active" })];
+                if state.running {
+                    status.push(String::from("running"));
+                }
+                status.push(format!("{} runs", state.runs));
+                if let Some(last_run) = state.last_run {
+                    status.push(format!("last run finished {}", format_time(last_run)));
+                }
+                match state.next_run {
+                    Some(next_run) if !state.paused && !state.running => status.push(format!(
+                        "next run in {}s",
+                        next_run.saturating_duration_since(Instant::now()).as_secs()
+                    )),
+                    _ => (),
+                }
+                status.join(", ")
+            }
+            Control::Health => {
+                let running_for = state
+                    .run_started
+                    .filter(|_| state.running)
+                    .map(|started| now().saturating_sub(started));
+                let health = Health {
+                    healthy: running_for.map_or(true, |secs| secs < self.stall_after.as_secs()),
+                    paused: state.paused,
+                    running_for,
+                    runs: state.runs,
+                    last_run: state.last_run,
+                    failures: state.failures,
+                    failed_runs: state.failed_runs,
+                };
+                serde_json::to_string(&health).unwrap_or_else(|err| format!("{err}"))
+            }
+        };
+        self.wake.notify_all();
+        answer
+    }
+    /// Waits until the next run is due, which is after interval unless a run
+    /// is asked for, and never while paused
+    pub fn wait(&self, interval: Duration) {
+        let mut state = self.state.lock().expect("daemon state poisoned");
+        let next_run = Instant::now() + interval;
+        state.next_run = Some(next_run);
+        while !state.run_now && (state.paused || Instant::now() < next_run) {
+            state = if state.paused {
+                self.wake.wait(state).expect("daemon state poisoned")
+            } else {
+                let timeout = next_run.saturating_duration_since(Instant::now());
+                let (state, _) = self
+                    .wake
+                    .wait_timeout(state, timeout)
+                    .expect("daemon state poisoned");
+                state
+            };
+        }
+        state.run_now = false;
+        state.running = true;
+        state.run_started = Some(now());
+    }
+    /// Records that a run started, outside of `wait`
+    fn start(&self) {
+        let mut state = self.state.lock().expect("daemon state poisoned");
+        state.running = true;
+        state.run_started = Some(now());
+    }
+    /// Records that a run with failures failed operations finished
+    pub fn ran(&self, failures: usize) {
+        let mut state = self.state.lock().expect("daemon state poisoned");
+        state.running = false;
+        state.runs += 1;
+        state.last_run = Some(now());
+        state.failures = failures;
+        state.failed_runs = match failures {
+            0 => 0,
+            _ => state.failed_runs + 1,
+        };
+    }
+}
+
+/// Listens on socket, replacing a socket left behind by a daemon that is
+/// gone, but not one that still answers
+pub fn bind(socket: &Path) -> io::Result<UnixListener> {
+    if socket.exists() {
+        if UnixStream::connect(socket).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("a daemon is already listening on {}", socket.display()),
+            ));
+        }
+        std::fs::remove_file(socket)?;
+    }
+    if let Some(parent) = socket.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    UnixListener::bind(socket)
+}
+
+/// Answers the command on stream
+fn answer(mut stream: UnixStream, controls: &Controls) -> io::Result<()> {
+    stream.set_read_timeout(Some(CONTROL_TIMEOUT))?;
+    stream.set_write_timeout(Some(CONTROL_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let answer = match Control::parse(line.trim()) {
+        Some(command) => {
+            info!("daemon: {}", command.name());
+            controls.handle(command)
+        }
+        None => format!("unknown command {:?}", line.trim()),
+    };
+    writeln!(stream, "{answer}")
+}
+
+/// Answers commands on listener, forever
+///
+/// Every connection is answered on a thread of its own, and given up on
+/// after `CONTROL_TIMEOUT`, so a client that never writes its command holds
+/// up nothing.
+pub fn listen(listener: UnixListener, controls: Arc<Controls>) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                warn!("daemon: control connection failed: {err}");
+                continue;
+            }
+        };
+        let controls = Arc::clone(&controls);
+        thread::spawn(move || {
+            if let Err(err) = answer(stream, &controls) {
+                warn!("daemon: control connection failed: {err}");
+            }
+        });
+    }
+}
+
+/// Links every enabled link that isn't in place, printing what changed
+///
+/// Links that are already in place are left alone. Links where something else
+/// is in the way are drift, and are only replaced if the settings force it.
+pub fn reconcile_links(config: &Config) -> RunReport {
+    let vars = config.template_vars();
+    let report = config.report_links("reconcile", |link| {
+        link.link_with(&vars, config.settings.force)
+    });
+    for category in &report.categories {
+        for link in &category.links {
+            match (&link.message, link.succeeded) {
+                // Linking succeeded without complaint, so the link was missing
+                (None, true) => eprintln!(
+                    "{} {} {}: recreated {}",
+                    result_str(true),
+                    category.name,
+                    link.name,
+                    link.rx
+                ),
+                (Some(message), false) => eprintln!(
+                    "{} {} {}: drift, {message}",
+                    result_str(false),
+                    category.name,
+                    link.name
+                ),
+                _ => trace!("{} {} is in place", category.name, link.name),
+            }
+        }
+    }
+    report
+}
+
+/// How many operations in report failed
+fn failures(report: &RunReport) -> usize {
+    report
+        .categories
+        .iter()
+        .map(|category| {
+            category.repos.iter().filter(|repo| !repo.succeeded).count()
+                + category.links.iter().filter(|link| !link.succeeded).count()
+        })
+        .sum()
+}
+
+/// Runs series, and reconciles links if links is set, every interval, forever,
+/// controlled over socket, counting as wedged in a run longer than
+/// stall_after
+///
+/// Each run gets the max duration of the settings anew.
+///
+/// Only returns if the socket can't be listened on.
+pub fn run(
+    config: &mut Config,
+    interval: Duration,
+    stall_after: Duration,
+    series: Series,
+    links: bool,
+    socket: &Path,
+) -> io::Result<()> {
+    let listener = bind(socket)?;
+    let controls = Arc::new(Controls::new(stall_after));
+    let listening = Arc::clone(&controls);
+    thread::spawn(move || listen(listener, listening));
+    controls.start();
+    loop {
+        debug!("daemon: running {series:?}, reconciling links: {links}");
+        config.settings.start();
+        let mut failed = match series {
+            Series::None => 0,
+            Series::Pull => failures(&config.pull_all()),
+            Series::Quick => failures(&config.quick(None)),
+            Series::Fast => failures(&config.fast(None)),
+        };
+        if links {
+            failed += failures(&reconcile_links(config));
+        }
+        controls.ran(failed);
+        controls.wait(interval);
+    }
+}
@@ -0,0 +1,495 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Diagnoses problems with the environment and config
+//!
+//! The checks only report, and `fix` repairs what can be repaired without
+//! a decision of the user, saying exactly what it changed.
+
+use log::{debug, error, info, trace, warn};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use crate::checkouts::{self, Found};
+use crate::copy::Hashes;
+use crate::environment::{compare_versions, parse_git_version, DEFAULT_MIN_GIT_VERSION};
+use crate::git::{Config, LinkStatus, Repo, RepoFlags, RepoStatus};
+use crate::history::History;
+use crate::ids::Ids;
+use crate::utils::exec::Exec;
+
+/// The result of checking a single thing
+#[derive(PartialEq, Eq, Debug)]
+pub struct Check {
+    /// What was checked
+    pub subject: String,
+    /// What is wrong with it, if anything
+    pub problem: Option<String>,
+}
+
+impl Check {
+    fn ok(subject: String) -> Self {
+        Check {
+            subject,
+            problem: None,
+        }
+    }
+    fn failed(subject: String, problem: String) -> Self {
+        Check {
+            subject,
+            problem: Some(problem),
+        }
+    }
+}
+
+/// A change `fix` made, or tried to make
+#[derive(PartialEq, Eq, Debug)]
+pub struct Fix {
+    /// What was changed
+    pub subject: String,
+    /// The change
+    pub change: String,
+    /// Why the change failed, if it did
+    pub error: Option<String>,
+}
+
+impl Fix {
+    fn new(subject: &str, change: String, result: Result<(), String>) -> Self {
+        Fix {
+            subject: subject.to_string(),
+            change,
+            error: result.err(),
+        }
+    }
+}
+
+fn check_git(min_version: &str) -> Check {
+    let subject = String::from("git");
+    match Command::new("git").arg("--version").exec_output() {
+        Ok(output) if output.status.success() => {
+            match parse_git_version(&String::from_utf8_lossy(&output.stdout)) {
+                Some(version) if compare_versions(&version, min_version).is_lt() => Check::failed(
+                    subject,
+                    format!("git {version} is older than {min_version}, some operations may fail"),
+                ),
+                Some(_) => Check::ok(subject),
+                None => Check::failed(subject, String::from("git version could not be read")),
+            }
+        }
+        Ok(output) => Check::failed(subject, String::from("git --version failed")),
+        Err(err) => Check::failed(subject, format!("git could not be run: {err}")),
+    }
+}
+
+fn check_config(config: &Config) -> Vec<Check> {
+    let problems = config.validate();
+    if problems.is_empty() {
+        vec![Check::ok(String::from("config"))]
+    } else {
+        problems
+            .into_iter()
+            .map(|problem| Check::failed(String::from("config"), problem))
+            .collect()
+    }
+}
+
+/// Checks the repos of config, with the checkouts found outside where config
+/// has them, for those not cloned there
+fn check_repos(config: &Config, found: &[Found]) -> Vec<Check> {
+    let mut checks = vec![];
+    for (category_name, category) in config.categories.iter() {
+        for (name, repo) in category.repos.iter().flatten() {
+            // Invalid repos are already reported by check_config
+            if repo.validate_kind().is_err() {
+                continue;
+            }
+            let subject = format!("repo {category_name} {name}");
+            checks.push(match repo.status() {
+                RepoStatus::Missing => match found.iter().find_map(|found| match found {
+                    Found::Moved {
+                        category,
+                        name: moved,
+                        path,
+                    } if category == category_name && moved == name => Some(path),
+                    _ => None,
+                }) {
+                    Some(path) => Check::failed(
+                        subject,
+                        format!(
+                            "not cloned, but {} is a checkout of its remote",
+                            path.display()
+                        ),
+                    ),
+                    None => Check::failed(subject, String::from("not cloned")),
+                },
+                RepoStatus::Unknown => {
+                    Check::failed(subject, String::from("not a working git repo"))
+                }
+                RepoStatus::Detached => Check::failed(
+                    subject,
+                    String::from("HEAD is detached, try seidr fix-head"),
+                ),
+                _ => match (&category.hooks_dir, repo.hooks_path()) {
+                    (Some(expected), Some(actual)) if &actual != expected => Check::failed(
+                        subject,
+                        format!("core.hooksPath is {actual}, expected {expected}"),
+                    ),
+                    (Some(expected), None) => Check::failed(
+                        subject,
+                        format!("core.hooksPath isn't set, expected {expected}"),
+                    ),
+                    _ => Check::ok(subject),
+                },
+            });
+            if let (Some(origin), Some(url)) = (repo.origin_url(), config.remote_url(repo)) {
+                if origin != url {
+                    checks.push(Check::failed(
+                        format!("repo {category_name} {name}"),
+                        format!("origin is {origin}, expected {url}"),
+                    ));
+                }
+            }
+            if let (Some(pushes_to), Some(url)) = (repo.push_url(), config.push_url(repo)) {
+                if pushes_to != url {
+                    checks.push(Check::failed(
+                        format!("repo {category_name} {name}"),
+                        format!("origin pushes to {pushes_to}, expected {url}"),
+                    ));
+                }
+            }
+            if !matches!(repo.status(), RepoStatus::Missing | RepoStatus::Unknown) {
+                for (key, value, current) in repo.git_config_drift() {
+                    checks.push(Check::failed(
+                        format!("repo {category_name} {name}"),
+                        match current {
+                            Some(current) => format!("{key} is {current}, expected {value}"),
+                            None => format!("{key} isn't set, expected {value}"),
+                        },
+                    ));
+                }
+            }
+        }
+    }
+    checks
+}
+
+/// Reports the checkouts of remotes that no repo in config has
+fn check_unmanaged(found: &[Found]) -> Vec<Check> {
+    found
+        .iter()
+        .filter_map(|found| match found {
+            Found::Unmanaged { path, url } => Some(Check::failed(
+                format!("checkout {}", path.display()),
+                format!("clone of {url}, which isn't in the config"),
+            )),
+            Found::Moved { .. } => None,
+        })
+        .collect()
+}
+
+fn check_links(config: &Config) -> Vec<Check> {
+    let mut checks = vec![];
+    for (category_name, category) in config.categories.iter() {
+        for (name, link) in category.links.iter().flatten() {
+            if !config.is_link_enabled(link) {
+                continue;
+            }
+            let subject = format!("link {category_name} {name}");
+            let rx = Path::new(&link.rx);
+            checks.push(match rx.read_link() {
+                Ok(target) if target == Path::new(&link.tx) => {
+                    if rx.exists() {
+                        Check::ok(subject)
+                    } else {
+                        Check::failed(subject, format!("{} doesn't exist", link.tx))
+                    }
+                }
+                Ok(target) => Check::failed(
+                    subject,
+                    format!("{} links to {}", link.rx, target.display()),
+                ),
+                Err(_) if rx.exists() => {
+                    Check::failed(subject, format!("{} exists and isn't a link", link.rx))
+                }
+                Err(_) => Check::failed(subject, String::from("not linked")),
+            });
+        }
+    }
+    checks
+}
+
+/// Finds state that refers to repos or links that aren't in config
+fn check_state(config: &Config, history: &History, ids: &Ids, hashes: &Hashes) -> Vec<Check> {
+    let mut checks = vec![];
+    let renames = ids.renames(config);
+    for rename in &renames {
+        checks.push(Check::failed(
+            format!("state {}/{}", rename.from.0, rename.from.1),
+            format!(
+                "renamed to {}/{}, the state is migrated on the next run",
+                rename.to.0, rename.to.1
+            ),
+        ));
+    }
+    let mut orphans = BTreeSet::new();
+    for record in history.list().unwrap_or_default() {
+        for category in &record.report.categories {
+            for repo in &category.repos {
+                let known = config
+                    .categories
+                    .get(&category.name)
+                    .and_then(|cat| cat.repos.as_ref())
+                    .map_or(false, |repos| repos.contains_key(&repo.name));
+                let renamed = renames
+                    .iter()
+                    .any(|rename| rename.from == (category.name.clone(), repo.name.clone()));
+                if !known && !renamed {
+                    orphans.insert(format!("{}/{}", category.name, repo.name));
+                }
+            }
+        }
+    }
+    for orphan in orphans {
+        checks.push(Check::failed(
+            format!("state {orphan}"),
+            String::from("history refers to a repo that isn't in the config"),
+        ));
+    }
+    let copies: BTreeSet<&String> = config
+        .categories
+        .values()
+        .flat_map(|category| category.links.iter().flatten())
+        .filter(|(_, link)| !link.kind.is_symlink())
+        .map(|(_, link)| &link.rx)
+        .collect();
+    for rx in hashes.paths().filter(|rx| !copies.contains(rx)) {
+        checks.push(Check::failed(
+            format!("state {rx}"),
+            String::from("hash of a copy that isn't in the config"),
+        ));
+    }
+    if checks.is_empty() {
+        checks.push(Check::ok(String::from("state")));
+    }
+    checks
+}
+
+/// Creates dir if it doesn't exist yet
+fn create_dir(fixes: &mut Vec<Fix>, subject: &str, dir: &Path) -> bool {
+    if dir.as_os_str().is_empty() || dir.exists() {
+        return true;
+    }
+    let result = fs::create_dir_all(dir).map_err(|err| err.to_string());
+    let created = result.is_ok();
+    fixes.push(Fix::new(
+        subject,
+        format!("created {}", dir.display()),
+        result,
+    ));
+    created
+}
+
+/// Clones missing repos, creating their path first, and sets origin and git
+/// config of cloned repos to what is in config, if confirm agrees
+fn fix_repos<F: Fn(&str) -> bool>(config: &Config, confirm: &F, fixes: &mut Vec<Fix>) {
+    let mut categories: Vec<_> = config.categories.iter().collect();
+    categories.sort_by_key(|(name, _)| *name);
+    for (category_name, category) in categories {
+        let mut repos: Vec<_> = category.repos.iter().flatten().collect();
+        repos.sort_by_key(|(name, _)| *name);
+        for (name, repo) in repos {
+            if repo.validate_kind().is_err() {
+                continue;
+            }
+            let subject = format!("repo {category_name} {name}");
+            let Some(url) = config.remote_url(repo) else {
+                continue;
+            };
+            match repo.status() {
+                RepoStatus::Missing => {
+                    let clones = repo
+                        .flags
+                        .as_ref()
+                        .map_or(false, |flags| flags.contains(&RepoFlags::Clone));
+                    let Some(path) = repo.path.as_ref().filter(|_| clones) else {
+                        continue;
+                    };
+                    if !create_dir(fixes, &subject, Path::new(path)) {
+                        continue;
+                    }
+                    let result = if repo.clone_url(&url) {
+                        Ok(())
+                    } else {
+                        Err(String::from("git clone failed"))
+                    };
+                    fixes.push(Fix::new(&subject, format!("cloned {url}"), result));
+                }
+                RepoStatus::Unknown => {}
+                _ => {
+                    let origin = repo.origin_url();
+                    fix_remote(&subject, "origin", origin, url, confirm, fixes, |url| {
+                        repo.set_origin_url(url)
+                    });
+                    if let Some(push_url) = config.push_url(repo) {
+                        let pushes_to = repo.push_url();
+                        let what = "where origin pushes to";
+                        fix_remote(&subject, what, pushes_to, push_url, confirm, fixes, |url| {
+                            repo.set_push_url(url)
+                        });
+                    }
+                    fix_git_config(&subject, repo, confirm, fixes);
+                }
+            }
+        }
+    }
+}
+
+/// Sets keys of git_config that aren't set in the checkout, and those set to
+/// something else, if confirm agrees
+fn fix_git_config<F: Fn(&str) -> bool>(
+    subject: &str,
+    repo: &Repo,
+    confirm: &F,
+    fixes: &mut Vec<Fix>,
+) {
+    for (key, value, current) in repo.git_config_drift() {
+        if let Some(current) = &current {
+            if !confirm(&format!(
+                "{subject}: change {key} from {current} to {value}?"
+            )) {
+                continue;
+            }
+        }
+        let result = if repo.set_git_config(key, value) {
+            Ok(())
+        } else {
+            Err(String::from("git config failed"))
+        };
+        fixes.push(Fix::new(subject, format!("set {key} to {value}"), result));
+    }
+}
+
+/// Sets what, which is current, to url with set, if confirm agrees
+fn fix_remote<F, S>(
+    subject: &str,
+    what: &str,
+    current: Option<String>,
+    url: String,
+    confirm: &F,
+    fixes: &mut Vec<Fix>,
+    set: S,
+) where
+    F: Fn(&str) -> bool,
+    S: FnOnce(&str) -> bool,
+{
+    let Some(current) = current.filter(|current| current != &url) else {
+        return;
+    };
+    if !confirm(&format!(
+        "{subject}: change {what} from {current} to {url}?"
+    )) {
+        return;
+    }
+    let result = if set(&url) {
+        Ok(())
+    } else {
+        Err(String::from("git remote set-url failed"))
+    };
+    fixes.push(Fix::new(
+        subject,
+        format!("changed {what} from {current} to {url}"),
+        result,
+    ));
+}
+
+/// Links missing links, creating the dir of rx first, and links broken
+/// symlinks again
+///
+/// Anything else in the way of a link is left for the user to decide about.
+fn fix_links(config: &Config, fixes: &mut Vec<Fix>) {
+    let tx_roots = config.tx_roots();
+    let vars = config.template_vars();
+    let mut categories: Vec<_> = config.categories.iter().collect();
+    categories.sort_by_key(|(name, _)| *name);
+    for (category_name, category) in categories {
+        let mut links: Vec<_> = category.links.iter().flatten().collect();
+        links.sort_by_key(|(name, _)| *name);
+        for (name, link) in links {
+            if !config.is_link_enabled(link) {
+                continue;
+            }
+            let subject = format!("link {category_name} {name}");
+            let change = match link.status(&tx_roots) {
+                LinkStatus::Missing => {
+                    let rx = Path::new(&link.rx);
+                    if !create_dir(fixes, &subject, rx.parent().unwrap_or(rx)) {
+                        continue;
+                    }
+                    format!("linked {} to {}", link.rx, link.tx)
+                }
+                LinkStatus::Broken => {
+                    if let Err(err) = fs::remove_file(&link.rx) {
+                        fixes.push(Fix::new(
+                            &subject,
+                            format!("removed broken link {}", link.rx),
+                            Err(err.to_string()),
+                        ));
+                        continue;
+                    }
+                    format!("linked {} to {} again", link.rx, link.tx)
+                }
+                _ => continue,
+            };
+            let result = link
+                .link_with(&vars, config.settings.force)
+                .map(|_| ())
+                .map_err(|err| err.to_string());
+            fixes.push(Fix::new(&subject, change, result));
+        }
+    }
+}
+
+/// Repairs what can be repaired of what the checks find, returning what was
+/// changed
+///
+/// Missing paths of repos and dirs of links are created, missing repos are
+/// cloned, missing and broken links are linked, and origin, and where it
+/// pushes to, are set to the urls in config when confirm agrees, as that
+/// changes where pushes go.
+pub fn fix<F: Fn(&str) -> bool>(config: &Config, confirm: F) -> Vec<Fix> {
+    let mut fixes = vec![];
+    fix_repos(config, &confirm, &mut fixes);
+    fix_links(config, &mut fixes);
+    fixes
+}
+
+/// Runs every check against config
+pub fn run(config: &Config) -> Vec<Check> {
+    let mut checks = vec![check_git(
+        config
+            .min_git_version
+            .as_deref()
+            .unwrap_or(DEFAULT_MIN_GIT_VERSION),
+    )];
+    checks.append(&mut check_config(config));
+    let found = checkouts::find(config);
+    let mut rest = check_repos(config, &found);
+    rest.append(&mut check_unmanaged(&found));
+    rest.append(&mut check_links(config));
+    match (Ids::load(), Hashes::load()) {
+        (Ok(ids), Ok(hashes)) => {
+            rest.append(&mut check_state(config, &History::default(), &ids, &hashes))
+        }
+        (Err(err), _) | (_, Err(err)) => rest.push(Check::failed(
+            String::from("state"),
+            format!("failed to read state: {err}"),
+        )),
+    }
+    rest.sort_by(|a, b| a.subject.cmp(&b.subject));
+    checks.append(&mut rest);
+    checks
+}
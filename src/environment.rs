@@ -0,0 +1,113 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! The environment seidr runs in, recorded with every run so a failure can be
+//! reproduced, and checked by doctor
+
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::process::Command;
+
+use crate::utils::dir::hostname;
+use crate::utils::exec::Exec;
+
+/// The oldest git seidr is known to work with, when the config doesn't say,
+/// as commit trailers need `git commit --trailer`
+pub const DEFAULT_MIN_GIT_VERSION: &str = "2.32";
+
+/// The versions of seidr and the tools it runs, and where it runs
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Environment {
+    pub seidr: String,
+    /// The version of git, if it could be run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git: Option<String>,
+    pub os: String,
+    pub host: String,
+}
+
+impl Environment {
+    /// Captures the environment of this process
+    pub fn capture() -> Self {
+        Environment {
+            seidr: env!("CARGO_PKG_VERSION").to_string(),
+            git: git_version(),
+            os: format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+            host: hostname(),
+        }
+    }
+}
+
+/// Returns the version of git, like "2.39.2"
+pub fn git_version() -> Option<String> {
+    let output = Command::new("git").arg("--version").exec_output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_git_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parses the output of git --version, like "git version 2.39.2", or
+/// "git version 2.39.2 (Apple Git-143)"
+pub fn parse_git_version(output: &str) -> Option<String> {
+    output
+        .trim()
+        .strip_prefix("git version ")?
+        .split_whitespace()
+        .next()
+        .map(str::to_string)
+}
+
+/// Whether version meets requirement, like ">=0.3", or several separated by
+/// commas, like ">=0.3, <1"
+///
+/// The operators are `>=`, `>`, `<=`, `<` and `=`, a version without one has
+/// to be met or exceeded.
+pub fn meets_requirement(version: &str, requirement: &str) -> Result<bool, String> {
+    let mut meets = true;
+    for part in requirement.split(',').map(str::trim) {
+        let (wanted, required): (&[Ordering], &str) = if let Some(v) = part.strip_prefix(">=") {
+            (&[Ordering::Greater, Ordering::Equal], v)
+        } else if let Some(v) = part.strip_prefix("<=") {
+            (&[Ordering::Less, Ordering::Equal], v)
+        } else if let Some(v) = part.strip_prefix('>') {
+            (&[Ordering::Greater], v)
+        } else if let Some(v) = part.strip_prefix('<') {
+            (&[Ordering::Less], v)
+        } else if let Some(v) = part.strip_prefix('=') {
+            (&[Ordering::Equal], v)
+        } else {
+            (&[Ordering::Greater, Ordering::Equal], part)
+        };
+        let required = required.trim();
+        if !required.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(format!("{part:?} isn't a version requirement"));
+        }
+        meets &= wanted.contains(&compare_versions(version, required));
+    }
+    Ok(meets)
+}
+
+/// Compares versions like "2.39.2" component by component, ignoring anything
+/// after the digits of a component, like in "2.40.0.windows.1" or "2.41.rc0"
+///
+/// Missing components count as zero, so "2.32" equals "2.32.0".
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parse = |version: &str| -> Vec<u64> {
+        version
+            .split('.')
+            .map_while(|component| {
+                let digits: String = component.chars().take_while(char::is_ascii_digit).collect();
+                digits.parse().ok()
+            })
+            .collect()
+    };
+    let (a, b) = (parse(a), parse(b));
+    (0..a.len().max(b.len()))
+        .map(|i| a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0)))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
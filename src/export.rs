@@ -0,0 +1,106 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Renders the config for other tools, so links can be migrated away from, or
+//! shared with, them
+//!
+//! Only links are exported, as the other tools don't manage repos. Links whose
+//! rx isn't in the home directory are skipped with a warning, since both stow
+//! and home-manager place files relative to it.
+
+use log::{debug, error, info, trace, warn};
+use std::fs;
+use std::io;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+use crate::git::{Config, Link};
+use crate::utils::dir::absolute_path;
+
+/// The enabled links of config, sorted by category and name
+fn enabled_links(config: &Config) -> Vec<(&str, &Link)> {
+    let mut links: Vec<(&str, &Link)> = config
+        .categories
+        .iter()
+        .flat_map(|(category, cat)| {
+            cat.links
+                .iter()
+                .flat_map(|links| links.values())
+                .map(move |link| (category.as_str(), link))
+        })
+        .filter(|(_, link)| config.is_link_enabled(link))
+        .collect();
+    links.sort_by_key(|(category, link)| (*category, &link.name));
+    links
+}
+
+/// The rx of link relative to home, None if it's elsewhere
+fn home_relative(link: &Link, home: &Path) -> Option<PathBuf> {
+    match absolute_path(Path::new(&link.rx)).strip_prefix(home) {
+        Ok(relative) if relative.as_os_str().is_empty() => None,
+        Ok(relative) => Some(relative.to_path_buf()),
+        Err(_) => {
+            warn!(
+                "skipping {}, {} is not in {}",
+                link.name,
+                link.rx,
+                home.display()
+            );
+            None
+        }
+    }
+}
+
+/// Creates a GNU Stow directory with a package per category in dir
+///
+/// Each link becomes a symlink to its tx, placed in the package where stow
+/// should put it relative to home, so `stow -d dir -t home category` recreates
+/// the links of category. Returns the symlinks created.
+pub fn stow(config: &Config, dir: &Path, home: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut created = vec![];
+    for (category, link) in enabled_links(config) {
+        let Some(relative) = home_relative(link, home) else {
+            continue;
+        };
+        let entry = dir.join(category).join(relative);
+        if let Some(parent) = entry.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        debug!("exporting {} to {}", link.name, entry.display());
+        symlink(absolute_path(Path::new(&link.tx)), &entry)?;
+        created.push(entry);
+    }
+    Ok(created)
+}
+
+/// Quotes s as a nix string
+fn nix_string(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace("${", "\\${");
+    format!("\"{escaped}\"")
+}
+
+/// Renders a home-manager module setting `home.file` for every link
+///
+/// The files are out of store symlinks to their tx, so like with seidr, edits
+/// to the tx show up without rebuilding.
+pub fn home_manager(config: &Config, home: &Path) -> String {
+    let mut module = String::from("{ config, ... }:\n{\n");
+    for (category, link) in enabled_links(config) {
+        let Some(relative) = home_relative(link, home) else {
+            continue;
+        };
+        module.push_str(&format!(
+            "  # {category} {}\n  home.file.{}.source =\n    config.lib.file.mkOutOfStoreSymlink {};\n",
+            link.name,
+            nix_string(&relative.to_string_lossy()),
+            nix_string(&absolute_path(Path::new(&link.tx)).to_string_lossy()),
+        ));
+    }
+    module.push_str("}\n");
+    module
+}
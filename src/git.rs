@@ -6,19 +6,42 @@
 //! Git repositories
 
 use log::{debug, error, info, trace, warn};
-use serde::{Deserialize, Serialize};
-use spinners::{Spinner, Spinners};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize, Serializer};
+use spinners::{Spinner, Spinners, Stream};
+use std::collections::{BTreeMap, HashMap};
 use std::fs::canonicalize;
 use std::os::unix::fs::symlink;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use std::{fmt, fs, process::Command};
 
-use crate::settings;
-use crate::utils::strings::{failure_str, success_str};
+use crate::archive;
+use crate::branches::DefaultBranches;
+use crate::condition::Condition;
+use crate::copy;
+use crate::environment::meets_requirement;
+use crate::leaks;
+use crate::parallel::{run_all, Limits, OpClass, Parallelism};
+use crate::progress::Compact;
+use crate::report::{CategoryReport, LinkResult, RepoResult, RunReport};
+use crate::schedule::{self, FileOrder, Schedule, Timings};
+use crate::secrets::HttpsAuth;
+use crate::selection::{Selection, Target};
+use crate::settings::Settings;
+use crate::state::StateConfig;
+use crate::template;
+use crate::trash::Trash;
+use crate::utils::dir::{absolute_path, expand_tilde, home_dir, hostname};
+use crate::utils::exec::Exec;
+use crate::utils::i18n::{fill, tr, tr_with, Msg};
+use crate::utils::strings::{result_str, result_width, FAST_COMMIT, QUICK_COMMIT, SPLIT_COMMIT};
+use crate::utils::suggest::with_suggestion;
+use crate::utils::term::{fit, Output};
+use crate::utils::url::{alias_ssh_host, GitUrl, UrlScheme};
+use crate::utils::yaml::duplicate_keys;
 
 /// An enum containing flags that change behaviour of repos and categories
-#[derive(PartialOrd, Ord, PartialEq, Eq, Serialize, Deserialize, Debug)]
+#[derive(Clone, PartialOrd, Ord, PartialEq, Eq, Serialize, Deserialize, Debug)]
 #[non_exhaustive]
 pub enum RepoFlags {
     /// If clone is set, the repository should respond to the clone subcommand
@@ -39,6 +62,8 @@ pub enum RepoFlags {
     ///
     /// This is a shortcut for Pull, Add, Commit, Push
     Fast,
+    /// If clean is set, untracked files may be removed by the clean subcommand
+    Clean,
 }
 
 #[derive(PartialEq, Eq, Serialize, Deserialize, Debug)]
@@ -52,48 +77,472 @@ pub enum RepoKinds {
     Link,
 }
 
+/// Serializes a map sorted by key, so the config is written in a stable order
+fn sorted<S: Serializer, V: Serialize>(
+    map: &HashMap<String, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+/// Like sorted, for optional maps
+fn sorted_opt<S: Serializer, V: Serialize>(
+    map: &Option<HashMap<String, V>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    map.as_ref()
+        .map(|map| map.iter().collect::<BTreeMap<_, _>>())
+        .serialize(serializer)
+}
+
 /// Represents the config.toml file.
 ///
 /// For diagrams of the underlying architecture, consult ARCHITECHTURE.md
-#[derive(Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
 pub struct Config {
+    /// the versions of seidr the config needs, like `>=0.3`, checked before
+    /// anything else is read, see `environment::meets_requirement`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requires: Option<String>,
     /// map of all categories
     ///
     /// Key should conceptually be seen as the name of the category.
+    #[serde(serialize_with = "sorted")]
     pub categories: HashMap<String, Category>,
+    /// git repo to sync the state dir with, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<StateConfig>,
+    /// map of host profiles
+    ///
+    /// Key should conceptually be seen as the hostname.
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "sorted"
+    )]
+    pub hosts: HashMap<String, HostProfile>,
+    /// template variables, also interpolated into paths as `${name}`, see
+    /// the template module
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "sorted"
+    )]
+    pub vars: HashMap<String, String>,
+    /// how many operations may run at once, sequential if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parallelism: Option<Parallelism>,
+    /// the oldest git doctor accepts, see the environment module for the
+    /// default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_git_version: Option<String>,
+    /// how many backups of the config seidr keeps when it writes it, see the
+    /// config_file module for the default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_backups: Option<usize>,
+    /// write the state of repos and links after runs, see the machine_state
+    /// module
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub machine_state: bool,
+    /// what seidr runs when run without arguments, like `links list`, as if
+    /// given on the command line, help is shown when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_command: Option<String>,
+    /// the order repos are started in, see the schedule module
+    #[serde(default, skip_serializing_if = "Schedule::is_alpha")]
+    pub schedule: Schedule,
+    /// the order the yaml lists the repos in, for `schedule: config_order`
+    #[serde(skip)]
+    pub order: FileOrder,
+    /// the settings runs on the config have, from the command line
+    #[serde(skip)]
+    pub settings: Settings,
+}
+
+/// Settings that only apply on a single host
+#[derive(Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct HostProfile {
+    /// The link groups to link on this host
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub link_groups: Vec<String>,
+    /// Template variables on this host, overriding those of the config
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "sorted"
+    )]
+    pub vars: HashMap<String, String>,
 }
 
 /// Represents a category of repositories
 ///
 /// This allows you to organize your repositories into categories
-#[derive(Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
 pub struct Category {
+    /// Flags of repos in the category that have none of their own, see
+    /// `Config::flatten_categories`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flags: Option<Vec<RepoFlags>>,
+    /// Directory repos in the category without a path are cloned in, which
+    /// subcategories inherit, or resolve a relative root against
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub flags: Option<Vec<RepoFlags>>, // FIXME: not implemented
+    pub root: Option<String>,
+    /// The category is skipped by commands that change things, unless it is
+    /// named, like experiments or huge mirrors, see
+    /// `selection::drop_manual`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub manual: bool,
     /// map of all repos in category
     ///
     /// Key should conceptually be seen as the name of the category.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "sorted_opt")]
     pub repos: Option<HashMap<String, Repo>>,
 
     /// map of all links in category
     ///
     /// Key should conceptually be seen as the name of the category.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "sorted_opt")]
     pub links: Option<HashMap<String, Link>>,
+
+    /// Directory of git hooks, set as core.hooksPath for all repos in the
+    /// category
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hooks_dir: Option<String>,
+
+    /// Commit message for repos in the category, when none is given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_commit_message: Option<String>,
+
+    /// Trailers added to commits seidr makes in repos of the category, see
+    /// `Config::trailers`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trailers: Option<Vec<String>>,
+
+    /// Aliases of ssh hosts, like `github.com: github-work`, that urls of
+    /// repos in the category are rewritten to, see `Config::remote_url`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssh_host_alias: Option<BTreeMap<String, String>>,
+
+    /// Git config of repos in the category, like `pull.rebase: true`, under
+    /// that of the repos themselves, see `Config::flatten_categories`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_config: Option<BTreeMap<String, String>>,
+
+    /// map of subcategories, which are named by their path, like
+    /// `work/client`, once the config is loaded
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "sorted_opt")]
+    pub categories: Option<HashMap<String, Category>>,
 }
 
 /// Contain fields for a single link.
-#[derive(Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Link {
     /// The name of the link
     pub name: String,
     pub rx: String,
     pub tx: String,
+    /// The link group, links in a group are only linked on hosts that enable
+    /// the group
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Whether rx is a symlink to tx, or a copy of it
+    #[serde(default, skip_serializing_if = "LinkKind::is_symlink")]
+    pub kind: LinkKind,
+    /// The link is part of the system, like /etc/nixos, rather than the home
+    /// dir of the user, and is only touched with the --system flag
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub system: bool,
+    /// What to do when something is in the way of the link, see
+    /// `Link::conflict_strategy`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_conflict: Option<ConflictStrategy>,
+    /// The link is only linked on machines where this holds, see the
+    /// condition module
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub condition: Option<Condition>,
+}
+
+/// What to do with a file, or anything else, in the way of a link
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictStrategy {
+    /// Leave it, and don't link
+    Skip,
+    /// Move it to the trash, and link
+    Backup,
+    /// Delete it, and link
+    Replace,
+    /// Leave it, and fail the link
+    Fail,
+}
+
+impl fmt::Display for ConflictStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConflictStrategy::Skip => write!(f, "skipped, as something is in the way"),
+            ConflictStrategy::Backup => write!(f, "moved what was in the way to the trash"),
+            ConflictStrategy::Replace => write!(f, "replaced what was in the way"),
+            ConflictStrategy::Fail => write!(f, "failed, as something is in the way"),
+        }
+    }
+}
+
+/// What linking did, when it succeeded
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Linked {
+    /// The link was created, with nothing in the way
+    Created,
+    /// Something was in the way, and the conflict strategy was applied
+    Resolved(ConflictStrategy),
+}
+
+/// What is at the rx of a link, as listed by `seidr links list`
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStatus {
+    /// rx is a symlink to tx, or an unchanged copy of it
+    Linked,
+    /// There is nothing at rx
+    Missing,
+    /// rx is a symlink to something that doesn't exist
+    Broken,
+    /// rx is a symlink to something outside the tx of every link
+    Foreign,
+    /// rx is a symlink to the tx of another link
+    Elsewhere,
+    /// rx is something other than a symlink, or a copy that was changed
+    Occupied,
+}
+
+impl fmt::Display for LinkStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkStatus::Linked => write!(f, "linked"),
+            LinkStatus::Missing => write!(f, "missing"),
+            LinkStatus::Broken => write!(f, "broken"),
+            LinkStatus::Foreign => write!(f, "foreign"),
+            LinkStatus::Elsewhere => write!(f, "elsewhere"),
+            LinkStatus::Occupied => write!(f, "occupied"),
+        }
+    }
+}
+
+/// A link and what is at its rx, see `Config::list_links`
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct LinkListing {
+    pub category: String,
+    pub name: String,
+    pub rx: String,
+    pub tx: String,
+    pub status: LinkStatus,
+    /// Where rx links to, if it is a symlink
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+/// How a link puts tx at rx
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkKind {
+    /// rx is a symlink to tx
+    #[default]
+    Symlink,
+    /// rx is a copy of tx, see the copy module
+    Copy,
+    /// rx is a copy of tx rendered as a template, see the template module
+    Template,
+}
+
+impl LinkKind {
+    pub fn is_symlink(&self) -> bool {
+        *self == LinkKind::Symlink
+    }
+}
+
+/// Which branches are pushed by the push subcommand
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PushBranches {
+    /// Let git decide, according to push.default (usually the current branch)
+    #[default]
+    Current,
+    /// Push all branches
+    All,
+    /// Push all branches that already exist on the remote
+    Matching,
+}
+
+/// Settings for pushing a repo
+#[derive(Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct PushConfig {
+    #[serde(default)]
+    pub branches: PushBranches,
+    /// Also push all tags
+    #[serde(default)]
+    pub tags: bool,
+    /// Push a branch without an upstream to origin, and make that its
+    /// upstream, instead of failing, see `Repo::missing_upstream`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub set_upstream: bool,
+    /// Quick and fast push to the review branch of this host instead, for
+    /// merging deliberately from another machine, see `review_branch`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub review: bool,
+}
+
+/// What the review branches of hosts are named, before the hostname
+pub const REVIEW_PREFIX: &str = "seidr/";
+
+/// Returns the review branch of this host, like `seidr/laptop`, which quick
+/// and fast push to for repos with `push.review` set
+pub fn review_branch() -> String {
+    format!("{REVIEW_PREFIX}{}", hostname())
+}
+
+/// What commit dates are rounded down to
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DateRounding {
+    #[default]
+    None,
+    Minute,
+    Hour,
+    Day,
+}
+
+impl DateRounding {
+    fn seconds(&self) -> u64 {
+        match self {
+            DateRounding::None => 1,
+            DateRounding::Minute => 60,
+            DateRounding::Hour => 60 * 60,
+            DateRounding::Day => 24 * 60 * 60,
+        }
+    }
+}
+
+/// Normalizes the dates of commits, so they leak less about when work was done
+#[derive(Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct CommitDateConfig {
+    #[serde(default)]
+    pub round: DateRounding,
+    /// Fixed timezone offset to commit in, like "+0000". Defaults to the local
+    /// timezone
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+}
+
+/// What to do when the editor of an interactive commit times out
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeoutFallback {
+    /// Don't commit
+    #[default]
+    Skip,
+    /// Commit with the default commit message instead
+    Message,
+}
+
+/// Gives up on the editor of an interactive commit, so an unattended run never
+/// hangs waiting for it
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct CommitTimeout {
+    /// Seconds the editor may be open
+    pub seconds: u64,
+    #[serde(default)]
+    pub fallback: TimeoutFallback,
+}
+
+/// Parses a timezone offset like "+0130" into seconds east of UTC
+fn parse_tz_offset(tz: &str) -> Option<i64> {
+    let (sign, digits) = match tz.split_at(1) {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i64 = digits[..2].parse().ok()?;
+    let minutes: i64 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 60 * 60 + minutes * 60))
+}
+
+impl CommitDateConfig {
+    /// Returns the normalized date for the unix time now, in git's internal
+    /// date format "<unix time> <offset>"
+    pub fn normalize(&self, now: u64, tz: &str) -> Result<String, String> {
+        let offset = parse_tz_offset(tz).ok_or_else(|| format!("invalid timezone offset {tz}"))?;
+        // Round in local time, so rounding to days gives local midnight
+        let local = now as i64 + offset;
+        let unit = self.round.seconds() as i64;
+        let rounded = local - local.rem_euclid(unit) - offset;
+        Ok(format!("{rounded} {tz}"))
+    }
+    /// Returns the environment to commit with
+    fn env(&self) -> Vec<(&'static str, String)> {
+        let tz = match &self.timezone {
+            Some(tz) => tz.clone(),
+            None => Command::new("date")
+                .arg("+%z")
+                .exec_output()
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+                .unwrap_or_else(|_| String::from("+0000")),
+        };
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        match self.normalize(now, &tz) {
+            Ok(date) => vec![
+                ("GIT_AUTHOR_DATE", date.clone()),
+                ("GIT_COMMITTER_DATE", date),
+            ],
+            Err(err) => {
+                error!("not normalizing commit date: {err}");
+                vec![]
+            }
+        }
+    }
+}
+
+/// Parses the output of `git ls-remote --symref <url> HEAD`, returning the
+/// branch HEAD points to
+pub fn parse_symref(output: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        let (symref, name) = line.strip_prefix("ref: ")?.split_once('\t')?;
+        (name == "HEAD").then(|| {
+            symref
+                .strip_prefix("refs/heads/")
+                .unwrap_or(symref)
+                .to_string()
+        })
+    })
+}
+
+/// Checks the `requires` of yaml against the version of seidr, before the
+/// rest of it is read, which a config for a newer seidr may fail
+pub fn check_requires(yaml: &str) -> Result<(), String> {
+    let requirement = match serde_yaml::from_str::<serde_yaml::Value>(yaml) {
+        Ok(serde_yaml::Value::Mapping(config)) => match config.get("requires") {
+            Some(serde_yaml::Value::String(requirement)) => requirement.clone(),
+            Some(serde_yaml::Value::Number(requirement)) => requirement.to_string(),
+            Some(_) => return Err(String::from("requires should be a version requirement")),
+            None => return Ok(()),
+        },
+        // Not for this to report
+        _ => return Ok(()),
+    };
+    let version = env!("CARGO_PKG_VERSION");
+    match meets_requirement(version, &requirement)? {
+        true => Ok(()),
+        false => Err(format!(
+            "The config requires seidr {requirement}, but this is seidr {version}, please upgrade seidr"
+        )),
+    }
 }
 
 /// Holds a single git repository and related fields.
-#[derive(Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
 pub struct Repo {
     pub name: Option<String>,
     pub path: Option<String>,
@@ -102,6 +551,110 @@ pub struct Repo {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub kind: Option<RepoKinds>, // FIXME: not implemented
     pub flags: Option<Vec<RepoFlags>>,
+    /// The branch to clone and pull, the default branch of the remote when
+    /// not set, see `Config::default_branch`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Clone only the history after this date, anything git understands like
+    /// `1.year.ago`, see `Repo::deepen` for getting the rest
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shallow_since: Option<String>,
+    /// Clone without the objects the filter leaves out, like `blob:none`, and
+    /// fetch them only when a checkout needs them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub push: Option<PushConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit_date: Option<CommitDateConfig>,
+    /// Commit message for the repo, when none is given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_commit_message: Option<String>,
+    /// Trailers added to commits seidr makes in the repo, after those of the
+    /// category, see `Config::trailers`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trailers: Option<Vec<String>>,
+    /// Timeout on the editor of interactive commits, see
+    /// `Config::commit_editor`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit_timeout: Option<CommitTimeout>,
+    /// The directory in path the repo is checked out to, see
+    /// `Repo::dir_name`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    /// The upstream url is a local mirror of, see `Repo::sync_mirror`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mirror_of: Option<String>,
+    /// The url to clone and pull from, when it isn't url, like the upstream
+    /// of a fork, see `Config::remote_url`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pull_remote: Option<String>,
+    /// The url to push to, when it isn't url, like a fork of the upstream
+    /// pulled from, see `Config::push_url`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub push_remote: Option<String>,
+    /// The repo needs the user to authenticate, e.g. with a hardware token, so
+    /// git may prompt, and the repo is run last, see `Repo::prompt_env`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub interactive: bool,
+    /// A stable identity for the repo, so its state follows it when it is
+    /// renamed or moved to another category, see the ids module
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Credentials for an https url, read from a secret store, see the
+    /// secrets module
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth: Option<HttpsAuth>,
+    /// Commits seidr makes in the repo get a trailer naming the links whose
+    /// tx they change, see `Config::link_trailer`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub link_trailer: bool,
+    /// Git config set in the checkout after cloning, like
+    /// `user.email: work@corp`, which doctor checks and fixes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_config: Option<BTreeMap<String, String>>,
+    /// Pathspecs the add step stages, instead of everything, for repos where
+    /// seidr only manages some files, see `Repo::add_pathspecs`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub add_paths: Option<Vec<String>>,
+    /// Pathspecs the add step never stages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclude_paths: Option<Vec<String>>,
+    /// The repo is kept for linking and jumping, but runs of seidr leave it
+    /// alone, see `Repo::skips`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archived: bool,
+    /// The url of a `UrlRepo` is an archive, downloaded and extracted to the
+    /// checkout dir instead of cloned, see the archive module
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub extract: bool,
+    /// The sha256 the archive of an `extract` repo has to have, in hex as
+    /// `sha256sum` writes it, optionally prefixed with `sha256:`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// Commits are refused when what they add looks like it has secrets in
+    /// it, see the leaks module
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub scan_secrets: bool,
+    /// Fetches prune the refs and tags gone from origin, so checkouts that
+    /// live for long don't pile up dead refs, see `Repo::stale_remote_refs`
+    ///
+    /// As with `git fetch --prune-tags`, this also deletes tags that only
+    /// exist locally, and were never pushed.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fetch_prune: bool,
+}
+
+/// What happens when an operation in a series fails
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Debug, Default)]
+pub enum OnFailure {
+    /// Report the failure, and continue with the next operation
+    #[default]
+    Continue,
+    /// Skip the rest of the operations on this repo
+    SkipRepo,
+    /// Stop the series entirely, on all repos
+    AbortRun,
 }
 
 /// Represents a single operation on a repository
@@ -109,7 +662,78 @@ pub struct SeriesItem<'series> {
     /// The string to be displayed to the user
     pub operation: &'series str,
     /// The closure representing the actual operation
-    pub closure: Box<dyn Fn(&Repo) -> (bool)>,
+    pub closure: Box<dyn Fn(&Repo) -> (bool) + Sync + 'series>,
+    /// What to do if the operation fails
+    pub on_failure: OnFailure,
+}
+
+/// The health of a repo's working tree
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepoStatus {
+    /// The working tree doesn't exist, likely it hasn't been cloned yet
+    Missing,
+    /// Nothing to commit, and in sync with upstream
+    Clean,
+    /// There are uncommitted changes
+    Dirty,
+    /// The current branch is ahead of or behind its upstream
+    Diverged,
+    /// HEAD isn't on a branch, so pulls fail, see `Repo::reattach_head`
+    Detached,
+    /// Git failed to report a status
+    Unknown,
+}
+
+impl RepoStatus {
+    /// Parses the output of `git status --porcelain --branch`
+    ///
+    /// A detached HEAD is reported over changes, as it is what breaks pulls.
+    pub fn from_porcelain(porcelain: &str) -> Self {
+        let mut lines = porcelain.lines();
+        let branch = lines.next().unwrap_or_default();
+        let diverged = branch.contains("[ahead") || branch.contains("[behind");
+        if branch.starts_with("## HEAD (no branch)") {
+            RepoStatus::Detached
+        } else if lines.next().is_some() {
+            RepoStatus::Dirty
+        } else if diverged {
+            RepoStatus::Diverged
+        } else {
+            RepoStatus::Clean
+        }
+    }
+}
+
+impl fmt::Display for RepoStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepoStatus::Missing => write!(f, "missing"),
+            RepoStatus::Clean => write!(f, "clean"),
+            RepoStatus::Dirty => write!(f, "dirty"),
+            RepoStatus::Diverged => write!(f, "diverged"),
+            RepoStatus::Detached => write!(f, "detached"),
+            RepoStatus::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// A config entry that manages some path, as found by `Config::which`
+#[derive(PartialEq, Eq, Debug)]
+pub enum PathOwner<'a> {
+    /// The path is inside the working tree of a repo
+    Repo { category: &'a str, name: &'a str },
+    /// The path is the rx or tx of a link, or inside of them
+    Link { category: &'a str, name: &'a str },
+}
+
+impl fmt::Display for PathOwner<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathOwner::Repo { category, name } => write!(f, "repo {category} {name}"),
+            PathOwner::Link { category, name } => write!(f, "link {category} {name}"),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -119,6 +743,8 @@ pub enum LinkError {
     FileExists(String, String),
     BrokenSymlinkExists(String, String),
     FailedCreatingLink(String, String),
+    ModifiedCopy(String, String),
+    TemplateError(String, String),
     IoError(std::io::Error),
 }
 
@@ -137,6 +763,10 @@ impl std::fmt::Display for LinkError {
                 write!(f, "Linking {tx} -> {rx} failed: broken symlink")
             }
             LinkError::FailedCreatingLink(tx, rx) => write!(f, "Linking {tx} -> {rx} failed"),
+            LinkError::TemplateError(tx, err) => write!(f, "Rendering {tx} failed: {err}"),
+            LinkError::ModifiedCopy(tx, rx) => {
+                write!(f, "Copying {tx} -> {rx} failed: copy was modified locally")
+            }
             LinkError::IoError(err) => write!(f, "IO Error: {err}"),
         }
     }
@@ -180,12 +810,86 @@ fn handle_file_exists(selff: &Link, tx_path: &Path, rx_path: &Path) -> Result<bo
 
 impl Link {
     /// Creates the link from the link struct
-    pub fn link(&self) -> Result<bool, LinkError> {
+    ///
+    /// Whatever is in the way of the link is handled by the conflict strategy
+    /// of the link, see `Link::conflict_strategy`.
+    pub fn link(&self) -> Result<Linked, LinkError> {
+        self.link_with(&HashMap::new(), false)
+    }
+    /// Returns what to do when something is in the way of the link
+    ///
+    /// This is on_conflict if set, otherwise backup if force is set, like
+    /// by `Settings::force`, and fail if it isn't.
+    pub fn conflict_strategy(&self, force: bool) -> ConflictStrategy {
+        self.on_conflict.unwrap_or(if force {
+            ConflictStrategy::Backup
+        } else {
+            ConflictStrategy::Fail
+        })
+    }
+    /// Applies the conflict strategy to conflict, calling create to link once
+    /// what was in the way is gone
+    pub(crate) fn resolve_conflict<F>(
+        &self,
+        conflict: LinkError,
+        force: bool,
+        create: F,
+    ) -> Result<Linked, LinkError>
+    where
+        F: FnOnce() -> Result<(), LinkError>,
+    {
+        let strategy = self.conflict_strategy(force);
+        let rx = Path::new(&self.rx);
+        match strategy {
+            ConflictStrategy::Fail => return Err(conflict),
+            ConflictStrategy::Skip => {
+                info!("{}: {conflict}, skipping", self.name);
+                return Ok(Linked::Resolved(strategy));
+            }
+            ConflictStrategy::Backup => {
+                let trashed = Trash::default().put(rx)?;
+                info!("moved {} to trash", trashed.original);
+            }
+            ConflictStrategy::Replace if rx.is_dir() && !rx.is_symlink() => {
+                fs::remove_dir_all(rx)?;
+            }
+            ConflictStrategy::Replace => fs::remove_file(rx)?,
+        }
+        create()?;
+        Ok(Linked::Resolved(strategy))
+    }
+    /// Like `link`, rendering templates with vars, see
+    /// `Config::template_vars`, and resolving conflicts as with force, see
+    /// `Link::conflict_strategy`
+    pub fn link_with(
+        &self,
+        vars: &HashMap<String, String>,
+        force: bool,
+    ) -> Result<Linked, LinkError> {
+        if !self.system && !absolute_path(Path::new(&self.rx)).starts_with(home_dir()) {
+            warn!(
+                "{} is outside the home dir, consider marking {} as a system link",
+                self.rx, self.name
+            );
+        }
+        if !self.kind.is_symlink() {
+            return copy::link(self, vars, force);
+        }
+        match self.try_link() {
+            Err(
+                conflict @ (LinkError::DifferentLink(_, _)
+                | LinkError::FileExists(_, _)
+                | LinkError::BrokenSymlinkExists(_, _)),
+            ) => self.resolve_conflict(conflict, force, || Ok(symlink(&self.tx, &self.rx)?)),
+            result => result.map(|_| Linked::Created),
+        }
+    }
+    fn try_link(&self) -> Result<bool, LinkError> {
         let tx_path: &Path = std::path::Path::new(&self.tx);
         let rx_path: &Path = std::path::Path::new(&self.rx);
         match rx_path.try_exists() {
             Ok(true) => handle_file_exists(self, tx_path, rx_path),
-            Ok(false) if rx_path.is_symlink() => Err(LinkError::FileExists(
+            Ok(false) if rx_path.is_symlink() => Err(LinkError::BrokenSymlinkExists(
                 tx_path.to_string_lossy().to_string(),
                 rx_path.to_string_lossy().to_string(),
             )),
@@ -199,36 +903,307 @@ impl Link {
             )),
         }
     }
+    /// Whether rx is a symlink to tx, or for copies, unchanged since seidr
+    /// wrote it
+    pub fn is_linked(&self) -> bool {
+        if !self.kind.is_symlink() {
+            return copy::is_written(self);
+        }
+        match self.target() {
+            Some(target) => match (canonicalize(target), canonicalize(&self.tx)) {
+                (Ok(target), Ok(tx)) => target == tx,
+                _ => false,
+            },
+            None => false,
+        }
+    }
+    /// Returns the target of rx, relative to the dir of rx, if it is a symlink
+    pub fn target(&self) -> Option<PathBuf> {
+        let rx = Path::new(&self.rx);
+        let target = rx.read_link().ok()?;
+        Some(
+            rx.parent()
+                .map_or(target.clone(), |parent| parent.join(target)),
+        )
+    }
+    /// Returns what is at rx, where tx_roots are the tx of every link, see
+    /// `Config::tx_roots`
+    pub fn status(&self, tx_roots: &[PathBuf]) -> LinkStatus {
+        let rx = Path::new(&self.rx);
+        if self.is_linked() {
+            return LinkStatus::Linked;
+        }
+        if !rx.exists() && !rx.is_symlink() {
+            return LinkStatus::Missing;
+        }
+        let Some(target) = self.target().filter(|_| self.kind.is_symlink()) else {
+            return LinkStatus::Occupied;
+        };
+        let target = absolute_path(&target);
+        if !rx.exists() {
+            LinkStatus::Broken
+        } else if tx_roots.iter().any(|root| target.starts_with(root)) {
+            LinkStatus::Elsewhere
+        } else {
+            LinkStatus::Foreign
+        }
+    }
+    /// Removes the link, if rx is a symlink to tx, or an unchanged copy
+    ///
+    /// Anything else at rx is left alone. Returns whether the link was removed.
+    pub fn unlink(&self) -> Result<bool, LinkError> {
+        if !self.is_linked() {
+            return Ok(false);
+        }
+        fs::remove_file(&self.rx)?;
+        Ok(true)
+    }
 }
 
 impl Repo {
+    /// Returns the name of the directory the repo is checked out to
+    ///
+    /// This is dir if set, otherwise name, otherwise the last segment of url.
+    pub fn dir_name(&self) -> String {
+        self.dir
+            .clone()
+            .or_else(|| self.name.clone())
+            .or_else(|| {
+                let url = GitUrl::parse(self.url.as_ref()?).ok()?;
+                url.repo_name().map(str::to_string)
+            })
+            .unwrap_or_else(|| panic!("repo has no dir, name or url: {:?}", &self))
+    }
+    /// Returns the directory the repo is checked out to
+    ///
+    /// All paths into the checkout should be built from this.
+    pub fn checkout_dir(&self) -> String {
+        format!("{}{}", self.path.as_ref().unwrap(), self.dir_name())
+    }
+    /// Checks that the checkout dir is the top level of a git repository
+    ///
+    /// If it isn't, say because path is misconfigured, git would happily
+    /// operate on whatever repository the checkout dir is inside of, like one
+    /// tracking the whole home directory.
+    pub fn verify_toplevel(&self) -> Result<(), String> {
+        let dir = self.checkout_dir();
+        let expected =
+            canonicalize(&dir).map_err(|err| format!("{dir} can't be resolved: {err}"))?;
+        let output = Command::new("git")
+            .current_dir(&dir)
+            .arg("rev-parse")
+            .arg("--show-toplevel")
+            .exec_output()
+            .map_err(|err| format!("git failed to run: {err}"))?;
+        if !output.status.success() {
+            return Err(format!("{dir} is not in a git repository"));
+        }
+        let toplevel = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        match canonicalize(&toplevel) {
+            Ok(toplevel) if toplevel == expected => Ok(()),
+            _ => Err(format!(
+                "{dir} is inside the git repository at {toplevel}, not its own"
+            )),
+        }
+    }
+    /// Like `verify_toplevel`, but logs the error, for guarding operations
+    fn guard_toplevel(&self) -> bool {
+        match self.verify_toplevel() {
+            Ok(()) => true,
+            Err(err) => {
+                error!("refusing to operate on {}: {err}", self.display_name());
+                false
+            }
+        }
+    }
+    /// Whether runs of op leave the repo alone, as it is archived, or an
+    /// extracted archive, which can only be cloned and pulled
+    ///
+    /// Archived repos are still cloned, so they can be jumped to.
+    pub fn skips(&self, op: &str) -> bool {
+        (self.archived && op != "clone") || (self.extract && !["clone", "pull"].contains(&op))
+    }
+    /// Returns the name to show the user, falling back to the directory name
+    pub fn display_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.dir_name())
+    }
     /// Clones the repository to its specified folder.
+    #[allow(clippy::should_implement_trait)]
     pub fn clone(&self) -> bool {
+        self.clone_url(self.url.as_ref().unwrap())
+    }
+    /// Clones the repository from url, rather than its own url, see
+    /// `Config::remote_url`.
+    pub fn clone_url(&self, url: &str) -> bool {
         if self
             .flags
             .as_ref()
             .expect("failed to unwrap flags")
             .contains(&RepoFlags::Clone)
         {
-            // TODO: check if &self.name.as_ref() already exists in dir
+            if self.extract {
+                return archive::sync(self, url);
+            }
+            if !self.sync_mirror() {
+                return false;
+            }
+            let branch = self.branch.clone().or_else(|| {
+                self.detect_default_branch_at(url)
+                    .map_err(|err| warn!("{}: {err}", self.display_name()))
+                    .ok()
+            });
+            // TODO: check if the checkout dir already exists
             let output = Command::new("git")
                 .current_dir(self.path.as_ref().unwrap())
                 .arg("clone")
-                .arg(self.url.as_ref().unwrap())
-                .arg(self.name.as_ref().unwrap())
-                .output()
+                .args(branch.iter().flat_map(|branch| ["--branch", branch]))
+                .args(
+                    self.shallow_since
+                        .iter()
+                        .map(|since| format!("--shallow-since={since}")),
+                )
+                .args(
+                    self.filter
+                        .iter()
+                        .map(|filter| format!("--filter={filter}")),
+                )
+                .arg(url)
+                .arg(self.dir_name())
+                .envs(self.remote_env())
+                .exec_output()
                 .unwrap_or_else(|_| panic!("git repo failed to clone: {:?}", &self,));
-            output.status.success()
+            if !output.status.success() {
+                return false;
+            }
+            // Fetch from the mirror, or the pull remote, but push straight to
+            // upstream, or the push remote
+            let pushes = match self.push_remote.as_ref().or(self.mirror_of.as_ref()) {
+                Some(push_url) => self.set_push_url(push_url),
+                None => true,
+            };
+            pushes && self.apply_git_config()
         } else {
-            info!(
-                "{} has clone set to false, not cloned",
-                &self.name.as_ref().unwrap()
-            );
+            info!("{} has clone set to false, not cloned", self.display_name());
             false
         }
     }
+    /// Asks the remote which branch its HEAD points to, without cloning
+    pub fn detect_remote_default_branch(&self) -> Result<String, String> {
+        self.detect_default_branch_at(self.url.as_deref().ok_or("no url")?)
+    }
+    fn detect_default_branch_at(&self, url: &str) -> Result<String, String> {
+        let output = Command::new("git")
+            .args(["ls-remote", "--symref", url, "HEAD"])
+            .envs(self.remote_env())
+            .exec_output()
+            .map_err(|err| format!("git failed to run: {err}"))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        parse_symref(&String::from_utf8_lossy(&output.stdout))
+            .ok_or_else(|| format!("{url} has no default branch"))
+    }
+    /// Returns the url of origin in the checkout
+    pub fn origin_url(&self) -> Option<String> {
+        self.git_stdout(&["remote", "get-url", "origin"]).ok()
+    }
+    /// Sets the url of origin in the checkout
+    pub fn set_origin_url(&self, url: &str) -> bool {
+        self.git_stdout(&["remote", "set-url", "origin", url])
+            .is_ok()
+    }
+    /// Returns the url origin pushes to in the checkout, which is its url
+    /// unless a push url is set
+    pub fn push_url(&self) -> Option<String> {
+        self.git_stdout(&["remote", "get-url", "--push", "origin"])
+            .ok()
+    }
+    /// Sets the url origin pushes to in the checkout
+    pub fn set_push_url(&self, url: &str) -> bool {
+        self.git_stdout(&["remote", "set-url", "--push", "origin", url])
+            .is_ok()
+    }
+    /// Returns the default branch of the remote, as git recorded it when
+    /// cloning
+    pub fn remote_head(&self) -> Option<String> {
+        let head = self
+            .git_stdout(&[
+                "symbolic-ref",
+                "--quiet",
+                "--short",
+                "refs/remotes/origin/HEAD",
+            ])
+            .ok()?;
+        Some(head.strip_prefix("origin/").unwrap_or(&head).to_string())
+    }
+    /// Returns the environment for git commands that talk to the remote
+    ///
+    /// Unless the repo is interactive, git is told not to prompt, so a missing
+    /// credential fails the operation instead of hanging the run. With auth
+    /// set, git is given the token for https urls.
+    pub(crate) fn remote_env(&self) -> Vec<(String, String)> {
+        let mut env = vec![];
+        if !self.interactive {
+            env.push((String::from("GIT_TERMINAL_PROMPT"), String::from("0")));
+        }
+        if let Some(auth) = &self.auth {
+            match auth.git_env() {
+                Ok(auth_env) => env.extend(auth_env),
+                Err(err) => error!(
+                    "{}: failed to read {}: {err}",
+                    self.display_name(),
+                    auth.token
+                ),
+            }
+        }
+        env
+    }
+    /// Creates or updates the local mirror, if the repo has mirror_of set.
+    ///
+    /// The mirror is a bare repository at url, which must be local, mirroring
+    /// mirror_of. Clones and pulls go through the mirror, so other checkouts
+    /// of the same upstream, and offline use, share it.
+    pub fn sync_mirror(&self) -> bool {
+        let Some(upstream) = &self.mirror_of else {
+            return true;
+        };
+        let url = self.url.as_ref().unwrap();
+        let mirror = match GitUrl::parse(url) {
+            // Relative to path, like the url is when cloning
+            Ok(parsed) if parsed.is_local() => Path::new(self.path.as_ref().unwrap())
+                .join(url.strip_prefix("file://").unwrap_or(url)),
+            _ => {
+                error!(
+                    "{}: url must be local to be a mirror of {upstream}",
+                    self.display_name()
+                );
+                return false;
+            }
+        };
+        let mut command = Command::new("git");
+        command.args(self.fetch_config());
+        if mirror.exists() {
+            command.arg("-C").arg(&mirror).arg("fetch").arg("--prune");
+        } else {
+            info!("creating mirror of {upstream} in {}", mirror.display());
+            command
+                .arg("clone")
+                .arg("--mirror")
+                .arg(upstream)
+                .arg(&mirror);
+        }
+        command
+            .envs(self.remote_env())
+            .exec_output()
+            .map_or(false, |output| output.status.success())
+    }
     /// Pulls the repository if able.
     pub fn pull(&self) -> bool {
+        self.pull_from(None)
+    }
+    /// Pulls the repository if able, and when on branch, pulls branch from
+    /// origin, whatever the branch tracks.
+    pub fn pull_from(&self, branch: Option<&str>) -> bool {
         if self
             .flags
             .as_ref()
@@ -236,24 +1211,91 @@ impl Repo {
             .iter()
             .any(|s| s == &RepoFlags::Pull || s == &RepoFlags::Fast)
         {
+            if self.extract {
+                return self
+                    .url
+                    .as_ref()
+                    .map_or(false, |url| archive::sync(self, url));
+            }
+            if !self.guard_toplevel() || !self.sync_mirror() {
+                return false;
+            }
+            let current = self.git_stdout(&["branch", "--show-current"]).ok();
             let output = Command::new("git")
-                .current_dir(format!(
-                    "{}{}",
-                    &self.path.as_ref().unwrap(),
-                    &self.name.as_ref().unwrap()
-                ))
+                .current_dir(self.checkout_dir())
+                .args(self.fetch_config())
                 .arg("pull")
-                .output()
+                .args(
+                    branch
+                        .filter(|branch| current.as_deref() == Some(*branch))
+                        .map(|branch| ["origin", branch])
+                        .iter()
+                        .flatten(),
+                )
+                .envs(self.remote_env())
+                .exec_output()
                 .unwrap_or_else(|_| panic!("git repo failed to pull: {:?}", &self,));
             output.status.success()
         } else {
-            info!(
-                "{} has clone set to false, not pulled",
-                &self.name.as_ref().unwrap()
-            );
+            info!("{} has clone set to false, not pulled", self.display_name());
             false
         }
     }
+    /// Returns the config git fetches with, pruning refs and tags gone from
+    /// origin if fetch_prune is set
+    ///
+    /// Every fetch, including the one of pull, goes with it, and it has to
+    /// come before the git subcommand.
+    fn fetch_config(&self) -> Vec<&'static str> {
+        if self.fetch_prune {
+            vec!["-c", "fetch.prune=true", "-c", "fetch.pruneTags=true"]
+        } else {
+            vec![]
+        }
+    }
+    /// Returns the remote-tracking refs of origin, like `origin/topic`, whose
+    /// branches are gone from origin, which is asked for its branches
+    pub fn stale_remote_refs(&self) -> Result<Vec<String>, String> {
+        if self.status() == RepoStatus::Missing {
+            return Err(String::from("not cloned"));
+        }
+        let output = Command::new("git")
+            .current_dir(self.checkout_dir())
+            .args(["ls-remote", "--heads", "origin"])
+            .envs(self.remote_env())
+            .exec_output()
+            .map_err(|err| format!("git failed to run: {err}"))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        let heads = String::from_utf8_lossy(&output.stdout).to_string();
+        let heads: Vec<&str> = heads
+            .lines()
+            .filter_map(|line| line.split_once("\trefs/heads/"))
+            .map(|(_, branch)| branch)
+            .collect();
+        Ok(self
+            .remote_refs()?
+            .into_iter()
+            .filter(|tracking| {
+                tracking
+                    .strip_prefix("origin/")
+                    .map_or(false, |branch| branch != "HEAD" && !heads.contains(&branch))
+            })
+            .collect())
+    }
+    /// Returns the remote-tracking refs of origin, like `origin/main`
+    fn remote_refs(&self) -> Result<Vec<String>, String> {
+        Ok(self
+            .git_stdout(&[
+                "for-each-ref",
+                "--format=%(refname:short)",
+                "refs/remotes/origin/",
+            ])?
+            .lines()
+            .map(str::to_string)
+            .collect())
+    }
     /// Adds all files in the repository.
     pub fn add_all(&self) -> bool {
         if self
@@ -263,25 +1305,77 @@ impl Repo {
             .iter()
             .any(|s| s == &RepoFlags::Add || s == &RepoFlags::Quick || s == &RepoFlags::Fast)
         {
+            if !self.guard_toplevel() {
+                return false;
+            }
             let output = Command::new("git")
-                .current_dir(format!(
-                    "{}{}",
-                    &self.path.as_ref().unwrap(),
-                    &self.name.as_ref().unwrap()
-                ))
+                .current_dir(self.checkout_dir())
                 .arg("add")
-                .arg(".")
-                .output()
+                .arg("--")
+                .args(self.add_pathspecs())
+                .exec_output()
                 .unwrap_or_else(|_| panic!("git repo failed to add: {:?}", &self,));
             output.status.success()
         } else {
-            info!(
-                "{} has clone set to false, not cloned",
-                &self.name.as_ref().unwrap()
-            );
+            info!("{} has clone set to false, not cloned", self.display_name());
             false
         }
     }
+    /// Returns the pathspecs the add step stages, everything in the checkout
+    /// unless add_paths is set, less exclude_paths
+    pub fn add_pathspecs(&self) -> Vec<String> {
+        let mut pathspecs = self
+            .add_paths
+            .clone()
+            .unwrap_or_else(|| vec![String::from(".")]);
+        pathspecs.extend(
+            self.exclude_paths
+                .iter()
+                .flatten()
+                .map(|path| format!(":(exclude){path}")),
+        );
+        pathspecs
+    }
+    /// Checks what committing paths would add for secrets, if the repo is to
+    /// be scanned, logging where any were found
+    ///
+    /// With paths empty, the staged changes are checked.
+    fn guard_secrets(&self, paths: &[&str]) -> bool {
+        if !self.scan_secrets {
+            return true;
+        }
+        let mut args = vec!["diff", "--unified=0", "--no-color", "--no-ext-diff"];
+        if paths.is_empty() {
+            args.push("--cached");
+        } else {
+            args.extend(["HEAD", "--"]);
+            args.extend(paths);
+        }
+        let findings = match self.git_stdout(&args) {
+            Ok(diff) => leaks::scan_diff(&diff),
+            Err(err) => {
+                error!("{}: failed to scan for secrets: {err}", self.display_name());
+                return false;
+            }
+        };
+        for finding in findings.iter() {
+            error!("{}: {finding}", self.display_name());
+        }
+        if !findings.is_empty() {
+            error!(
+                "{}: not committing, as it looks like secrets would be, mark lines that are fine with \"{}\"",
+                self.display_name(),
+                leaks::ALLOW_MARKER
+            );
+        }
+        findings.is_empty()
+    }
+    /// Returns the environment commits should be made with
+    fn commit_env(&self) -> Vec<(&'static str, String)> {
+        self.commit_date
+            .as_ref()
+            .map_or(vec![], CommitDateConfig::env)
+    }
     /// Tries to commit changes in the repository.
     ///
     /// # Development
@@ -291,6 +1385,11 @@ impl Repo {
     /// easy
     #[allow(dead_code)]
     pub fn commit(&self) -> bool {
+        self.try_commit().unwrap_or(false)
+    }
+    /// Tries to commit changes in the editor, like `Repo::commit`, returning
+    /// None if the editor was open longer than commit_timeout allows.
+    pub fn try_commit(&self) -> Option<bool> {
         if self
             .flags
             .as_ref()
@@ -298,26 +1397,39 @@ impl Repo {
             .iter()
             .any(|s| s == &RepoFlags::Commit || s == &RepoFlags::Quick || s == &RepoFlags::Fast)
         {
-            let status = Command::new("git")
-                .current_dir(format!(
-                    "{}{}",
-                    &self.path.as_ref().unwrap(),
-                    &self.name.as_ref().unwrap()
-                ))
+            if !self.guard_toplevel() || !self.guard_secrets(&[]) {
+                return Some(false);
+            }
+            let mut command = Command::new("git");
+            command
+                .current_dir(self.checkout_dir())
                 .arg("commit")
-                .status()
-                .unwrap_or_else(|_| panic!("git repo failed to commit: {:?}", &self,));
-            status.success()
+                .envs(self.commit_env());
+            let status = match self.commit_timeout {
+                Some(timeout) => command.exec_status_timeout(Duration::from_secs(timeout.seconds)),
+                None => command.exec_status().map(Some),
+            }
+            .unwrap_or_else(|_| panic!("git repo failed to commit: {:?}", &self,));
+            status.map(|status| status.success())
         } else {
-            info!(
-                "{} has push set to false, not cloned",
-                &self.name.as_ref().unwrap()
-            );
-            false
+            info!("{} has push set to false, not cloned", self.display_name());
+            Some(false)
         }
     }
     /// Tries to commit changes with a message argument.
     pub fn commit_with_msg(&self, msg: &str) -> bool {
+        self.commit_with_trailers(msg, &[])
+    }
+    /// Tries to commit changes with a message argument, and trailers.
+    ///
+    /// A bare `Signed-off-by` trailer signs off as the committer, like
+    /// `git commit --signoff`.
+    pub fn commit_with_trailers(&self, msg: &str, trailers: &[String]) -> bool {
+        self.commit_paths(msg, trailers, &[])
+    }
+    /// Like `commit_with_trailers`, but only commits paths, as they are in
+    /// the working tree, unless paths is empty.
+    fn commit_paths(&self, msg: &str, trailers: &[String], paths: &[&str]) -> bool {
         if self
             .flags
             .as_ref()
@@ -325,28 +1437,87 @@ impl Repo {
             .iter()
             .any(|s| s == &RepoFlags::Commit || s == &RepoFlags::Quick || s == &RepoFlags::Fast)
         {
+            if !self.guard_toplevel() || !self.guard_secrets(paths) {
+                return false;
+            }
             let output = Command::new("git")
-                .current_dir(format!(
-                    "{}{}",
-                    &self.path.as_ref().unwrap(),
-                    &self.name.as_ref().unwrap()
-                ))
+                .current_dir(self.checkout_dir())
                 .arg("commit")
                 .arg("-m")
                 .arg(msg)
-                .output()
+                .args(trailers.iter().flat_map(|trailer| {
+                    if trailer == "Signed-off-by" {
+                        vec!["--signoff"]
+                    } else {
+                        vec!["--trailer", trailer]
+                    }
+                }))
+                .args(if paths.is_empty() { vec![] } else { vec!["--"] })
+                .args(paths)
+                .envs(self.commit_env())
+                .exec_output()
                 .unwrap_or_else(|_| panic!("git repo failed to commit: {:?}", &self,));
             output.status.success()
         } else {
-            info!(
-                "{} has clone set to false, not cloned",
-                &self.name.as_ref().unwrap()
-            );
+            info!("{} has clone set to false, not cloned", self.display_name());
             false
         }
     }
+    /// Commits the staged changes with a commit per top-level directory they
+    /// are in, with msg, where `{dir}` is the directory.
+    ///
+    /// Files at the top level are committed on their own, with the file as
+    /// `{dir}`. Returns whether every commit succeeded.
+    pub fn commit_split(&self, msg: &str, trailers: &[String]) -> bool {
+        self.commit_split_with(msg, |_| trailers.to_vec())
+    }
+    /// Like `commit_split`, with the trailers of each commit from trailers,
+    /// given the paths it commits
+    pub fn commit_split_with<F>(&self, msg: &str, trailers: F) -> bool
+    where
+        F: Fn(&[&str]) -> Vec<String>,
+    {
+        if !self.guard_toplevel() {
+            return false;
+        }
+        let staged = match self.staged_paths() {
+            Ok(staged) => staged,
+            Err(err) => {
+                error!("{}: {err}", self.display_name());
+                return false;
+            }
+        };
+        let mut dirs: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for path in &staged {
+            let dir = path.split('/').next().unwrap_or(path);
+            dirs.entry(dir).or_default().push(path);
+        }
+        if dirs.is_empty() {
+            info!("{}: {}", self.display_name(), tr(Msg::NothingToCommit));
+            return false;
+        }
+        dirs.iter().fold(true, |succeeded, (dir, paths)| {
+            self.commit_paths(&fill(msg, &[("dir", dir)]), &trailers(paths), paths) && succeeded
+        })
+    }
+    /// Returns the staged paths, relative to the checkout
+    pub fn staged_paths(&self) -> Result<Vec<String>, String> {
+        Ok(self
+            .git_stdout(&["diff", "--cached", "--name-only", "--no-renames", "-z"])?
+            .split('\0')
+            .filter(|path| !path.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
     /// Attempts to push the repository.
     pub fn push(&self) -> bool {
+        self.push_with(None, false)
+    }
+    /// Attempts to push the repository, overriding the branches to push, and
+    /// pushing tags if either tags or the repos push config says so.
+    ///
+    /// Tags are pushed to origin in the same push as the branches.
+    pub fn push_with(&self, branches: Option<PushBranches>, tags: bool) -> bool {
         if self
             .flags
             .as_ref()
@@ -354,24 +1525,655 @@ impl Repo {
             .iter()
             .any(|s| s == &RepoFlags::Push || s == &RepoFlags::Quick || s == &RepoFlags::Fast)
         {
+            if !self.guard_toplevel() {
+                return false;
+            }
+            let config = self.push.as_ref();
+            let branches = branches.unwrap_or(config.map_or(PushBranches::Current, |c| c.branches));
+            let tags = tags || config.map_or(false, |c| c.tags);
+            let mut command = Command::new("git");
+            command.current_dir(self.checkout_dir());
+            if branches == PushBranches::Matching && !tags {
+                command.args(["-c", "push.default=matching"]);
+            }
+            command.arg("push");
+            let upstream = match branches {
+                PushBranches::Current => self.missing_upstream(),
+                _ => None,
+            };
+            // NOTE: git refuses to combine --all and --tags, so with tags the
+            // branches are pushed by refspec instead, in the same push, which
+            // has to name the remote, and so pushes the current branch to the
+            // branch of the same name
+            match (branches, &upstream) {
+                (PushBranches::Current, Some(branch)) => {
+                    command.args(["--set-upstream", "origin", branch]);
+                }
+                (PushBranches::Current, None) if tags => {
+                    command.args(["origin", "HEAD"]);
+                }
+                (PushBranches::Current, None) => (),
+                (PushBranches::All, _) if tags => {
+                    command.args(["origin", "refs/heads/*:refs/heads/*"]);
+                }
+                (PushBranches::All, _) => {
+                    command.arg("--all");
+                }
+                (PushBranches::Matching, _) if tags => {
+                    command.args(["origin", ":"]);
+                }
+                (PushBranches::Matching, _) => (),
+            };
+            if tags {
+                command.arg("refs/tags/*:refs/tags/*");
+            }
+            command
+                .envs(self.remote_env())
+                .exec_output()
+                .unwrap_or_else(|_| panic!("git repo failed to push: {:?}", &self,))
+                .status
+                .success()
+        } else {
+            info!("{} has clone set to false, not cloned", self.display_name());
+            false
+        }
+    }
+    /// Whether quick and fast push to the review branch of this host
+    pub fn reviews(&self) -> bool {
+        self.push.as_ref().map_or(false, |push| push.review)
+    }
+    /// Pushes what quick and fast committed, to the review branch of this
+    /// host if the repo reviews, see `review_branch`, otherwise like `push`
+    pub fn push_quick(&self) -> bool {
+        if !self.reviews() {
+            return self.push();
+        }
+        let flags = self.flags.as_ref().expect("failed to unwrap flags");
+        if !flags
+            .iter()
+            .any(|s| s == &RepoFlags::Push || s == &RepoFlags::Quick || s == &RepoFlags::Fast)
+        {
+            info!("{} has push set to false, not pushed", self.display_name());
+            return false;
+        }
+        if !self.guard_toplevel() {
+            return false;
+        }
+        Command::new("git")
+            .current_dir(self.checkout_dir())
+            .arg("push")
+            .arg("origin")
+            .arg(format!("HEAD:refs/heads/{}", review_branch()))
+            .envs(self.remote_env())
+            .exec_output()
+            .map_or(false, |output| output.status.success())
+    }
+    /// Fetches origin, and fast-forwards the branch checked out to the review
+    /// branches on it that it doesn't have yet, returning those
+    ///
+    /// Stops at the first review branch that can't be fast-forwarded to, as it
+    /// has to be merged by hand.
+    pub fn merge_reviews(&self) -> Result<Vec<String>, String> {
+        let fetched = Command::new("git")
+            .current_dir(self.checkout_dir())
+            .args(self.fetch_config())
+            .args(["fetch", "--quiet", "origin"])
+            .envs(self.remote_env())
+            .exec_output()
+            .map_or(false, |output| output.status.success());
+        if !fetched {
+            return Err(String::from("failed to fetch origin"));
+        }
+        let refs = format!("refs/remotes/origin/{REVIEW_PREFIX}");
+        let branches = self.git_stdout(&["for-each-ref", "--format=%(refname:short)", &refs])?;
+        let mut merged = vec![];
+        for branch in branches.lines() {
+            if self
+                .git_stdout(&["merge-base", "--is-ancestor", branch, "HEAD"])
+                .is_ok()
+            {
+                continue;
+            }
+            self.git_stdout(&["merge", "--ff-only", "--quiet", branch])
+                .map_err(|err| format!("{branch} can't be fast-forwarded to: {err}"))?;
+            merged.push(branch.to_string());
+        }
+        Ok(merged)
+    }
+    /// Shows what committing all changes would commit, as `git status
+    /// --short` followed by a diffstat.
+    ///
+    /// Returns None if the repo isn't cloned, or git fails, and an empty
+    /// string if there is nothing to commit.
+    pub fn commit_preview(&self) -> Option<String> {
+        if self.status() == RepoStatus::Missing || !self.guard_toplevel() {
+            return None;
+        }
+        let status = Command::new("git")
+            .current_dir(self.checkout_dir())
+            .arg("status")
+            .arg("--short")
+            .arg("--")
+            .args(self.add_pathspecs())
+            .exec_output()
+            .ok()?;
+        if !status.status.success() {
+            return None;
+        }
+        let mut preview = String::from_utf8_lossy(&status.stdout).to_string();
+        if preview.is_empty() {
+            return Some(preview);
+        }
+        // Without a commit yet there is no HEAD to diff against
+        let diffstat = Command::new("git")
+            .current_dir(self.checkout_dir())
+            .arg("diff")
+            .arg("--stat")
+            .arg("HEAD")
+            .arg("--")
+            .args(self.add_pathspecs())
+            .exec_output()
+            .ok()
+            .filter(|output| output.status.success());
+        if let Some(diffstat) = diffstat {
+            preview.push_str(&String::from_utf8_lossy(&diffstat.stdout));
+        }
+        Some(preview)
+    }
+    /// Lists the untracked files and directories clean would remove.
+    ///
+    /// Returns None if the repo doesn't have the clean flag, or git fails.
+    pub fn clean_preview(&self) -> Option<Vec<String>> {
+        if !self
+            .flags
+            .as_ref()
+            .expect("failed to unwrap flags")
+            .contains(&RepoFlags::Clean)
+        {
+            info!(
+                "{} doesn't have the clean flag, not cleaned",
+                self.display_name()
+            );
+            return None;
+        }
+        if !self.guard_toplevel() {
+            return None;
+        }
+        // What `git clean -d` removes, untracked dirs as a whole, less the
+        // repos nested in them, which it leaves alone
+        let output = Command::new("git")
+            .current_dir(self.checkout_dir())
+            .args([
+                "ls-files",
+                "--others",
+                "--exclude-standard",
+                "--directory",
+                "-z",
+            ])
+            .exec_output()
+            .ok()?;
+        let checkout = PathBuf::from(self.checkout_dir());
+        output.status.success().then(|| {
+            String::from_utf8_lossy(&output.stdout)
+                .split('\0')
+                .filter(|path| !path.is_empty() && !checkout.join(path).join(".git").exists())
+                .map(str::to_string)
+                .collect()
+        })
+    }
+    /// Removes the untracked files and directories paths, as returned by
+    /// `Repo::clean_preview`, in the repository.
+    ///
+    /// This should only be called after showing the user the preview. Only
+    /// paths are removed, so what was created since isn't.
+    pub fn clean(&self, paths: &[String]) -> bool {
+        if self
+            .flags
+            .as_ref()
+            .expect("failed to unwrap flags")
+            .contains(&RepoFlags::Clean)
+        {
+            if !self.guard_toplevel() {
+                return false;
+            }
+            // Without paths, git would clean everything
+            if paths.is_empty() {
+                return true;
+            }
             let output = Command::new("git")
-                .current_dir(format!(
-                    "{}{}",
-                    &self.path.as_ref().unwrap(),
-                    &self.name.as_ref().unwrap()
-                ))
-                .arg("push")
-                .output()
-                .unwrap_or_else(|_| panic!("git repo failed to push: {:?}", &self,));
+                .current_dir(self.checkout_dir())
+                .args(["clean", "-fd", "--"])
+                .args(paths.iter().map(|path| format!(":(literal){path}")))
+                .exec_output()
+                .unwrap_or_else(|_| panic!("git repo failed to clean: {:?}", &self,));
             output.status.success()
         } else {
             info!(
-                "{} has clone set to false, not cloned",
-                &self.name.as_ref().unwrap()
+                "{} doesn't have the clean flag, not cleaned",
+                self.display_name()
             );
             false
         }
     }
+    /// Applies the patch at patch to the repository.
+    ///
+    /// Patches in mbox format, as made by git format-patch, are applied with
+    /// git am, so they are committed. Other patches are applied to the working
+    /// tree and index with git apply. Either way, a patch that doesn't apply
+    /// leaves the repository as it was, and the error is what git said.
+    pub fn apply_patch(&self, patch: &Path) -> Result<(), String> {
+        if self.status() == RepoStatus::Missing {
+            return Err(String::from("not cloned"));
+        }
+        self.verify_toplevel()?;
+        let patch = absolute_path(patch);
+        let mbox = fs::read_to_string(&patch)
+            .map_err(|err| format!("{}: {err}", patch.display()))?
+            .starts_with("From ");
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .current_dir(self.checkout_dir())
+                .args(args)
+                .arg(&patch)
+                .exec_output()
+                .map_err(|err| format!("git failed to run: {err}"))
+        };
+        let output = if mbox {
+            git(&["am", "--3way"])?
+        } else {
+            match git(&["apply", "--check"])? {
+                output if output.status.success() => git(&["apply", "--index"])?,
+                output => output,
+            }
+        };
+        if output.status.success() {
+            return Ok(());
+        }
+        if mbox {
+            Command::new("git")
+                .current_dir(self.checkout_dir())
+                .arg("am")
+                .arg("--abort")
+                .exec_output()
+                .map_err(|err| format!("git failed to run: {err}"))?;
+        }
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+    /// Runs git with args in the repository, returning its trimmed stdout, or
+    /// what git said if it failed
+    fn git_stdout(&self, args: &[&str]) -> Result<String, String> {
+        let output = Command::new("git")
+            .current_dir(self.checkout_dir())
+            .args(args)
+            .exec_output()
+            .map_err(|err| format!("git failed to run: {err}"))?;
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        } else {
+            Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+        }
+    }
+    /// Renames the local branch old to new.
+    ///
+    /// Returns whether there was a branch old to rename.
+    pub fn rename_branch(&self, old: &str, new: &str) -> Result<bool, String> {
+        if self.status() == RepoStatus::Missing {
+            return Err(String::from("not cloned"));
+        }
+        self.verify_toplevel()?;
+        let branch = format!("refs/heads/{old}");
+        if self
+            .git_stdout(&["rev-parse", "--verify", "--quiet", &branch])
+            .is_err()
+        {
+            return Ok(false);
+        }
+        self.git_stdout(&["branch", "-m", old, new])?;
+        Ok(true)
+    }
+    /// Removes remote-tracking refs whose branch is gone from the remote, and
+    /// with merged, also deletes local branches merged into the default branch
+    /// of the remote.
+    ///
+    /// Tags gone from the remote, and tags that only exist locally, are
+    /// removed too if fetch_prune is set. The current branch, and the default
+    /// branch itself, are never deleted. Returns the removed remote-tracking
+    /// refs, then the deleted local branches.
+    pub fn prune_branches(
+        &self,
+        merged: bool,
+        default: Option<&str>,
+    ) -> Result<Vec<String>, String> {
+        if self.status() == RepoStatus::Missing {
+            return Err(String::from("not cloned"));
+        }
+        self.verify_toplevel()?;
+        let before = self.remote_refs()?;
+        let mut fetch = vec!["fetch", "--prune", "--quiet"];
+        if self.fetch_prune {
+            fetch.push("--prune-tags");
+        }
+        self.git_stdout(&fetch)?;
+        let after = self.remote_refs()?;
+        let mut deleted: Vec<String> = before
+            .into_iter()
+            .filter(|tracking| !after.contains(tracking))
+            .collect();
+        if !merged {
+            return Ok(deleted);
+        }
+        // Without a known default branch, merged means merged into HEAD
+        let default = default.map(str::to_string).or_else(|| self.remote_head());
+        let base = default
+            .as_ref()
+            .map_or(String::from("HEAD"), |default| format!("origin/{default}"));
+        let default = default.unwrap_or_else(|| String::from("HEAD"));
+        let current = self.git_stdout(&["branch", "--show-current"])?;
+        let branches = self.git_stdout(&[
+            "for-each-ref",
+            "--merged",
+            &base,
+            "--format=%(refname:short)",
+            "refs/heads/",
+        ])?;
+        for branch in branches
+            .lines()
+            .filter(|branch| *branch != current && *branch != default)
+        {
+            // Merged was just checked, against base rather than HEAD, which
+            // is what -d would check against
+            self.git_stdout(&["branch", "-D", branch])?;
+            deleted.push(branch.to_string());
+        }
+        Ok(deleted)
+    }
+    /// Returns the commit times of commits on local branches that aren't on
+    /// any remote, newest first
+    pub fn unpushed_commit_times(&self) -> Result<Vec<u64>, String> {
+        Ok(self
+            .git_stdout(&["log", "--branches", "--not", "--remotes", "--format=%ct"])?
+            .lines()
+            .filter_map(|time| time.parse().ok())
+            .collect())
+    }
+    /// Returns the commit HEAD is on
+    pub fn head_commit(&self) -> Option<String> {
+        self.git_stdout(&["rev-parse", "--verify", "--quiet", "HEAD"])
+            .ok()
+    }
+    /// Whether the branch checked out has an upstream
+    pub fn has_upstream(&self) -> bool {
+        self.git_stdout(&["rev-parse", "--abbrev-ref", "--symbolic-full-name", "@{u}"])
+            .is_ok()
+    }
+    /// Returns the branch checked out, if the push config says to set its
+    /// upstream, and it has none
+    pub fn missing_upstream(&self) -> Option<String> {
+        if !self.push.as_ref().map_or(false, |push| push.set_upstream) {
+            return None;
+        }
+        self.head_branch().filter(|_| !self.has_upstream())
+    }
+    /// Returns the branch checked out, None if HEAD is detached
+    pub fn head_branch(&self) -> Option<String> {
+        self.git_stdout(&["symbolic-ref", "--quiet", "--short", "HEAD"])
+            .ok()
+    }
+    /// Returns how many commits only HEAD has, which no branch, tag or remote
+    /// branch contains
+    pub fn unreferenced_commits(&self) -> Result<usize, String> {
+        self.git_stdout(&[
+            "rev-list",
+            "--count",
+            "HEAD",
+            "--not",
+            "--branches",
+            "--tags",
+            "--remotes",
+        ])?
+        .parse()
+        .map_err(|err| format!("git rev-list: {err}"))
+    }
+    /// Checks out branch in a checkout with a detached HEAD
+    ///
+    /// Refuses when HEAD has commits nothing else has, as checking out would
+    /// leave them to be garbage collected. A checkout on a branch is left
+    /// alone.
+    pub fn reattach_head(&self, branch: &str) -> Result<(), String> {
+        if self.head_branch().is_some() {
+            return Ok(());
+        }
+        match self.unreferenced_commits()? {
+            0 => self
+                .git_stdout(&["checkout", "--quiet", branch])
+                .map(|_| ()),
+            count => Err(format!(
+                "HEAD has {count} commits no branch has, branch them off first"
+            )),
+        }
+    }
+    /// Whether the checkout is a shallow clone, missing older history
+    pub fn is_shallow(&self) -> bool {
+        self.git_stdout(&["rev-parse", "--is-shallow-repository"])
+            .map_or(false, |shallow| shallow == "true")
+    }
+    /// Whether the checkout is a partial clone, fetching objects when needed
+    pub fn is_partial(&self) -> bool {
+        self.git_stdout(&["config", "--get", "extensions.partialclone"])
+            .map_or(false, |remote| !remote.is_empty())
+    }
+    /// Returns how many objects the checkout has, loose and packed
+    pub fn object_count(&self) -> Result<u64, String> {
+        Ok(self
+            .git_stdout(&["count-objects", "-v"])?
+            .lines()
+            .filter_map(|line| line.split_once(": "))
+            .filter(|(key, _)| matches!(*key, "count" | "in-pack"))
+            .filter_map(|(_, count)| count.parse::<u64>().ok())
+            .sum())
+    }
+    /// Returns the bytes on disk taken by the objects rev-list finds with
+    /// args, like `["--all"]`
+    pub fn disk_usage(&self, args: &[&str]) -> Result<u64, String> {
+        let mut rev_list = vec!["rev-list", "--disk-usage", "--objects"];
+        rev_list.extend(args);
+        self.git_stdout(&rev_list)?
+            .parse()
+            .map_err(|err| format!("failed to read disk usage: {err}"))
+    }
+    /// Returns the blobs anywhere in history taking at least min bytes on
+    /// disk, as a path they are at and the bytes, largest first
+    pub fn large_blobs(&self, min: u64) -> Result<Vec<(String, u64)>, String> {
+        let objects = self.git_stdout(&[
+            "cat-file",
+            "--batch-all-objects",
+            "--batch-check=%(objecttype) %(objectsize:disk) %(objectname)",
+        ])?;
+        let large: HashMap<&str, u64> = objects
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split(' ');
+                if fields.next()? != "blob" {
+                    return None;
+                }
+                let size = fields.next()?.parse().ok()?;
+                Some((fields.next()?, size))
+            })
+            .filter(|(_, size)| *size >= min)
+            .collect();
+        if large.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut blobs: Vec<(String, u64)> = self
+            .git_stdout(&["rev-list", "--objects", "--all"])?
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .filter_map(|(hash, path)| Some((path.to_string(), *large.get(hash)?)))
+            .collect();
+        blobs.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(blobs)
+    }
+    /// Fetches the history a shallow checkout is missing, back to since if
+    /// given, or all of it
+    ///
+    /// Checkouts that aren't shallow already have everything, and are left
+    /// alone.
+    pub fn deepen(&self, since: Option<&str>) -> bool {
+        if !self.is_shallow() {
+            debug!("{} isn't shallow", self.display_name());
+            return true;
+        }
+        let depth = match since {
+            Some(since) => format!("--shallow-since={since}"),
+            None => String::from("--unshallow"),
+        };
+        Command::new("git")
+            .current_dir(self.checkout_dir())
+            .args(self.fetch_config())
+            .arg("fetch")
+            .arg(depth)
+            .envs(self.remote_env())
+            .exec_output()
+            .map_or(false, |output| output.status.success())
+    }
+    /// Returns the commits on HEAD made after since, and before until if
+    /// given, newest first, as their short hash and subject
+    ///
+    /// Both are anything git understands as a date, like `2024-01-01`,
+    /// `1 month ago` or `@1700000000`.
+    pub fn commits_between(
+        &self,
+        since: &str,
+        until: Option<&str>,
+    ) -> Result<Vec<(String, String)>, String> {
+        let since = format!("--since={since}");
+        let until = until.map(|until| format!("--until={until}"));
+        let mut args = vec!["log", "--format=%h%x09%s", &since];
+        args.extend(until.as_deref());
+        Ok(self
+            .git_stdout(&args)?
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .map(|(hash, subject)| (hash.to_string(), subject.to_string()))
+            .collect())
+    }
+    /// Returns the paths under pathspec with changes that aren't committed,
+    /// staged or not, including untracked files, relative to the checkout
+    pub fn uncommitted_paths(&self, pathspec: &Path) -> Result<Vec<String>, String> {
+        let pathspec = pathspec.to_string_lossy();
+        let staged =
+            self.git_stdout(&["diff", "--cached", "--name-only", "-z", "--", &pathspec])?;
+        let unstaged = self.git_stdout(&[
+            "ls-files",
+            "--modified",
+            "--others",
+            "--exclude-standard",
+            "-z",
+            "--",
+            &pathspec,
+        ])?;
+        let mut paths: Vec<String> = staged
+            .split('\0')
+            .chain(unstaged.split('\0'))
+            .filter(|path| !path.is_empty())
+            .map(str::to_string)
+            .collect();
+        paths.sort();
+        paths.dedup();
+        Ok(paths)
+    }
+    /// Returns the core.hooksPath of the repository, if set.
+    pub fn hooks_path(&self) -> Option<String> {
+        let output = Command::new("git")
+            .current_dir(self.checkout_dir())
+            .arg("config")
+            .arg("--get")
+            .arg("core.hooksPath")
+            .exec_output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+    /// Sets core.hooksPath of the repository to hooks_dir, with `~` expanded,
+    /// as git only expands it in config files.
+    pub fn set_hooks_path(&self, hooks_dir: &str) -> bool {
+        Command::new("git")
+            .current_dir(self.checkout_dir())
+            .arg("config")
+            .arg("core.hooksPath")
+            .arg(expand_tilde(hooks_dir))
+            .exec_output()
+            .map_or(false, |output| output.status.success())
+    }
+    /// Returns the value of key in the git config of the checkout
+    pub fn git_config_value(&self, key: &str) -> Option<String> {
+        self.git_stdout(&["config", "--get", key]).ok()
+    }
+    /// Sets key to value in the git config of the checkout
+    pub fn set_git_config(&self, key: &str, value: &str) -> bool {
+        self.git_stdout(&["config", key, value]).is_ok()
+    }
+    /// Sets everything in git_config in the checkout
+    pub fn apply_git_config(&self) -> bool {
+        self.git_config
+            .iter()
+            .flatten()
+            .all(|(key, value)| self.set_git_config(key, value))
+    }
+    /// Returns the keys of git_config that aren't set to their value in the
+    /// checkout, with what they are set to instead
+    pub fn git_config_drift(&self) -> Vec<(&String, &String, Option<String>)> {
+        self.git_config
+            .iter()
+            .flatten()
+            .filter_map(|(key, value)| {
+                let current = self.git_config_value(key);
+                (current.as_ref() != Some(value)).then_some((key, value, current))
+            })
+            .collect()
+    }
+    /// Reports the health of the working tree.
+    pub fn status(&self) -> RepoStatus {
+        let dir = self.checkout_dir();
+        if !Path::new(&dir).is_dir() {
+            return RepoStatus::Missing;
+        }
+        // An extracted archive has no working tree to change
+        if self.extract {
+            return RepoStatus::Clean;
+        }
+        match Command::new("git")
+            .current_dir(dir)
+            .arg("status")
+            .arg("--porcelain")
+            .arg("--branch")
+            .exec_output()
+        {
+            Ok(output) if output.status.success() => {
+                RepoStatus::from_porcelain(&String::from_utf8_lossy(&output.stdout))
+            }
+            _ => RepoStatus::Unknown,
+        }
+    }
+    /// Returns the short status of the working tree, for displaying to the user.
+    pub fn status_short(&self) -> String {
+        match Command::new("git")
+            .current_dir(self.checkout_dir())
+            .arg("status")
+            .arg("--short")
+            .arg("--branch")
+            .exec_output()
+        {
+            Ok(output) => format!(
+                "{}{}",
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            ),
+            Err(err) => format!("{err}"),
+        }
+    }
     /// Removes a repository (not implemented)
     ///
     /// Kept here as a reminder that we probably shouldn't do this
@@ -380,42 +2182,123 @@ impl Repo {
         unimplemented!("This seems to easy to missuse/exploit");
         // fs::remove_dir_all(format!("{}{}", &self.path.as_ref(), &self.name.as_ref()))
     }
-    fn check_is_valid_gitrepo(&self) -> bool {
-        if (self.name.is_none()) {
-            eprintln!("{:?} must have name: <string>", self.kind);
-            return false;
-        }
+    fn check_is_valid_gitrepo(&self) -> Result<(), String> {
         if (self.path.is_none()) {
-            eprintln!("{:?} must have path: <string>", self.kind);
-            return false;
+            return Err(format!("{:?} must have path: <string>", self.kind));
         }
         if (self.url.is_none()) {
-            eprintln!("{:?} must have url: <string>", self.kind);
-            return false;
+            return Err(format!("{:?} must have url: <string>", self.kind));
         }
-        assert!(self.name.is_some());
         assert!(self.path.is_some());
         assert!(self.url.is_some());
-        true
+        let url = GitUrl::parse(self.url.as_ref().unwrap())?;
+        if self.mirror_of.is_some() && !url.is_local() {
+            return Err(format!(
+                "{:?} with mirror_of must have a local url: <path>",
+                self.kind
+            ));
+        }
+        if self.mirror_of.is_some() && self.pull_remote.is_some() {
+            return Err(format!(
+                "{:?} with mirror_of pulls from the mirror, and can't have pull_remote",
+                self.kind
+            ));
+        }
+        if self.dir.is_none() && self.name.is_none() && url.repo_name().is_none() {
+            return Err(format!(
+                "{:?} must have name: <string> or dir: <string>, as url has no name",
+                self.kind
+            ));
+        }
+        Ok(())
     }
-    fn check_is_valid_githubrepo(&self) -> bool {
-        todo!();
+    /// Checks the url is on a forge, in the form git@host:owner/name or
+    /// https://host/owner/name
+    ///
+    /// If nested is set, the url may contain subgroups between the owner and
+    /// name, like GitLab allows.
+    fn check_is_valid_forge_url(&self, nested: bool) -> Result<GitUrl, String> {
+        self.check_is_valid_gitrepo()?;
+        let url = self.url.as_ref().unwrap();
+        let kind = self.kind.as_ref().unwrap();
+        let parsed = GitUrl::parse(url)?;
+        match (parsed.scheme, parsed.user.as_deref()) {
+            (UrlScheme::Https, _) | (UrlScheme::Ssh, Some("git")) => (),
+            (UrlScheme::Ssh, _) => {
+                return Err(format!(
+                    "{kind:?} url {url} must use the git user for ssh, like git@{}:owner/name",
+                    parsed.host
+                ))
+            }
+            (scheme, _) => {
+                return Err(format!(
+                    "{kind:?} url {url} must use https or ssh, not {scheme}"
+                ))
+            }
+        }
+        match parsed.segments.len() {
+            0 | 1 => Err(format!(
+                "{kind:?} url {url} must contain both owner and name, like owner/name"
+            )),
+            2 => Ok(parsed),
+            _ if nested => Ok(parsed),
+            _ => Err(format!(
+                "{kind:?} url {url} must contain only owner and name, found {}",
+                parsed.segments.join("/")
+            )),
+        }
     }
-    fn check_is_valid_gitlabrepo(&self) -> bool {
-        todo!();
+    fn check_is_valid_githubrepo(&self) -> Result<(), String> {
+        let parsed = self.check_is_valid_forge_url(false)?;
+        if parsed.host != "github.com" {
+            return Err(format!(
+                "GitHubRepo url {} must be on github.com, not {}",
+                self.url.as_ref().unwrap(),
+                parsed.host
+            ));
+        }
+        Ok(())
     }
-    fn check_is_valid_gitearepo(&self) -> bool {
-        todo!();
+    fn check_is_valid_gitlabrepo(&self) -> Result<(), String> {
+        let parsed = self.check_is_valid_forge_url(true)?;
+        // NOTE: self hosted instances are usually on gitlab.<domain>
+        if !parsed.host.contains("gitlab") {
+            return Err(format!(
+                "GitLabRepo url {} must be on a gitlab host, not {}",
+                self.url.as_ref().unwrap(),
+                parsed.host
+            ));
+        }
+        Ok(())
     }
-    fn check_is_valid_urlrepo(&self) -> bool {
-        todo!();
+    fn check_is_valid_gitearepo(&self) -> Result<(), String> {
+        // NOTE: gitea is self hosted, so any host goes
+        self.check_is_valid_forge_url(false).map(|_| ())
     }
-    fn check_is_valid_link(&self) -> bool {
-        todo!();
+    fn check_is_valid_urlrepo(&self) -> Result<(), String> {
+        self.check_is_valid_gitrepo()?;
+        let url = self.url.as_ref().unwrap();
+        if self.extract && archive::Format::of(url).is_none() {
+            return Err(format!(
+                "UrlRepo url {url} must be a tar or zip archive to be extracted"
+            ));
+        }
+        Ok(())
     }
-    /// Check if Repo is a valid instance of its kind
-    pub fn is_valid_kind(&self) -> bool {
+    fn check_is_valid_link(&self) -> Result<(), String> {
+        Err(String::from(
+            "kind Link is for links, and can't be used for repos",
+        ))
+    }
+    /// Check if Repo is a valid instance of its kind, explaining why if not
+    pub fn validate_kind(&self) -> Result<(), String> {
         use RepoKinds::*;
+        if self.extract && self.kind != Some(UrlRepo) {
+            return Err(format!(
+                "extract is only for UrlRepo, not kind {:?}",
+                self.kind
+            ));
+        }
         match &self.kind {
             Some(GitRepo) => self.check_is_valid_gitrepo(),
             Some(GitHubRepo) => self.check_is_valid_githubrepo(),
@@ -423,12 +2306,16 @@ impl Repo {
             Some(GiteaRepo) => self.check_is_valid_gitearepo(),
             Some(UrlRepo) => self.check_is_valid_urlrepo(),
             Some(Link) => self.check_is_valid_link(),
-            Some(kind) => {
-                panic!("kind {kind:?} not implemented");
-                false
-            }
-            None => {
-                println!("unknown kind {:?}", self.kind);
+            Some(kind) => Err(format!("kind {kind:?} not implemented")),
+            None => Err(format!("unknown kind {:?}", self.kind)),
+        }
+    }
+    /// Check if Repo is a valid instance of its kind
+    pub fn is_valid_kind(&self) -> bool {
+        match self.validate_kind() {
+            Ok(()) => true,
+            Err(msg) => {
+                eprintln!("{msg}");
                 false
             }
         }
@@ -440,12 +2327,13 @@ impl Repo {
 /// # Examples
 ///
 ///
-/// ```
+/// ```no_run
 /// use seidr::git;
 /// use seidr::git::Repo;
 /// use seidr::git::Config;
 /// use std::env::current_dir;
-/// use seidr::git::SeriesItem;
+/// use seidr::git::{OnFailure, SeriesItem};
+/// use seidr::run_series;
 /// use relative_path::RelativePath;
 ///
 /// let root = current_dir().expect("failed to get current dir");
@@ -461,18 +2349,22 @@ impl Repo {
 ///     SeriesItem {
 ///         operation: "pull",
 ///         closure: Box::new(move |repo: &Repo| repo.pull()),
+///         on_failure: OnFailure::SkipRepo,
 ///     },
 ///     SeriesItem {
 ///         operation: "add",
 ///         closure: Box::new(move |repo: &Repo| repo.add_all()),
+///         on_failure: OnFailure::Continue,
 ///     },
 ///     SeriesItem {
 ///         operation: "commit",
 ///         closure: Box::new(move |repo: &Repo| repo.commit()),
+///         on_failure: OnFailure::Continue,
 ///     },
 ///     SeriesItem {
 ///         operation: "push",
 ///         closure: Box::new(move |repo: &Repo| repo.push()),
+///         on_failure: OnFailure::Continue,
 ///     },
 /// ];
 ///
@@ -480,32 +2372,214 @@ impl Repo {
 /// run_series!(config, series);
 ///
 /// // If we want to skip repo as soon as a step fails
+/// # let series: Vec<SeriesItem> = vec![];
 /// run_series!(config, series, true);
 /// ```
 #[macro_export]
 macro_rules! run_series {
     ($conf:ident, $closures:ident) => {
-        $conf.all_on_all($closures, false);
+        $conf.all_on_all($closures, false)
     };
     ($conf:ident, $closures:ident, $stop_on_err:tt) => {
-        $conf.all_on_all($closures, $stop_on_err);
+        $conf.all_on_all($closures, $stop_on_err)
     };
 }
 
+/// Runs f on repo with a spinner showing op
+///
+/// Interactive repos get a plain line instead, as the spinner would draw over
+/// any prompt, and so do repos in a parallel run, as spinners would draw over
+/// each other.
+fn with_spinner<F>(repo: &Repo, op: &str, parallel: bool, f: F) -> bool
+where
+    F: Fn(&Repo) -> bool,
+{
+    // Leaves room for the spinner or result and the space after it
+    let message = fit(
+        &format!("{}: {}", repo.display_name(), op),
+        Output::Stderr,
+        result_width() + 1,
+    );
+    if repo.interactive || parallel {
+        if repo.interactive {
+            eprintln!("{message} (interactive)");
+        }
+        let succeeded = f(repo);
+        eprintln!("{} {message}", result_str(succeeded));
+        return succeeded;
+    }
+    let mut sp = Spinner::with_stream(Spinners::Dots10, message.clone(), Stream::Stderr);
+    let succeeded = f(repo);
+    sp.stop_and_persist(&result_str(succeeded), message);
+    succeeded
+}
+
 impl Config {
     /// Loads the configuration toml from a path in to the Config struct.
+    ///
+    /// Subcategories are flattened, see `Config::flatten_categories`.
     pub fn new(path: &String) -> Self {
+        let mut config = Config::read(path);
+        config.interpolate_paths().unwrap_or_else(|err| {
+            panic!("Should have been able to interpolate config paths: path -> {path:?}: {err}")
+        });
+        config.flatten_categories();
+        config
+    }
+    /// Loads the configuration like `Config::new`, but leaves subcategories
+    /// nested as they are written, for writing the config back
+    pub fn read(path: &String) -> Self {
         debug!("initializing new Config struct");
         let yaml = fs::read_to_string(path).unwrap_or_else(|_| {
             panic!("Should have been able to read the file: path -> {:?}", path,)
         });
+        Config::from_yaml(&yaml, path)
+    }
+    /// Parses yaml, read from path, without flattening subcategories
+    ///
+    /// Keys that appear twice in a mapping are refused, as serde_yaml would
+    /// silently keep only the last of them.
+    pub fn from_yaml(yaml: &str, path: &String) -> Self {
+        // A config for a newer seidr may use anything, so nothing else of it
+        // is read before its version requirement is
+        if let Err(err) = check_requires(yaml) {
+            panic!("{err}: path -> {path:?}");
+        }
+        let duplicates = duplicate_keys(yaml);
+        if !duplicates.is_empty() {
+            let duplicates: Vec<String> = duplicates.iter().map(ToString::to_string).collect();
+            panic!(
+                "Duplicate keys in yaml config: path -> {path:?}\n{}",
+                duplicates.join("\n")
+            );
+        }
         debug!("deserialized yaml from config file");
-        serde_yaml::from_str(&yaml).unwrap_or_else(|_| {
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap_or_else(|_| {
             panic!(
                 "Should have been able to deserialize yaml config: path -> {:?}",
                 path,
             )
-        })
+        });
+        config.order = FileOrder::of(yaml);
+        config
+    }
+    /// Replaces `${name}` in the roots, hooks dirs and repo and link paths of
+    /// categories with the template vars of this host, see
+    /// `template::interpolate`
+    ///
+    /// Subcategories are still nested, so their relative roots are resolved
+    /// against interpolated ones when flattened.
+    pub fn interpolate_paths(&mut self) -> Result<(), String> {
+        fn interpolate_category(
+            name: &str,
+            category: &mut Category,
+            vars: &HashMap<String, String>,
+        ) -> Result<(), String> {
+            let interpolate = |path: &mut String| -> Result<(), String> {
+                *path = template::interpolate(path, vars)?;
+                Ok(())
+            };
+            let in_category = |err| format!("category {name}: {err}");
+            for path in category.root.iter_mut().chain(&mut category.hooks_dir) {
+                interpolate(path).map_err(in_category)?;
+            }
+            for (repo_name, repo) in category.repos.iter_mut().flatten() {
+                for path in repo.path.iter_mut() {
+                    interpolate(path).map_err(|err| in_category(format!("{repo_name}: {err}")))?;
+                }
+            }
+            for (link_name, link) in category.links.iter_mut().flatten() {
+                for path in [&mut link.rx, &mut link.tx] {
+                    interpolate(path).map_err(|err| in_category(format!("{link_name}: {err}")))?;
+                }
+            }
+            for (child_name, child) in category.categories.iter_mut().flatten() {
+                interpolate_category(&format!("{name}/{child_name}"), child, vars)?;
+            }
+            Ok(())
+        }
+        let vars = self.template_vars();
+        for (name, category) in self.categories.iter_mut() {
+            interpolate_category(name, category, &vars)?;
+        }
+        Ok(())
+    }
+    /// Moves subcategories up to the top level, named by their path like
+    /// `work/client`, so everything else only sees a flat list of categories
+    ///
+    /// A subcategory without flags or root inherits those of its parent, with
+    /// a relative root resolved against the root of the parent, and repos
+    /// without flags or path get those of their category. Subcategories of a
+    /// manual category are manual too. Git config is merged down, with the
+    /// keys of subcategories and repos winning.
+    pub fn flatten_categories(&mut self) {
+        fn flatten(name: String, mut category: Category, into: &mut HashMap<String, Category>) {
+            for repo in category
+                .repos
+                .iter_mut()
+                .flat_map(|repos| repos.values_mut())
+            {
+                if repo.flags.is_none() {
+                    repo.flags = category.flags.clone();
+                }
+                if repo.path.is_none() {
+                    repo.path = category.root.clone();
+                }
+                repo.git_config = merge_git_config(&category.git_config, repo.git_config.take());
+            }
+            for (child_name, mut child) in category.categories.take().into_iter().flatten() {
+                if child.flags.is_none() {
+                    child.flags = category.flags.clone();
+                }
+                child.manual |= category.manual;
+                child.git_config = merge_git_config(&category.git_config, child.git_config);
+                child.root = match (&category.root, child.root) {
+                    (Some(parent), Some(root)) => Some(
+                        Path::new(&expand_tilde(parent))
+                            .join(expand_tilde(&root))
+                            .to_string_lossy()
+                            .to_string(),
+                    ),
+                    (parent, root) => root.or_else(|| parent.clone()),
+                };
+                flatten(format!("{name}/{child_name}"), child, into);
+            }
+            into.insert(name, category);
+        }
+        fn merge_git_config(
+            parent: &Option<BTreeMap<String, String>>,
+            own: Option<BTreeMap<String, String>>,
+        ) -> Option<BTreeMap<String, String>> {
+            match (parent, own) {
+                (Some(parent), Some(own)) => {
+                    let mut merged = parent.clone();
+                    merged.extend(own);
+                    Some(merged)
+                }
+                (parent, own) => own.or_else(|| parent.clone()),
+            }
+        }
+        let mut categories = HashMap::new();
+        for (name, category) in self.categories.drain() {
+            flatten(name, category, &mut categories);
+        }
+        self.categories = categories;
+    }
+    /// Whether the category named name is category, or one of its
+    /// subcategories
+    pub fn in_category(name: &str, category: &str) -> bool {
+        name == category
+            || name
+                .strip_prefix(category)
+                .map_or(false, |rest| rest.starts_with('/'))
+    }
+    /// Renders the config as canonical yaml
+    ///
+    /// Categories, repos, links and hosts are sorted by name, and fields are
+    /// in the order they are declared in, so the same config is always
+    /// written the same way.
+    pub fn canonical_yaml(&self) -> Result<String, serde_yaml::Error> {
+        serde_yaml::to_string(self)
     }
     /// Runs associated function on all repos in config
     ///
@@ -540,94 +2614,255 @@ impl Config {
     //         }
     //     }
     // }
-    /// Runs associated function on all repos in config
-    fn on_all_repos_spinner<F>(&self, op: &str, f: F)
+    /// Runs associated function on all repos in config, reporting the results
+    ///
+    /// Nothing is printed, see `on_all_repos_spinner` for that.
+    pub fn report_repos<F>(&self, op: &str, f: F) -> RunReport
     where
-        F: Fn(&Repo) -> bool,
+        F: Fn(&Repo) -> bool + Sync,
     {
-        for category in self.categories.values() {
-            match category.repos.as_ref() {
-                Some(repos) => {
-                    for repo in repos.values() {
-                        if !settings::QUIET.load(std::sync::atomic::Ordering::Relaxed) {
-                            let mut sp = Spinner::new(
-                                Spinners::Dots10,
-                                format!("{}: {}", repo.name.as_ref().unwrap(), op),
-                            );
-                            if f(repo) {
-                                sp.stop_and_persist(
-                                    success_str(),
-                                    format!("{}: {}", repo.name.as_ref().unwrap(), op),
-                                );
-                            } else {
-                                sp.stop_and_persist(
-                                    failure_str(),
-                                    format!("{}: {}", repo.name.as_ref().unwrap(), op),
-                                );
-                            }
-                        } else {
-                            f(repo);
-                        }
-                    }
-                }
-                None => continue,
-            };
+        self.report_repos_on(op, self.parallelism().limit(OpClass::of(op)), f)
+    }
+    /// Like `report_repos`, but on at most workers repos at once, whatever
+    /// the parallelism
+    fn report_repos_on<F>(&self, op: &str, workers: usize, f: F) -> RunReport
+    where
+        F: Fn(&Repo) -> bool + Sync,
+    {
+        let mut repos = self.repos_scheduled();
+        repos.retain(|(_, _, repo)| !repo.skips(op));
+        // Interactive repos are last, and run one at a time after the others
+        let (others, interactive) =
+            repos.split_at(repos.partition_point(|(_, _, r)| !r.interactive));
+        // Out of time, repos are skipped rather than started, None
+        let run = |(_, _, repo): &(&String, &String, &Repo)| {
+            (!self.settings.out_of_time()).then(|| {
+                let started = Instant::now();
+                (f(repo), started.elapsed().as_millis() as u64)
+            })
+        };
+        let mut results = run_all(others, workers, run);
+        results.extend(interactive.iter().map(run));
+        let mut report = RunReport::default();
+        for ((category, name, _), result) in repos.iter().zip(results) {
+            report.add_repo(
+                category,
+                RepoResult {
+                    name: name.to_string(),
+                    operation: op.to_string(),
+                    succeeded: result.map_or(false, |(succeeded, _)| succeeded),
+                    skipped: result.is_none(),
+                    duration_ms: result.map(|(_, duration_ms)| duration_ms),
+                },
+            );
         }
+        report.sort();
+        report
+    }
+    /// The parallelism section of the config, or sequential if there is none
+    ///
+    /// The --jobs flag overrides the limits of every class.
+    pub fn parallelism(&self) -> Parallelism {
+        match self.settings.jobs {
+            0 => self.parallelism.unwrap_or_default(),
+            jobs => Parallelism {
+                network: jobs.into(),
+                local: jobs.into(),
+            },
+        }
+    }
+    /// Returns all repos with their category and name, sorted, but with
+    /// interactive repos last, so the user is only needed at the end of a run
+    fn repos_interactive_last(&self) -> Vec<(&String, &String, &Repo)> {
+        let mut repos: Vec<(&String, &String, &Repo)> = self
+            .categories
+            .iter()
+            .flat_map(|(category, cat)| {
+                cat.repos
+                    .iter()
+                    .flatten()
+                    .map(move |(name, repo)| (category, name, repo))
+            })
+            .collect();
+        repos.sort_by_key(|(category, name, repo)| (repo.interactive, *category, *name));
+        repos
+    }
+    /// Returns all repos like `repos_interactive_last`, but in the order the
+    /// schedule starts them
+    fn repos_scheduled(&self) -> Vec<(&String, &String, &Repo)> {
+        let mut repos = self.repos_interactive_last();
+        let timings = match self.schedule {
+            Schedule::ShortestFirst => Timings::load(),
+            _ => Timings::default(),
+        };
+        schedule::order(&mut repos, self.schedule, &self.order, &timings);
+        repos
     }
-    /// Runs associated function on all links in config
-    fn on_all_links_spinner<F>(&self, op: &str, f: F)
+    /// Runs associated function on all enabled links in config, reporting the
+    /// results
+    ///
+    /// Nothing is printed, see `on_all_links_spinner` for that.
+    pub fn report_links<F>(&self, op: &str, f: F) -> RunReport
     where
-        F: Fn(&Link) -> Result<bool, LinkError>,
+        F: Fn(&Link) -> Result<Linked, LinkError>,
     {
-        for category in self.categories.values() {
-            match category.links.as_ref() {
-                Some(links) => {
-                    for link in links.values() {
-                        if !settings::QUIET.load(std::sync::atomic::Ordering::Relaxed) {
-                            let mut sp =
-                                Spinner::new(Spinners::Dots10, format!("{}: {}", link.name, op));
-                            match f(link) {
-                                Ok(_) => sp.stop_and_persist(
-                                    success_str(),
-                                    format!("{}: {}", link.name, op),
-                                ),
-                                Err(e @ LinkError::AlreadyLinked(_, _)) => {
-                                    sp.stop_and_persist(success_str(), format!("{e}"))
-                                }
-                                Err(e @ LinkError::DifferentLink(_, _)) => {
-                                    sp.stop_and_persist(failure_str(), format!("{e}"))
-                                }
-                                Err(e @ LinkError::FileExists(_, _)) => {
-                                    sp.stop_and_persist(failure_str(), format!("{e}"))
-                                }
-                                Err(e @ LinkError::BrokenSymlinkExists(_, _)) => {
-                                    sp.stop_and_persist(failure_str(), format!("{e}"))
-                                }
-                                Err(e @ LinkError::FailedCreatingLink(_, _)) => {
-                                    sp.stop_and_persist(failure_str(), format!("{e}"))
-                                }
-                                Err(e @ LinkError::IoError(_)) => sp.stop_and_persist(
-                                    failure_str(),
-                                    format!("{}: {op}, {e}", link.name),
-                                ),
-                                Err(e) => sp.stop_and_persist(failure_str(), format!("{e}")),
-                                _ => sp.stop_and_persist(
-                                    failure_str(),
-                                    format!("{}: {}", link.name, op),
+        let mut categories: Vec<(&String, &Category)> = self.categories.iter().collect();
+        categories.sort_by_key(|(name, _)| *name);
+        let categories = categories
+            .into_iter()
+            .filter_map(|(category_name, category)| {
+                let links = category.links.as_ref()?;
+                let mut links: Vec<(&String, &Link)> = links
+                    .iter()
+                    .filter(|(_, link)| self.is_link_enabled(link))
+                    .collect();
+                links.sort_by_key(|(name, _)| *name);
+                Some(CategoryReport {
+                    name: category_name.clone(),
+                    repos: vec![],
+                    links: links
+                        .into_iter()
+                        .map(|(name, link)| {
+                            let result = f(link);
+                            LinkResult {
+                                name: name.clone(),
+                                operation: op.to_string(),
+                                rx: link.rx.clone(),
+                                tx: link.tx.clone(),
+                                succeeded: matches!(
+                                    result,
+                                    Ok(_) | Err(LinkError::AlreadyLinked(_, _))
                                 ),
+                                message: match result {
+                                    Ok(Linked::Created) => None,
+                                    Ok(Linked::Resolved(strategy)) => Some(strategy.to_string()),
+                                    Err(e) => Some(e.to_string()),
+                                },
                             }
-                        } else {
-                            f(link);
-                        }
-                    }
-                }
-                None => continue,
-            };
+                        })
+                        .collect(),
+                })
+            })
+            .collect();
+        RunReport { categories }
+    }
+    /// Runs associated function on all repos in config, with a spinner for
+    /// each unless the settings are quiet, or a single status line
+    /// with compact progress
+    fn on_all_repos_spinner<F>(&self, op: &str, f: F) -> RunReport
+    where
+        F: Fn(&Repo) -> bool + Sync,
+    {
+        self.on_all_repos_spinner_on(op, self.parallelism().limit(OpClass::of(op)), f)
+    }
+    /// Like `on_all_repos_spinner`, but on at most workers repos at once,
+    /// whatever the parallelism
+    fn on_all_repos_spinner_on<F>(&self, op: &str, workers: usize, f: F) -> RunReport
+    where
+        F: Fn(&Repo) -> bool + Sync,
+    {
+        if self.settings.quiet {
+            return self.report_repos_on(op, workers, f);
+        }
+        if self.settings.compact_progress {
+            let total = self
+                .repos_interactive_last()
+                .iter()
+                .filter(|(_, _, repo)| !repo.skips(op))
+                .count();
+            let progress = Compact::new(op, total);
+            let report = self.report_repos_on(op, workers, |repo| {
+                let name = repo.display_name();
+                progress.start(&name);
+                let succeeded = f(repo);
+                progress.finish(&name, succeeded);
+                succeeded
+            });
+            progress.end();
+            return report;
         }
+        self.report_repos_on(op, workers, |repo| with_spinner(repo, op, workers > 1, &f))
     }
-    /// Runs associated function on all repos in config
+    /// Runs associated function on all links in config, with a spinner for
+    /// each unless the settings are quiet, or a single status line
+    /// with compact progress
+    fn on_all_links_spinner<F>(&self, op: &str, f: F) -> RunReport
+    where
+        F: Fn(&Link) -> Result<Linked, LinkError>,
+    {
+        if self.settings.quiet {
+            return self.report_links(op, f);
+        }
+        if self.settings.compact_progress {
+            let total = self
+                .categories
+                .values()
+                .flat_map(|category| category.links.iter().flatten())
+                .filter(|(_, link)| self.is_link_enabled(link))
+                .count();
+            let progress = Compact::new(op, total);
+            let report = self.report_links(op, |link| {
+                progress.start(&link.name);
+                let result = f(link);
+                let succeeded = matches!(result, Ok(_) | Err(LinkError::AlreadyLinked(_, _)));
+                progress.finish(&link.name, succeeded);
+                result
+            });
+            progress.end();
+            return report;
+        }
+        self.report_links(op, |link| {
+            let mut sp = Spinner::with_stream(
+                Spinners::Dots10,
+                fit(
+                    &format!("{}: {}", link.name, op),
+                    Output::Stderr,
+                    result_width() + 1,
+                ),
+                Stream::Stderr,
+            );
+            let result = f(link);
+            match &result {
+                Ok(Linked::Created) => sp.stop_and_persist(
+                    &result_str(true),
+                    fit(
+                        &format!("{}: {}", link.name, op),
+                        Output::Stderr,
+                        result_width() + 1,
+                    ),
+                ),
+                Ok(Linked::Resolved(strategy)) => sp.stop_and_persist(
+                    &result_str(true),
+                    fit(
+                        &format!("{}: {op}, {strategy}", link.name),
+                        Output::Stderr,
+                        result_width() + 1,
+                    ),
+                ),
+                Err(e @ LinkError::AlreadyLinked(_, _)) => sp.stop_and_persist(
+                    &result_str(true),
+                    fit(&e.to_string(), Output::Stderr, result_width() + 1),
+                ),
+                Err(e @ LinkError::IoError(_)) => sp.stop_and_persist(
+                    &result_str(false),
+                    fit(
+                        &format!("{}: {op}, {e}", link.name),
+                        Output::Stderr,
+                        result_width() + 1,
+                    ),
+                ),
+                Err(e) => sp.stop_and_persist(
+                    &result_str(false),
+                    fit(&e.to_string(), Output::Stderr, result_width() + 1),
+                ),
+            }
+            result
+        })
+    }
+    /// Runs a series of operations on all repos in config
     ///
-    /// Unlike `series_on_all`, this does not stop if it encounters an error
+    /// When an operation fails, its `on_failure` decides what happens next. If
+    /// break_on_err is set, any failure skips at least the rest of the repo.
     ///
     /// # Usage
     ///
@@ -635,74 +2870,224 @@ impl Config {
     ///
     /// ```
     /// # use seidr::git::Repo;
-    /// # use seidr::git::SeriesItem;
+    /// # use seidr::git::{OnFailure, SeriesItem};
     ///
     /// let series: Vec<SeriesItem> = vec![
     ///     SeriesItem {
     ///         operation: "pull",
     ///         closure: Box::new(move |repo: &Repo| repo.pull()),
+    ///         on_failure: OnFailure::SkipRepo,
     ///     },
     ///     SeriesItem {
     ///         operation: "add",
     ///         closure: Box::new(move |repo: &Repo| repo.add_all()),
+    ///         on_failure: OnFailure::Continue,
     ///     },
     ///     SeriesItem {
     ///         operation: "commit",
     ///         closure: Box::new(move |repo: &Repo| repo.commit()),
+    ///         on_failure: OnFailure::Continue,
     ///     },
     ///     SeriesItem {
     ///         operation: "push",
     ///         closure: Box::new(move |repo: &Repo| repo.push()),
+    ///         on_failure: OnFailure::Continue,
     ///     },
     /// ];
     /// ```
-    pub fn all_on_all(&self, closures: Vec<SeriesItem>, break_on_err: bool) {
-        // HACK: creates a empty repo, that is used if a category doesn't have
-        // any repos or don't define the repo field
-        let tmp: HashMap<String, Repo> = HashMap::new();
-
-        for category in self.categories.values() {
-            // HACK: if the repo doesn't exist here, we inject tmp
-            for (_, repo) in category.repos.as_ref().unwrap_or(&tmp).iter() {
-                use RepoKinds::*;
-                match &repo.kind {
-                    Some(GitRepo) => {
-                        for instruction in &closures {
-                            let f = &instruction.closure;
-                            let op = instruction.operation;
-                            if !settings::QUIET.load(std::sync::atomic::Ordering::Relaxed) {
-                                let mut sp = Spinner::new(
-                                    Spinners::Dots10,
-                                    format!("{}: {}", repo.name.as_ref().unwrap(), op),
-                                );
-                                if f(repo) {
-                                    sp.stop_and_persist(
-                                        success_str(),
-                                        format!("{}: {}", repo.name.as_ref().unwrap(), op),
-                                    );
-                                } else {
-                                    sp.stop_and_persist(
-                                        failure_str(),
-                                        format!("{}: {}", repo.name.as_ref().unwrap(), op),
-                                    );
-                                    if break_on_err {
-                                        break;
-                                    }
-                                }
+    pub fn all_on_all(&self, closures: Vec<SeriesItem>, break_on_err: bool) -> RunReport {
+        let parallelism = self.parallelism();
+        let limits = Limits::new(&parallelism);
+        let parallel = parallelism.workers() > 1;
+        let quiet = self.settings.quiet;
+        // Once set, no more repos are started
+        let aborted = std::sync::atomic::AtomicBool::new(false);
+        let series = |repo: &Repo| -> Vec<RepoResult> {
+            use RepoKinds::*;
+            let mut results = vec![];
+            match &repo.kind {
+                Some(GitRepo) => {
+                    for instruction in &closures {
+                        if aborted.load(std::sync::atomic::Ordering::Relaxed) {
+                            break;
+                        }
+                        let f = &instruction.closure;
+                        let op = instruction.operation;
+                        if repo.skips(op) {
+                            continue;
+                        }
+                        if self.settings.out_of_time() {
+                            results.push(RepoResult {
+                                name: String::new(),
+                                operation: op.to_string(),
+                                succeeded: false,
+                                skipped: true,
+                                duration_ms: None,
+                            });
+                            continue;
+                        }
+                        let started = Instant::now();
+                        let succeeded = limits.run(OpClass::of(op), || {
+                            if quiet {
+                                f(repo)
                             } else {
-                                f(repo);
+                                with_spinner(repo, op, parallel, f)
+                            }
+                        });
+                        results.push(RepoResult {
+                            name: String::new(),
+                            operation: op.to_string(),
+                            succeeded,
+                            skipped: false,
+                            duration_ms: Some(started.elapsed().as_millis() as u64),
+                        });
+                        if succeeded {
+                            continue;
+                        }
+                        let on_failure = if break_on_err {
+                            instruction.on_failure.max(OnFailure::SkipRepo)
+                        } else {
+                            instruction.on_failure
+                        };
+                        match on_failure {
+                            OnFailure::Continue => (),
+                            OnFailure::SkipRepo => break,
+                            OnFailure::AbortRun => {
+                                error!("{}: {op} failed, aborting", repo.display_name());
+                                aborted.store(true, std::sync::atomic::Ordering::Relaxed);
+                                break;
                             }
                         }
                     }
-                    None => {
-                        println!("unknown kind {:?}", repo.kind);
-                    }
-                    Some(kind) => {
-                        println!("unknown kind {kind:?}");
-                    }
                 }
+                None => {
+                    eprintln!("unknown kind {:?}", repo.kind);
+                }
+                Some(kind) => {
+                    eprintln!("unknown kind {kind:?}");
+                }
+            }
+            results
+        };
+        let repos = self.repos_scheduled();
+        // Interactive repos are last, and run one at a time after the others
+        let (others, interactive) =
+            repos.split_at(repos.partition_point(|(_, _, repo)| !repo.interactive));
+        let mut results = run_all(others, parallelism.workers(), |(_, _, repo)| series(repo));
+        results.extend(interactive.iter().map(|(_, _, repo)| series(repo)));
+        let mut report = RunReport::default();
+        for ((category, name, _), results) in repos.iter().zip(results) {
+            for result in results {
+                report.add_repo(
+                    category,
+                    RepoResult {
+                        name: name.to_string(),
+                        ..result
+                    },
+                );
             }
         }
+        report.sort();
+        report
+    }
+    /// Finds a repo by category and name, with a suggestion if there's no
+    /// such repo
+    pub fn find_repo(&self, cat_name: &str, repo_name: &str) -> Result<&Repo, String> {
+        let category = self.find_category(cat_name)?;
+        let repos = category.repos.iter().flatten();
+        repos
+            .clone()
+            .find(|(name, _)| *name == repo_name)
+            .map(|(_, repo)| repo)
+            .ok_or_else(|| {
+                with_suggestion(
+                    format!("no repo {repo_name} in category {cat_name}"),
+                    repo_name,
+                    repos.map(|(name, _)| name.as_str()),
+                )
+            })
+    }
+    /// Finds a link by category and name, with a suggestion if there's no
+    /// such link
+    pub fn find_link(&self, cat_name: &str, link_name: &str) -> Result<&Link, String> {
+        let category = self.find_category(cat_name)?;
+        let links = category.links.iter().flatten();
+        links
+            .clone()
+            .find(|(name, _)| *name == link_name)
+            .map(|(_, link)| link)
+            .ok_or_else(|| {
+                with_suggestion(
+                    format!("no link {link_name} in category {cat_name}"),
+                    link_name,
+                    links.map(|(name, _)| name.as_str()),
+                )
+            })
+    }
+    /// Moves link name in category to rx, which is as written in the config,
+    /// removing it from where it was and creating it at rx
+    ///
+    /// If it can't be created at rx, it's put back where it was. What is at
+    /// its old rx that isn't the link is left alone.
+    pub fn move_link(
+        &self,
+        cat_name: &str,
+        link_name: &str,
+        rx: &str,
+    ) -> Result<RunReport, String> {
+        let link = self.find_link(cat_name, link_name)?;
+        let vars = self.template_vars();
+        let moved = Link {
+            rx: template::interpolate(rx, &vars)?,
+            ..link.clone()
+        };
+        if moved.rx == link.rx {
+            return Err(format!("{link_name} is already at {rx}"));
+        }
+        let unlinked = link
+            .unlink()
+            .map_err(|err| format!("failed to remove {}: {err}", link.rx))?;
+        if !unlinked {
+            warn!("{} isn't linked, so it's left as it is", link.rx);
+        }
+        // Programs that move their config usually move it to a dir of its own
+        if let Some(parent) = Path::new(&moved.rx).parent() {
+            if let Err(err) = fs::create_dir_all(parent) {
+                warn!("failed to create {}: {err}", parent.display());
+            }
+        }
+        let linked = moved.link_with(&vars, self.settings.force);
+        if let Err(err) = &linked {
+            if unlinked {
+                if let Err(err) = link.link_with(&vars, self.settings.force) {
+                    error!("failed to link {} again: {err}", link.rx);
+                }
+            }
+            return Err(format!("failed to link {}: {err}", moved.rx));
+        }
+        Ok(RunReport {
+            categories: vec![CategoryReport {
+                name: cat_name.to_string(),
+                repos: vec![],
+                links: vec![LinkResult {
+                    name: link_name.to_string(),
+                    operation: String::from("mv"),
+                    rx: moved.rx,
+                    tx: moved.tx,
+                    succeeded: true,
+                    message: Some(format!("moved from {}", link.rx)),
+                }],
+            }],
+        })
+    }
+    fn find_category(&self, cat_name: &str) -> Result<&Category, String> {
+        self.categories.get(cat_name).ok_or_else(|| {
+            with_suggestion(
+                format!("no category {cat_name}"),
+                cat_name,
+                self.categories.keys().map(String::as_str),
+            )
+        })
     }
     pub fn get_repo<F>(&self, cat_name: &str, repo_name: &str, f: F)
     where
@@ -732,82 +3117,804 @@ impl Config {
             .get(link_name)
             .expect("failed to get category"));
     }
+    /// Finds the repos and links that manage a path
+    ///
+    /// A repo manages everything inside its working tree, and a link manages
+    /// both its rx and tx, as well as anything inside of them. Paths are
+    /// compared lexically, so the path doesn't have to exist.
+    pub fn which(&self, path: &Path) -> Vec<PathOwner> {
+        let path = absolute_path(path);
+        let mut owners = Vec::new();
+        for (category_name, category) in self.categories.iter() {
+            for (name, repo) in category.repos.iter().flatten() {
+                if repo.path.is_some()
+                    && path.starts_with(absolute_path(Path::new(&repo.checkout_dir())))
+                {
+                    owners.push(PathOwner::Repo {
+                        category: category_name,
+                        name,
+                    });
+                }
+            }
+            for (name, link) in category.links.iter().flatten() {
+                if path.starts_with(absolute_path(Path::new(&link.rx)))
+                    || path.starts_with(absolute_path(Path::new(&link.tx)))
+                {
+                    owners.push(PathOwner::Link {
+                        category: category_name,
+                        name,
+                    });
+                }
+            }
+        }
+        owners.sort_by_key(|owner| match owner {
+            PathOwner::Repo { category, name } => (0, *category, *name),
+            PathOwner::Link { category, name } => (1, *category, *name),
+        });
+        owners
+    }
+    /// Whether link should be linked on this host
+    ///
+    /// Links without a group are always enabled, grouped links are enabled if
+    /// the profile of the host enables their group. System links are only
+    /// enabled with the --system flag, and user links only without it.
+    pub fn is_link_enabled(&self, link: &Link) -> bool {
+        if link.system != self.settings.system {
+            return false;
+        }
+        if !link.condition.as_ref().map_or(true, Condition::holds) {
+            debug!("the condition of {} doesn't hold", link.name);
+            return false;
+        }
+        match &link.group {
+            None => true,
+            Some(group) => self
+                .hosts
+                .get(&hostname())
+                .map_or(false, |profile| profile.link_groups.contains(group)),
+        }
+    }
+    /// Returns the tx of every link, absolute, whether enabled or not
+    pub fn tx_roots(&self) -> Vec<PathBuf> {
+        self.categories
+            .values()
+            .flat_map(|category| category.links.iter().flatten())
+            .map(|(_, link)| absolute_path(Path::new(&link.tx)))
+            .collect()
+    }
+    /// Lists the enabled links, with what is at their rx, sorted by category
+    /// and name
+    ///
+    /// Only links with one of statuses are listed, or every link if statuses
+    /// is empty.
+    pub fn list_links(&self, statuses: &[LinkStatus]) -> Vec<LinkListing> {
+        let tx_roots = self.tx_roots();
+        let mut listings: Vec<LinkListing> = self
+            .categories
+            .iter()
+            .flat_map(|(category, cat)| {
+                cat.links
+                    .iter()
+                    .flatten()
+                    .map(move |(name, link)| (category, name, link))
+            })
+            .filter(|(_, _, link)| self.is_link_enabled(link))
+            .map(|(category, name, link)| LinkListing {
+                category: category.clone(),
+                name: name.clone(),
+                rx: link.rx.clone(),
+                tx: link.tx.clone(),
+                status: link.status(&tx_roots),
+                target: link
+                    .target()
+                    .map(|target| target.to_string_lossy().to_string()),
+            })
+            .filter(|listing| statuses.is_empty() || statuses.contains(&listing.status))
+            .collect();
+        listings.sort_by(|a, b| (&a.category, &a.name).cmp(&(&b.category, &b.name)));
+        listings
+    }
+    /// The template variables on this host
+    ///
+    /// These are the vars of the config, overridden by those of the host
+    /// profile.
+    pub fn template_vars(&self) -> HashMap<String, String> {
+        let mut vars = self.vars.clone();
+        if let Some(profile) = self.hosts.get(&hostname()) {
+            vars.extend(profile.vars.clone());
+        }
+        vars
+    }
+    /// Finds the category repo is in
+    ///
+    /// Repos are compared by identity, not equality, as the same repo may be in
+    /// several categories.
+    pub fn category_of(&self, repo: &Repo) -> Option<&Category> {
+        self.categories.values().find(|category| {
+            category
+                .repos
+                .iter()
+                .flat_map(HashMap::values)
+                .any(|candidate| std::ptr::eq(candidate, repo))
+        })
+    }
+    /// Resolves the commit message for repo
+    ///
+    /// In order of precedence, the message is:
+    ///
+    /// 1. msg, as given by the -m flag
+    /// 2. default_commit_message of the repo
+    /// 3. default_commit_message of the category of the repo
+    /// 4. fallback, usually `QUICK_COMMIT`
+    pub fn commit_message<'a>(
+        &'a self,
+        repo: &'a Repo,
+        msg: Option<&'a str>,
+        fallback: &'a str,
+    ) -> &'a str {
+        msg.or(repo.default_commit_message.as_deref())
+            .or_else(|| {
+                self.category_of(repo)
+                    .and_then(|category| category.default_commit_message.as_deref())
+            })
+            .unwrap_or(fallback)
+    }
+    /// Returns the trailers for commits seidr makes in repo, those of its
+    /// category followed by its own
+    ///
+    /// Trailers are written as `Key: value`, and `{name}` in the value is
+    /// replaced by the template variable name, such as `{hostname}`.
+    pub fn trailers(&self, repo: &Repo) -> Vec<String> {
+        let mut vars = self.template_vars();
+        vars.entry(String::from("hostname"))
+            .or_insert_with(hostname);
+        let args: Vec<(&str, &str)> = vars
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        self.category_of(repo)
+            .and_then(|category| category.trailers.as_ref())
+            .into_iter()
+            .chain(repo.trailers.as_ref())
+            .flatten()
+            .map(|trailer| fill(trailer, &args))
+            .collect()
+    }
+    /// Returns the url repo is cloned and pulled from, its pull_remote or
+    /// url, with its ssh host rewritten to the alias the category of repo has
+    /// for it, if any
+    pub fn remote_url(&self, repo: &Repo) -> Option<String> {
+        self.aliased_url(repo, repo.pull_remote.as_ref().or(repo.url.as_ref())?)
+    }
+    /// Returns the push_remote of repo, if it has one, with its ssh host
+    /// rewritten like `Config::remote_url`
+    pub fn push_url(&self, repo: &Repo) -> Option<String> {
+        self.aliased_url(repo, repo.push_remote.as_ref()?)
+    }
+    fn aliased_url(&self, repo: &Repo, url: &str) -> Option<String> {
+        self.category_of(repo)
+            .and_then(|category| category.ssh_host_alias.as_ref())
+            .and_then(|aliases| alias_ssh_host(url, aliases))
+            .or_else(|| Some(url.to_string()))
+    }
+    /// Returns the branch of repo, or the default branch of its remote
+    /// recorded when it was cloned, see the branches module
+    pub fn default_branch(&self, repo: &Repo) -> Option<String> {
+        repo.branch.clone().or_else(|| {
+            DefaultBranches::load()
+                .ok()?
+                .get(&repo.checkout_dir())
+                .cloned()
+        })
+    }
+    /// Commits in repo, with the message from `Config::commit_message` and
+    /// the trailers from `Config::trailers`
+    pub fn commit_repo(&self, repo: &Repo, msg: Option<&str>, fallback: &str) -> bool {
+        let mut trailers = self.trailers(repo);
+        if repo.link_trailer {
+            match repo.staged_paths() {
+                Ok(staged) => {
+                    let staged: Vec<&str> = staged.iter().map(String::as_str).collect();
+                    trailers.extend(self.link_trailer(repo, &staged));
+                }
+                Err(err) => warn!("{}: {err}", repo.display_name()),
+            }
+        }
+        repo.commit_with_trailers(self.commit_message(repo, msg, fallback), &trailers)
+    }
+    /// Commits in repo with a commit per top-level directory, see
+    /// `Repo::commit_split`, with the message from `Config::commit_message` as
+    /// the template
+    pub fn commit_repo_split(&self, repo: &Repo, msg: Option<&str>) -> bool {
+        let trailers = self.trailers(repo);
+        repo.commit_split_with(self.commit_message(repo, msg, SPLIT_COMMIT), |paths| {
+            let mut trailers = trailers.clone();
+            if repo.link_trailer {
+                trailers.extend(self.link_trailer(repo, paths));
+            }
+            trailers
+        })
+    }
+    /// Returns a `Links:` trailer naming the links, as `category/name`, with
+    /// their tx in repo at or above one of paths, which are relative to the
+    /// checkout, if any
+    pub fn link_trailer(&self, repo: &Repo, paths: &[&str]) -> Option<String> {
+        let checkout = absolute_path(Path::new(&repo.checkout_dir()));
+        let mut links: Vec<String> = self
+            .categories
+            .iter()
+            .flat_map(|(category, cat)| {
+                cat.links
+                    .iter()
+                    .flatten()
+                    .map(move |(name, link)| (category, name, link))
+            })
+            .filter(|(_, _, link)| {
+                let tx = absolute_path(Path::new(&expand_tilde(&link.tx)));
+                tx.strip_prefix(&checkout).map_or(false, |tx| {
+                    paths.iter().any(|path| Path::new(path).starts_with(tx))
+                })
+            })
+            .map(|(category, name, _)| format!("{category}/{name}"))
+            .collect();
+        if links.is_empty() {
+            return None;
+        }
+        links.sort();
+        Some(format!("Links: {}", links.join(", ")))
+    }
+    /// Commits in repo with the editor, and if the editor times out, falls
+    /// back to what commit_timeout of the repo says
+    pub fn commit_editor(&self, repo: &Repo) -> bool {
+        match repo.try_commit() {
+            Some(committed) => committed,
+            None => match repo.commit_timeout.map(|timeout| timeout.fallback) {
+                Some(TimeoutFallback::Message) => {
+                    warn!("editor timed out, committing with the default message");
+                    self.commit_repo(repo, None, QUICK_COMMIT)
+                }
+                _ => {
+                    warn!("editor timed out, skipping commit");
+                    false
+                }
+            },
+        }
+    }
+    /// Validates every repo in the config, returning a message for each
+    /// problem found
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems: Vec<String> = self
+            .categories
+            .iter()
+            .flat_map(|(category_name, category)| {
+                category
+                    .repos
+                    .iter()
+                    .flatten()
+                    .filter_map(move |(name, repo)| {
+                        repo.validate_kind()
+                            .err()
+                            .map(|msg| format!("{category_name} {name}: {msg}"))
+                    })
+            })
+            .collect();
+        problems.sort();
+        problems
+    }
+    /// Checks out the branch of repos with a detached HEAD, the configured one
+    /// or the default branch of the remote, see `Repo::reattach_head`
+    pub fn fix_head_all(&self) -> RunReport {
+        debug!("exectuting fix_head_all");
+        self.on_all_repos_spinner("fix-head", |repo| {
+            let result = match self.default_branch(repo).or_else(|| repo.remote_head()) {
+                Some(branch) => repo.reattach_head(&branch),
+                None => Err(String::from("no branch to check out")),
+            };
+            result
+                .map_err(|err| warn!("{}: {err}", repo.display_name()))
+                .is_ok()
+        })
+    }
+    /// Fetches the history shallow clones are missing, back to since, or all
+    /// of it, see `Repo::deepen`
+    pub fn deepen_all(&self, since: Option<&str>) -> RunReport {
+        debug!("exectuting deepen_all");
+        self.on_all_repos_spinner("deepen", |repo| repo.deepen(since))
+    }
     /// Tries to pull all repositories, skips if fail.
-    pub fn pull_all(&self) {
+    pub fn pull_all(&self) -> RunReport {
         debug!("exectuting pull_all");
-        self.on_all_repos_spinner("pull", Repo::pull);
+        self.on_all_repos_spinner("pull", |repo| {
+            repo.pull_from(self.default_branch(repo).as_deref())
+        })
+    }
+    /// Tries to push all repositories, skips if fail.
+    ///
+    /// If all_branches is set, every branch is pushed, regardless of the
+    /// repos push config.
+    pub fn push_all(&self, all_branches: bool, tags: bool) -> RunReport {
+        debug!("exectuting push_all");
+        let branches = all_branches.then_some(PushBranches::All);
+        let missing = self.missing_upstreams(false);
+        let report = self.on_all_repos_spinner("push", |repo| repo.push_with(branches, tags));
+        self.print_created_upstreams(&missing);
+        report
+    }
+    /// Returns the repos whose branch is pushed with --set-upstream, as
+    /// their category, name and branch, see `Repo::missing_upstream`
+    ///
+    /// For quick and fast, repos pushing to their review branch are left out,
+    /// as that doesn't set an upstream, see `Repo::push_quick`.
+    fn missing_upstreams(&self, quick: bool) -> Vec<(&String, &String, String)> {
+        self.repos_interactive_last()
+            .into_iter()
+            .filter(|(_, _, repo)| !(quick && repo.reviews()))
+            .filter_map(|(category, name, repo)| {
+                repo.missing_upstream()
+                    .map(|branch| (category, name, branch))
+            })
+            .collect()
+    }
+    /// Says which of missing got an upstream during a run, unless the settings
+    /// are quiet
+    fn print_created_upstreams(&self, missing: &[(&String, &String, String)]) {
+        if self.settings.quiet {
+            return;
+        }
+        for (category, name, branch) in missing {
+            let created = self
+                .find_repo(category, name)
+                .map_or(false, |repo| repo.has_upstream());
+            if created {
+                eprintln!(
+                    "{} {category} {name}: upstream of {branch} set to origin/{branch}",
+                    result_str(true)
+                );
+            }
+        }
+    }
+    /// Removes untracked files in all repositories with the clean flag.
+    ///
+    /// What would be removed is shown first, and unless settings force it,
+    /// confirm is asked before removing anything.
+    pub fn clean_all<F>(&self, confirm: F)
+    where
+        F: Fn(&str) -> bool,
+    {
+        debug!("exectuting clean_all");
+        let mut repos: Vec<(&String, &String, &Repo)> = self
+            .categories
+            .iter()
+            .flat_map(|(category, cat)| {
+                cat.repos
+                    .iter()
+                    .flatten()
+                    .map(move |(name, repo)| (category, name, repo))
+            })
+            .collect();
+        repos.sort_by_key(|(category, name, _)| (*category, *name));
+        for (category, name, repo) in repos {
+            let Some(preview) = repo.clean_preview() else {
+                continue;
+            };
+            if preview.is_empty() {
+                continue;
+            }
+            eprintln!("{category} {name}:");
+            for path in &preview {
+                eprintln!("  {path}");
+            }
+            if !self.settings.force
+                && !confirm(&tr_with(
+                    Msg::CleanRepo,
+                    &[("category", category), ("name", name)],
+                ))
+            {
+                continue;
+            }
+            eprintln!(
+                "{} {}: clean",
+                result_str(repo.clean(&preview)),
+                repo.display_name()
+            );
+        }
+    }
+    /// Shows what op would commit in every repo, and asks confirm once
+    /// whether to go ahead, unless settings force it.
+    ///
+    /// Returns whether to run op.
+    pub fn preview_commits<F>(&self, op: &str, confirm: F) -> bool
+    where
+        F: Fn(&str) -> bool,
+    {
+        let mut changed = false;
+        for (category, name, repo) in self.repos_interactive_last() {
+            if repo.skips(op) {
+                continue;
+            }
+            match repo.commit_preview() {
+                Some(preview) if !preview.is_empty() => {
+                    changed = true;
+                    eprintln!("{category} {name}:");
+                    for line in preview.lines() {
+                        eprintln!("  {line}");
+                    }
+                }
+                _ => (),
+            }
+        }
+        if !changed {
+            eprintln!("{}", tr(Msg::NothingToCommit));
+        }
+        self.settings.force || confirm(&tr_with(Msg::RunOp, &[("op", op)]))
+    }
+    /// Applies the patch at patch to all repos, or only those in category and
+    /// the categories nested in it.
+    ///
+    /// Returns whether it applied everywhere, or an error if there is no such
+    /// category.
+    pub fn apply_all(&self, patch: &Path, category: Option<&str>) -> Result<bool, String> {
+        debug!("exectuting apply_all");
+        let mut applied = true;
+        for (category, name, repo) in self.repos_in(category)? {
+            match repo.apply_patch(patch) {
+                Ok(()) => eprintln!("{} {category} {name}: apply", result_str(true)),
+                Err(err) => {
+                    applied = false;
+                    eprintln!("{} {category} {name}: apply", result_str(false));
+                    for line in err.lines() {
+                        eprintln!("  {line}");
+                    }
+                }
+            }
+        }
+        Ok(applied)
+    }
+    /// Returns the repos of category and the categories nested in it, or of
+    /// all categories, sorted by category and name, or an error if there is
+    /// no such category.
+    fn repos_in(&self, category: Option<&str>) -> Result<Vec<(&String, &String, &Repo)>, String> {
+        if let Some(category) = category {
+            if !self
+                .categories
+                .keys()
+                .any(|name| Config::in_category(name, category))
+            {
+                return Err(with_suggestion(
+                    format!("no category {category}"),
+                    category,
+                    self.categories.keys().map(String::as_str),
+                ));
+            }
+        }
+        let mut repos: Vec<(&String, &String, &Repo)> = self
+            .categories
+            .iter()
+            .filter(|(name, _)| {
+                category.map_or(true, |category| Config::in_category(name, category))
+            })
+            .flat_map(|(category, cat)| {
+                cat.repos
+                    .iter()
+                    .flatten()
+                    .map(move |(name, repo)| (category, name, repo))
+            })
+            .collect();
+        repos.sort_by_key(|(category, name, _)| (*category, *name));
+        Ok(repos)
+    }
+    /// Renames the branch old to new in every repo of category, or all repos,
+    /// that has it.
+    ///
+    /// Returns whether it renamed without errors, or an error if there is no
+    /// such category.
+    pub fn rename_branch_all(
+        &self,
+        old: &str,
+        new: &str,
+        category: Option<&str>,
+    ) -> Result<bool, String> {
+        debug!("exectuting rename_branch_all");
+        let mut renamed = true;
+        for (category, name, repo) in self.repos_in(category)? {
+            match repo.rename_branch(old, new) {
+                Ok(true) => eprintln!(
+                    "{} {category} {name}: rename {old} to {new}",
+                    result_str(true)
+                ),
+                Ok(false) => info!("{category} {name} has no branch {old}"),
+                Err(err) => {
+                    renamed = false;
+                    eprintln!("{} {category} {name}: rename: {err}", result_str(false));
+                }
+            }
+        }
+        Ok(renamed)
+    }
+    /// Prunes branches in every repo of category, or all repos, see
+    /// `Repo::prune_branches`.
+    ///
+    /// Returns whether it pruned without errors, or an error if there is no
+    /// such category.
+    pub fn prune_branches_all(&self, merged: bool, category: Option<&str>) -> Result<bool, String> {
+        debug!("exectuting prune_branches_all");
+        let mut pruned = true;
+        for (category, name, repo) in self.repos_in(category)? {
+            match repo.prune_branches(merged, self.default_branch(repo).as_deref()) {
+                Ok(deleted) => {
+                    eprintln!("{} {category} {name}: prune", result_str(true));
+                    for branch in deleted {
+                        eprintln!("  deleted {branch}");
+                    }
+                }
+                Err(err) => {
+                    pruned = false;
+                    eprintln!("{} {category} {name}: prune: {err}", result_str(false));
+                }
+            }
+        }
+        Ok(pruned)
+    }
+    /// Fast-forwards every repo of category, or all repos, to the review
+    /// branches on origin, and pushes those that moved, see
+    /// `Repo::merge_reviews`
+    ///
+    /// Returns whether it merged without errors, or an error if there is no
+    /// such category.
+    pub fn merge_reviews_all(&self, category: Option<&str>) -> Result<bool, String> {
+        debug!("exectuting merge_reviews_all");
+        let mut all_merged = true;
+        for (category, name, repo) in self.repos_in(category)? {
+            if repo.status() == RepoStatus::Missing {
+                continue;
+            }
+            let result = repo.merge_reviews().and_then(|merged| {
+                if merged.is_empty() || repo.push() {
+                    Ok(merged)
+                } else {
+                    Err(format!("merged {}, but failed to push", merged.join(", ")))
+                }
+            });
+            match result {
+                Ok(merged) if merged.is_empty() => info!("{category} {name} has nothing to review"),
+                Ok(merged) => {
+                    eprintln!("{} {category} {name}: review merge", result_str(true));
+                    for branch in merged {
+                        eprintln!("  merged {branch}");
+                    }
+                }
+                Err(err) => {
+                    all_merged = false;
+                    eprintln!(
+                        "{} {category} {name}: review merge: {err}",
+                        result_str(false)
+                    );
+                }
+            }
+        }
+        Ok(all_merged)
     }
     /// Tries to clone all repossitories, skips if fail.
-    pub fn clone_all(&self) {
+    pub fn clone_all(&self) -> RunReport {
         debug!("exectuting clone_all");
-        self.on_all_repos_spinner("clone", Repo::clone);
+        let report = self.on_all_repos_spinner("clone", |repo| match self.remote_url(repo) {
+            Some(url) => repo.clone_url(&url),
+            None => repo.clone(),
+        });
+        self.hooks_all();
+        self.remotes_all();
+        report
+    }
+    /// Points origin of all cloned repos at their url with the ssh host alias
+    /// of their category, if they still point at their plain url, and does
+    /// the same for the url origin pushes to, if they have a push_remote
+    ///
+    /// Remotes the user has set to something else are left alone.
+    pub fn remotes_all(&self) {
+        debug!("exectuting remotes_all");
+        for (category, name, repo) in self.repos_interactive_last() {
+            if repo.status() == RepoStatus::Missing {
+                continue;
+            }
+            let pulls = (
+                repo.pull_remote.as_ref().or(repo.url.as_ref()),
+                self.remote_url(repo),
+                repo.origin_url(),
+            );
+            let pushes = (
+                repo.push_remote.as_ref(),
+                self.push_url(repo),
+                repo.push_url(),
+            );
+            for (is_push, (plain, url, current)) in [(false, pulls), (true, pushes)] {
+                let (Some(plain), Some(url)) = (plain, url) else {
+                    continue;
+                };
+                if &url == plain || current.as_ref() != Some(plain) {
+                    continue;
+                }
+                let set = if is_push {
+                    repo.set_push_url(&url)
+                } else {
+                    repo.set_origin_url(&url)
+                };
+                let result = result_str(set);
+                if !self.settings.quiet {
+                    let remote = if is_push {
+                        "origin pushes to"
+                    } else {
+                        "origin is"
+                    };
+                    eprintln!("{result} {category} {name}: {remote} {url}");
+                }
+            }
+        }
+    }
+    /// Sets core.hooksPath in all cloned repos of categories with a hooks_dir.
+    pub fn hooks_all(&self) {
+        debug!("exectuting hooks_all");
+        let mut categories: Vec<(&String, &Category)> = self.categories.iter().collect();
+        categories.sort_by_key(|(name, _)| *name);
+        for (_, category) in categories {
+            let Some(hooks_dir) = category.hooks_dir.as_deref().map(expand_tilde) else {
+                continue;
+            };
+            let mut repos: Vec<(&String, &Repo)> = category.repos.iter().flatten().collect();
+            repos.sort_by_key(|(name, _)| *name);
+            for (_, repo) in repos {
+                if repo.status() == RepoStatus::Missing
+                    || repo.hooks_path().as_ref() == Some(&hooks_dir)
+                {
+                    continue;
+                }
+                let result = if repo.set_hooks_path(&hooks_dir) {
+                    result_str(true)
+                } else {
+                    result_str(false)
+                };
+                if !self.settings.quiet {
+                    eprintln!("{result} {}: hooks", repo.display_name());
+                }
+            }
+        }
     }
     /// Tries to add all work in all repossitories, skips if fail.
-    pub fn add_all(&self) {
+    pub fn add_all(&self) -> RunReport {
         debug!("exectuting clone_all");
-        self.on_all_repos_spinner("add", Repo::add_all);
+        self.on_all_repos_spinner("add", Repo::add_all)
     }
     /// Tries to commit all repossitories one at a time, skips if fail.
-    pub fn commit_all(&self) {
+    ///
+    /// The editor of each commit needs the terminal to itself, so commits
+    /// never run at once, whatever the parallelism.
+    pub fn commit_all(&self) -> RunReport {
         debug!("exectuting clone_all");
-        self.on_all_repos_spinner("commit", Repo::commit);
+        self.on_all_repos_spinner_on("commit", 1, |repo| self.commit_editor(repo))
+    }
+    /// Tries to commit all repossitories with a commit per top-level
+    /// directory, skips if fail.
+    pub fn commit_all_split(&self, msg: Option<&str>) -> RunReport {
+        debug!("exectuting commit_all_split");
+        self.on_all_repos_spinner("commit", |repo| self.commit_repo_split(repo, msg))
     }
     /// Tries to commit all repossitories with msg, skips if fail.
-    pub fn commit_all_msg(&self, msg: &str) {
+    pub fn commit_all_msg(&self, msg: Option<&str>) -> RunReport {
         debug!("exectuting clone_all");
-        self.on_all_repos_spinner("commit", |repo| repo.commit_with_msg(msg));
+        self.on_all_repos_spinner("commit", |repo| self.commit_repo(repo, msg, QUICK_COMMIT))
     }
     /// Tries to pull, add all, commit with msg "quick commit", and push all
     /// repositories, skips if fail.
-    pub fn quick(&self, msg: &'static str) {
+    pub fn quick(&self, msg: Option<&str>) -> RunReport {
         debug!("exectuting quick");
         let series: Vec<SeriesItem> = vec![
             SeriesItem {
                 operation: "pull",
-                closure: Box::new(Repo::pull),
+                closure: Box::new(move |repo: &Repo| {
+                    repo.pull_from(self.default_branch(repo).as_deref())
+                }),
+                on_failure: OnFailure::SkipRepo,
             },
             SeriesItem {
                 operation: "add",
                 closure: Box::new(Repo::add_all),
+                on_failure: OnFailure::Continue,
             },
             SeriesItem {
                 operation: "commit",
-                closure: Box::new(move |repo: &Repo| repo.commit_with_msg(msg)),
+                closure: Box::new(move |repo: &Repo| self.commit_repo(repo, msg, QUICK_COMMIT)),
+                on_failure: OnFailure::Continue,
             },
             SeriesItem {
                 operation: "push",
-                closure: Box::new(Repo::push),
+                closure: Box::new(Repo::push_quick),
+                on_failure: OnFailure::Continue,
             },
         ];
-        run_series!(self, series);
+        let missing = self.missing_upstreams(true);
+        let report = run_series!(self, series);
+        self.print_created_upstreams(&missing);
+        report
     }
     /// Tries to pull, add all, commit with msg "quick commit", and push all
     /// repositories, skips if fail.
-    pub fn fast(&self, msg: &'static str) {
+    pub fn fast(&self, msg: Option<&str>) -> RunReport {
         debug!("exectuting fast");
         let series: Vec<SeriesItem> = vec![
             SeriesItem {
                 operation: "pull",
-                closure: Box::new(Repo::pull),
+                closure: Box::new(move |repo: &Repo| {
+                    repo.pull_from(self.default_branch(repo).as_deref())
+                }),
+                on_failure: OnFailure::Continue,
             },
             SeriesItem {
                 operation: "add",
                 closure: Box::new(Repo::add_all),
+                on_failure: OnFailure::Continue,
             },
             SeriesItem {
                 operation: "commit",
-                closure: Box::new(move |repo: &Repo| repo.commit_with_msg(msg)),
+                closure: Box::new(move |repo: &Repo| self.commit_repo(repo, msg, FAST_COMMIT)),
+                on_failure: OnFailure::Continue,
             },
             SeriesItem {
                 operation: "push",
-                closure: Box::new(Repo::push),
+                closure: Box::new(Repo::push_quick),
+                on_failure: OnFailure::Continue,
             },
         ];
-        run_series!(self, series, true);
+        let missing = self.missing_upstreams(true);
+        let report = run_series!(self, series, true);
+        self.print_created_upstreams(&missing);
+        report
+    }
+    /// Narrows the config down to category, or only repo in it if given,
+    /// the same as with --repos-from, returning the target it was narrowed to
+    ///
+    /// Fails if there is no such category or repo.
+    pub fn narrow_to(&mut self, category: &str, repo: Option<&str>) -> Result<Target, String> {
+        let target = Target {
+            category: category.to_string(),
+            repo: repo.map(str::to_string),
+        };
+        let selection = Selection {
+            targets: vec![target.clone()],
+        };
+        selection.apply(self)?;
+        debug!("narrowed to {target}");
+        Ok(target)
+    }
+    /// Quick, on the repos of category, and its subcategories, only, see
+    /// `Config::narrow_to`
+    ///
+    /// The config is left narrowed down to category.
+    pub fn quick_category(
+        &mut self,
+        category: &str,
+        msg: Option<&str>,
+    ) -> Result<RunReport, String> {
+        debug!("exectuting quick_category on {category}");
+        self.narrow_to(category, None)?;
+        Ok(self.quick(msg))
+    }
+    /// Quick, on the repo named repo in category only, see
+    /// `Config::narrow_to`
+    ///
+    /// The config is left narrowed down to repo.
+    pub fn quick_repo(
+        &mut self,
+        category: &str,
+        repo: &str,
+        msg: Option<&str>,
+    ) -> Result<RunReport, String> {
+        debug!("exectuting quick_repo on {category} {repo}");
+        self.narrow_to(category, Some(repo))?;
+        Ok(self.quick(msg))
     }
     /// Tries to link all repositories, skips if fail.
-    pub fn link_all(&self) {
+    pub fn link_all(&self) -> RunReport {
         debug!("exectuting link_all");
-        self.on_all_links_spinner("link", Link::link);
+        let vars = self.template_vars();
+        self.on_all_links_spinner("link", |link| link.link_with(&vars, self.settings.force))
     }
 }
@@ -0,0 +1,163 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Renders the structure of the config as a graph, for documentation and for
+//! seeing how a larger config hangs together
+//!
+//! Categories contain their repos and links, and a link depends on the repo
+//! its tx is in, as it can't be linked before that repo is cloned.
+
+use clap::ValueEnum;
+use log::{debug, error, info, trace, warn};
+use std::path::Path;
+
+use crate::git::{Config, PathOwner};
+
+/// The language the graph is written in
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, ValueEnum)]
+pub enum GraphFormat {
+    /// Graphviz
+    #[default]
+    Dot,
+    /// Mermaid, which renders in markdown on most forges
+    Mermaid,
+}
+
+/// A repo or link in the graph
+struct Node {
+    id: String,
+    label: String,
+    is_link: bool,
+}
+
+/// A category and its nodes
+struct Cluster {
+    id: String,
+    label: String,
+    nodes: Vec<Node>,
+}
+
+/// Builds the clusters of config, and the edges between their nodes, all
+/// sorted by name, so the same config always gives the same graph
+///
+/// Ids are positional, as names can have characters the formats don't allow
+/// in ids.
+fn structure(config: &Config) -> (Vec<Cluster>, Vec<(String, String)>) {
+    let mut categories: Vec<_> = config.categories.iter().collect();
+    categories.sort_by_key(|(name, _)| *name);
+    let mut clusters = vec![];
+    let mut repo_ids = vec![];
+    let mut links = vec![];
+    for (i, (category_name, category)) in categories.into_iter().enumerate() {
+        let mut nodes = vec![];
+        let mut repos: Vec<_> = category.repos.iter().flatten().collect();
+        repos.sort_by_key(|(name, _)| *name);
+        for (j, (name, repo)) in repos.into_iter().enumerate() {
+            let id = format!("c{i}r{j}");
+            repo_ids.push(((category_name, name), id.clone()));
+            nodes.push(Node {
+                id,
+                label: if repo.archived {
+                    format!("{name} (archived)")
+                } else {
+                    name.clone()
+                },
+                is_link: false,
+            });
+        }
+        let mut category_links: Vec<_> = category.links.iter().flatten().collect();
+        category_links.sort_by_key(|(name, _)| *name);
+        for (j, (name, link)) in category_links.into_iter().enumerate() {
+            let id = format!("c{i}l{j}");
+            links.push((id.clone(), link));
+            nodes.push(Node {
+                id,
+                label: name.clone(),
+                is_link: true,
+            });
+        }
+        clusters.push(Cluster {
+            id: format!("c{i}"),
+            label: category_name.clone(),
+            nodes,
+        });
+    }
+    let mut edges = vec![];
+    for (link_id, link) in links {
+        for owner in config.which(Path::new(&link.tx)) {
+            let PathOwner::Repo { category, name } = owner else {
+                continue;
+            };
+            if let Some((_, repo_id)) = repo_ids
+                .iter()
+                .find(|((c, n), _)| *c == category && *n == name)
+            {
+                edges.push((link_id.clone(), repo_id.clone()));
+            }
+        }
+    }
+    (clusters, edges)
+}
+
+fn escape_dot(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_mermaid(label: &str) -> String {
+    label.replace('"', "#quot;")
+}
+
+/// Renders the config as a graph in format
+pub fn render(config: &Config, format: GraphFormat) -> String {
+    let (clusters, edges) = structure(config);
+    let mut out = String::new();
+    match format {
+        GraphFormat::Dot => {
+            out.push_str("digraph seidr {\n  rankdir=LR;\n");
+            for (i, cluster) in clusters.iter().enumerate() {
+                out.push_str(&format!(
+                    "  subgraph cluster_{i} {{\n    label=\"{}\";\n",
+                    escape_dot(&cluster.label)
+                ));
+                for node in &cluster.nodes {
+                    let shape = if node.is_link { "note" } else { "box" };
+                    out.push_str(&format!(
+                        "    {} [label=\"{}\", shape={shape}];\n",
+                        node.id,
+                        escape_dot(&node.label)
+                    ));
+                }
+                out.push_str("  }\n");
+            }
+            for (from, to) in &edges {
+                out.push_str(&format!("  {from} -> {to};\n"));
+            }
+            out.push_str("}\n");
+        }
+        GraphFormat::Mermaid => {
+            out.push_str("flowchart LR\n");
+            for cluster in &clusters {
+                out.push_str(&format!(
+                    "  subgraph {} [\"{}\"]\n",
+                    cluster.id,
+                    escape_mermaid(&cluster.label)
+                ));
+                for node in &cluster.nodes {
+                    let (open, close) = if node.is_link { (">", "]") } else { ("[", "]") };
+                    out.push_str(&format!(
+                        "    {}{open}\"{}\"{close}\n",
+                        node.id,
+                        escape_mermaid(&node.label)
+                    ));
+                }
+                out.push_str("  end\n");
+            }
+            for (from, to) in &edges {
+                out.push_str(&format!("  {from} --> {to}\n"));
+            }
+        }
+    }
+    out
+}
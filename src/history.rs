@@ -0,0 +1,162 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! History of runs, kept in the state dir
+//!
+//! Every run that operates on repos or links is recorded in its own file in
+//! the history directory, named by the run id, so the history sorts
+//! chronologically and syncs between machines along with the rest of the
+//! state.
+
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::environment::Environment;
+use crate::report::RunReport;
+use crate::trash::run_id;
+use crate::utils::dir::{hostname, state_dir};
+
+/// A single recorded run
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub id: String,
+    pub host: String,
+    /// The arguments seidr was run with
+    pub command: String,
+    /// The targets the run was narrowed down to, empty if it wasn't
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scope: Vec<String>,
+    /// When the run started, in seconds since the unix epoch
+    pub started: u64,
+    pub duration_ms: u64,
+    pub report: RunReport,
+    /// What the run ran with, missing in records from before it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<Environment>,
+}
+
+impl RunRecord {
+    /// Whether the run touched the repo, given as `name` or `category/name`
+    pub fn has_repo(&self, repo: &str) -> bool {
+        self.report.categories.iter().any(|category| {
+            category.repos.iter().any(|result| {
+                result.name == repo || format!("{}/{}", category.name, result.name) == repo
+            })
+        })
+    }
+}
+
+/// The history directory
+pub struct History {
+    dir: PathBuf,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::at(state_dir().join("history"))
+    }
+}
+
+impl History {
+    /// Uses dir as the history directory
+    pub fn at(dir: PathBuf) -> Self {
+        History { dir }
+    }
+    /// Records a run of this process
+    pub fn record(
+        &self,
+        command: String,
+        scope: Vec<String>,
+        started: u64,
+        duration_ms: u64,
+        report: RunReport,
+    ) -> io::Result<()> {
+        let record = RunRecord {
+            id: run_id().to_string(),
+            host: hostname(),
+            command,
+            scope,
+            started,
+            duration_ms,
+            report,
+            environment: Some(Environment::capture()),
+        };
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("{}.yaml", record.id));
+        debug!("recording run in {}", path.display());
+        let yaml = serde_yaml::to_string(&record)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        fs::write(path, yaml)
+    }
+    /// Moves the results of a repo in every recorded run to its new name,
+    /// given as (category, name)
+    ///
+    /// Returns the number of records that changed.
+    pub fn rename_repo(&self, from: (&str, &str), to: (&str, &str)) -> io::Result<usize> {
+        let mut changed = 0;
+        for mut record in self.list()? {
+            if !record.report.rename_repo(from, to) {
+                continue;
+            }
+            let yaml = serde_yaml::to_string(&record)
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+            fs::write(self.dir.join(format!("{}.yaml", record.id)), yaml)?;
+            changed += 1;
+        }
+        Ok(changed)
+    }
+    /// Lists recorded runs, oldest first
+    ///
+    /// Records that can't be read are skipped with a warning.
+    pub fn list(&self) -> io::Result<Vec<RunRecord>> {
+        if !self.dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut paths: Vec<PathBuf> = fs::read_dir(&self.dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<_>>()?;
+        paths.sort();
+        let mut records = vec![];
+        for path in paths {
+            let parsed = fs::read_to_string(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|yaml| serde_yaml::from_str(&yaml).map_err(|err| err.to_string()));
+            match parsed {
+                Ok(record) => records.push(record),
+                Err(err) => warn!("skipping {}: {err}", path.display()),
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// Formats secs since the unix epoch as a UTC date and time
+pub fn format_time(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+    // Civil date from days since the epoch, after Howard Hinnant
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02} UTC",
+        time / 3600,
+        time % 3600 / 60
+    )
+}
@@ -0,0 +1,114 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Stable identities of repos, so their state survives renames
+//!
+//! State like the history refers to repos by category and name, so renaming a
+//! repo in the config orphans it. Repos with an `id` have the name they were
+//! last seen under kept in the state dir; when that changes, the state is
+//! migrated to the new name. Repos without an id can't be followed, and their
+//! orphaned state is reported by doctor.
+
+use log::{debug, error, info, trace, warn};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::git::Config;
+use crate::history::History;
+use crate::utils::dir::state_dir;
+
+/// A repo that was renamed, as (category, name)
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Rename {
+    pub id: String,
+    pub from: (String, String),
+    pub to: (String, String),
+}
+
+/// The names repos were last seen under, by id
+pub struct Ids {
+    path: PathBuf,
+    names: BTreeMap<String, (String, String)>,
+}
+
+impl Ids {
+    /// Loads the ids from the state dir
+    pub fn load() -> io::Result<Self> {
+        Self::load_from(state_dir().join("ids.yaml"))
+    }
+    /// Loads the ids from path, which doesn't have to exist yet
+    pub fn load_from(path: PathBuf) -> io::Result<Self> {
+        let names = match fs::read_to_string(&path) {
+            Ok(yaml) => serde_yaml::from_str(&yaml)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err),
+        };
+        Ok(Ids { path, names })
+    }
+    /// Finds the repos in config whose id was last seen under another name
+    pub fn renames(&self, config: &Config) -> Vec<Rename> {
+        let mut renames = vec![];
+        for (category, cat) in config.categories.iter() {
+            for (name, repo) in cat.repos.iter().flatten() {
+                let Some(id) = &repo.id else {
+                    continue;
+                };
+                match self.names.get(id) {
+                    Some(from) if *from != (category.clone(), name.clone()) => {
+                        renames.push(Rename {
+                            id: id.clone(),
+                            from: from.clone(),
+                            to: (category.clone(), name.clone()),
+                        })
+                    }
+                    _ => (),
+                }
+            }
+        }
+        renames.sort_by(|a, b| a.id.cmp(&b.id));
+        renames
+    }
+    /// Records the names of every repo in config with an id, and saves them
+    pub fn record(&mut self, config: &Config) -> io::Result<()> {
+        let before = self.names.clone();
+        for (category, cat) in config.categories.iter() {
+            for (name, repo) in cat.repos.iter().flatten() {
+                if let Some(id) = &repo.id {
+                    self.names
+                        .insert(id.clone(), (category.clone(), name.clone()));
+                }
+            }
+        }
+        if self.names == before {
+            return Ok(());
+        }
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let yaml = serde_yaml::to_string(&self.names)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        fs::write(&self.path, yaml)
+    }
+}
+
+/// Migrates the history of every renamed repo in config to its new name
+///
+/// Returns the renames that were migrated.
+pub fn migrate(config: &Config, ids: &mut Ids, history: &History) -> io::Result<Vec<Rename>> {
+    let renames = ids.renames(config);
+    for rename in &renames {
+        let (from, to) = (&rename.from, &rename.to);
+        let changed = history.rename_repo((&from.0, &from.1), (&to.0, &to.1))?;
+        info!(
+            "{} was renamed from {}/{} to {}/{}, migrated {changed} history records",
+            rename.id, from.0, from.1, to.0, to.1
+        );
+    }
+    ids.record(config)?;
+    Ok(renames)
+}
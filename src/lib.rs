@@ -0,0 +1,46 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+#[allow(unused)]
+mod archive;
+#[cfg(feature = "async")]
+#[allow(unused)]
+pub mod async_engine;
+#[allow(unused)]
+mod branches;
+#[allow(unused)]
+mod condition;
+#[allow(unused)]
+mod copy;
+#[allow(unused)]
+mod environment;
+#[allow(unused)]
+pub mod git;
+#[allow(unused)]
+mod history;
+#[allow(unused)]
+mod leaks;
+#[allow(unused)]
+pub mod parallel;
+#[allow(unused)]
+mod progress;
+#[allow(unused)]
+pub mod report;
+#[allow(unused)]
+mod schedule;
+#[allow(unused)]
+mod secrets;
+#[allow(unused)]
+pub mod selection;
+#[allow(unused)]
+pub mod settings;
+#[allow(unused)]
+mod state;
+#[allow(unused)]
+mod template;
+#[allow(unused)]
+mod trash;
+#[allow(unused)]
+mod utils;
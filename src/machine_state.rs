@@ -0,0 +1,126 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! A summary of the state of the machine, written after runs
+//!
+//! With `machine_state: true` in the config, every run that operates on repos
+//! or links writes the state of the repos, with the commit they are on, and of
+//! the links to `$XDG_STATE_HOME/seidr/machine.json`. Activation scripts of
+//! NixOS or home-manager can then assert on it, or run seidr when it is off,
+//! without parsing what seidr prints.
+//!
+//! The file is replaced atomically, so a reader never sees half of it.
+
+use log::{debug, error, info, trace, warn};
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::git::{Config, LinkListing, RepoStatus};
+use crate::utils::dir::{hostname, state_dir};
+
+/// The state of a single repo
+#[derive(PartialEq, Eq, Debug, Serialize)]
+pub struct RepoState {
+    pub category: String,
+    pub name: String,
+    /// Where the repo is checked out
+    pub path: String,
+    pub status: RepoStatus,
+    /// The commit HEAD is on, if the repo is cloned
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub head: Option<String>,
+    /// The branch checked out, if HEAD isn't detached
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+}
+
+/// The state of the repos and links of a config, after a run
+#[derive(PartialEq, Eq, Debug, Serialize)]
+pub struct MachineState {
+    /// The version of seidr that wrote the file
+    pub seidr: String,
+    pub host: String,
+    /// When the file was written, in seconds since the unix epoch
+    pub written: u64,
+    /// The arguments of the run that wrote the file
+    pub command: String,
+    /// The targets the run was narrowed down to, empty if it wasn't, in which
+    /// case only those are in the file
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub scope: Vec<String>,
+    /// Whether every operation of the run succeeded
+    pub succeeded: bool,
+    pub repos: Vec<RepoState>,
+    pub links: Vec<LinkListing>,
+}
+
+impl MachineState {
+    /// Captures the state of the repos and links of config, sorted by
+    /// category and name
+    pub fn capture(
+        config: &Config,
+        command: String,
+        scope: Vec<String>,
+        succeeded: bool,
+        now: u64,
+    ) -> Self {
+        let mut repos: Vec<RepoState> = config
+            .categories
+            .iter()
+            .flat_map(|(category, cat)| {
+                cat.repos.iter().flatten().map(move |(name, repo)| {
+                    let status = repo.status();
+                    let cloned = status != RepoStatus::Missing;
+                    RepoState {
+                        category: category.clone(),
+                        name: name.clone(),
+                        path: repo.checkout_dir(),
+                        status,
+                        head: repo.head_commit().filter(|_| cloned),
+                        branch: repo.head_branch().filter(|_| cloned),
+                    }
+                })
+            })
+            .collect();
+        repos.sort_by(|a, b| (&a.category, &a.name).cmp(&(&b.category, &b.name)));
+        MachineState {
+            seidr: env!("CARGO_PKG_VERSION").to_string(),
+            host: hostname(),
+            written: now,
+            command,
+            scope,
+            succeeded,
+            repos,
+            links: config.list_links(&[]),
+        }
+    }
+    /// Writes the state to path as json, replacing what was there
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+        let written = File::create(&tmp).and_then(|mut file| {
+            file.write_all(json.as_bytes())?;
+            file.write_all(b"\n")?;
+            file.sync_all()
+        });
+        if let Err(err) = written.and_then(|_| fs::rename(&tmp, path)) {
+            let _ = fs::remove_file(&tmp);
+            return Err(err);
+        }
+        debug!("wrote machine state to {}", path.display());
+        Ok(())
+    }
+}
+
+/// Where the machine state is written
+pub fn default_path() -> PathBuf {
+    state_dir().join("machine.json")
+}
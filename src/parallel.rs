@@ -0,0 +1,163 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Running operations on several repos at once
+//!
+//! Every operation has a class, by what bounds it. Operations that talk to a
+//! remote mostly wait on the network, so many of them can run at once, while
+//! local operations compete for the disk and cpu. Each class has its own limit
+//! in the `parallelism` section of the config, and both default to 1, so runs
+//! are sequential unless configured otherwise.
+
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+/// What bounds an operation
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OpClass {
+    /// Talks to a remote, like clone, pull and push
+    Network,
+    /// Only touches the checkout, like add and commit
+    Local,
+}
+
+impl OpClass {
+    /// Classifies an operation by its name
+    pub fn of(op: &str) -> Self {
+        match op {
+            "clone" | "pull" | "push" | "fetch" | "deepen" => OpClass::Network,
+            _ => OpClass::Local,
+        }
+    }
+}
+
+fn one() -> usize {
+    1
+}
+
+/// How many operations of each class may run at once
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Parallelism {
+    #[serde(default = "one")]
+    pub network: usize,
+    #[serde(default = "one")]
+    pub local: usize,
+}
+
+impl Default for Parallelism {
+    fn default() -> Self {
+        Parallelism {
+            network: 1,
+            local: 1,
+        }
+    }
+}
+
+impl Parallelism {
+    /// The limit for class, at least 1
+    pub fn limit(&self, class: OpClass) -> usize {
+        match class {
+            OpClass::Network => self.network,
+            OpClass::Local => self.local,
+        }
+        .max(1)
+    }
+    /// The number of workers needed to reach every limit
+    pub fn workers(&self) -> usize {
+        self.limit(OpClass::Network).max(self.limit(OpClass::Local))
+    }
+}
+
+/// A counting semaphore
+struct Permits {
+    available: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl Permits {
+    fn new(count: usize) -> Self {
+        Permits {
+            available: Mutex::new(count),
+            freed: Condvar::new(),
+        }
+    }
+    fn run<T>(&self, f: impl FnOnce() -> T) -> T {
+        let mut available = self
+            .freed
+            .wait_while(self.available.lock().expect("permits poisoned"), |n| {
+                *n == 0
+            })
+            .expect("permits poisoned");
+        *available -= 1;
+        drop(available);
+        let result = f();
+        *self.available.lock().expect("permits poisoned") += 1;
+        self.freed.notify_one();
+        result
+    }
+}
+
+/// The limits of every class during a run
+pub struct Limits {
+    network: Permits,
+    local: Permits,
+}
+
+impl Limits {
+    pub fn new(parallelism: &Parallelism) -> Self {
+        Limits {
+            network: Permits::new(parallelism.limit(OpClass::Network)),
+            local: Permits::new(parallelism.limit(OpClass::Local)),
+        }
+    }
+    /// Runs f once fewer than the limit of class are running
+    pub fn run<T>(&self, class: OpClass, f: impl FnOnce() -> T) -> T {
+        match class {
+            OpClass::Network => self.network.run(f),
+            OpClass::Local => self.local.run(f),
+        }
+    }
+}
+
+/// Runs job on every item with up to workers threads, returning the results in
+/// the order of items
+///
+/// Items are started in order, so with a single worker this is the same as
+/// mapping job over items.
+pub fn run_all<I, T, F>(items: &[I], workers: usize, job: F) -> Vec<T>
+where
+    I: Sync,
+    T: Send,
+    F: Fn(&I) -> T + Sync,
+{
+    let workers = workers.clamp(1, items.len().max(1));
+    if workers == 1 {
+        return items.iter().map(job).collect();
+    }
+    debug!("running {} items on {workers} workers", items.len());
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<T>>> = Mutex::new(items.iter().map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(i) else {
+                    break;
+                };
+                let result = job(item);
+                results.lock().expect("results poisoned")[i] = Some(result);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .expect("results poisoned")
+        .into_iter()
+        .map(|result| result.expect("worker didn't finish its item"))
+        .collect()
+}
@@ -0,0 +1,209 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Interactive fuzzy picker for selecting categories and repos
+//!
+//! The picker is drawn on stderr, so stdout stays usable for the output of the
+//! command the selection is for.
+
+use log::{debug, error, info, trace, warn};
+use std::io::{self, Read, Write};
+
+use crate::git::Config;
+use crate::selection::{Selection, Target};
+use crate::utils::term::{color, RawMode, BOLD, CLEAR, GREEN, RESET, REVERSE};
+
+const HELP: &str = "type to filter  up/down: move  tab: toggle  enter: accept  ctrl-c: cancel";
+
+/// How many matches are shown at once
+const SHOWN: usize = 20;
+
+/// Scores how well query fuzzy matches candidate, None if it doesn't match
+///
+/// Every character of the query must appear in the candidate in order, case
+/// insensitively. Consecutive characters, and characters at the start of a
+/// word, score higher.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<usize> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for wanted in query.to_lowercase().chars() {
+        let found = candidate[position..]
+            .iter()
+            .position(|c| *c == wanted)
+            .map(|offset| position + offset)?;
+        score += 1;
+        if previous.map_or(false, |previous| previous + 1 == found) {
+            score += 4;
+        }
+        if found == 0 || matches!(candidate[found - 1], '/' | '_' | '-' | '.' | ' ') {
+            score += 2;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+struct Picker {
+    items: Vec<Target>,
+    labels: Vec<String>,
+    query: String,
+    /// Indices of items matching the query, best first
+    matches: Vec<usize>,
+    /// Index into matches of the highlighted item
+    cursor: usize,
+    picked: Vec<bool>,
+}
+
+impl Picker {
+    fn new(config: &Config) -> Self {
+        let mut items = vec![];
+        for (category, cat) in config.categories.iter() {
+            items.push(Target {
+                category: category.clone(),
+                repo: None,
+            });
+            for name in cat.repos.iter().flat_map(|repos| repos.keys()) {
+                items.push(Target {
+                    category: category.clone(),
+                    repo: Some(name.clone()),
+                });
+            }
+        }
+        items.sort_by(|a, b| (&a.category, &a.repo).cmp(&(&b.category, &b.repo)));
+        let labels = items.iter().map(ToString::to_string).collect();
+        let picked = vec![false; items.len()];
+        let mut picker = Picker {
+            items,
+            labels,
+            query: String::new(),
+            matches: vec![],
+            cursor: 0,
+            picked,
+        };
+        picker.filter();
+        picker
+    }
+
+    fn filter(&mut self) {
+        let mut scored: Vec<(usize, usize)> = self
+            .labels
+            .iter()
+            .enumerate()
+            .filter_map(|(i, label)| fuzzy_score(&self.query, label).map(|score| (score, i)))
+            .collect();
+        // Best score first, ties in alphabetical order
+        scored.sort_by(|(a_score, a), (b_score, b)| b_score.cmp(a_score).then(a.cmp(b)));
+        self.matches = scored.into_iter().map(|(_, i)| i).collect();
+        self.cursor = 0;
+    }
+
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        write!(out, "{CLEAR}{BOLD}> {}{RESET}\r\n", self.query)?;
+        write!(out, "{HELP}\r\n\r\n")?;
+        for (row, &i) in self.matches.iter().take(SHOWN).enumerate() {
+            let highlight = if row == self.cursor { REVERSE } else { "" };
+            let mark = if self.picked[i] { "*" } else { " " };
+            write!(
+                out,
+                "{}{mark}{RESET} {highlight}{}{RESET}\r\n",
+                color(GREEN),
+                self.labels[i]
+            )?;
+        }
+        if self.matches.len() > SHOWN {
+            write!(out, "  ... {} more\r\n", self.matches.len() - SHOWN)?;
+        }
+        out.flush()
+    }
+
+    fn down(&mut self) {
+        if self.cursor + 1 < self.matches.len().min(SHOWN) {
+            self.cursor += 1;
+        }
+    }
+
+    fn up(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    fn toggle(&mut self) {
+        if let Some(&i) = self.matches.get(self.cursor) {
+            self.picked[i] = !self.picked[i];
+            self.down();
+        }
+    }
+
+    /// The picked targets, or the highlighted one if none are picked
+    fn selection(self) -> Selection {
+        let highlighted = self
+            .matches
+            .get(self.cursor)
+            .map(|&i| self.items[i].clone());
+        let mut targets: Vec<Target> = self
+            .items
+            .into_iter()
+            .zip(self.picked)
+            .filter_map(|(target, picked)| picked.then_some(target))
+            .collect();
+        if targets.is_empty() {
+            targets.extend(highlighted);
+        }
+        Selection { targets }
+    }
+}
+
+/// Lets the user pick categories and repos from config
+///
+/// Returns None if the user cancelled.
+pub fn pick(config: &Config) -> io::Result<Option<Selection>> {
+    let mut picker = Picker::new(config);
+    let raw = RawMode::enable()?;
+    let mut stderr = io::stderr();
+    let mut stdin = io::stdin();
+    let mut key = [0u8; 1];
+    let accepted = loop {
+        picker.draw(&mut stderr)?;
+        if stdin.read(&mut key)? == 0 {
+            break false;
+        }
+        trace!("picker got key {:?}", key[0]);
+        match key[0] {
+            // ctrl-c
+            3 => break false,
+            b'\r' | b'\n' => break true,
+            b'\t' => picker.toggle(),
+            // ctrl-n and ctrl-p
+            14 => picker.down(),
+            16 => picker.up(),
+            // backspace
+            8 | 127 => {
+                picker.query.pop();
+                picker.filter();
+            }
+            // Arrow keys are sent as ESC [ A/B
+            0x1b => {
+                let mut seq = [0u8; 2];
+                stdin.read_exact(&mut seq)?;
+                match seq {
+                    [b'[', b'A'] => picker.up(),
+                    [b'[', b'B'] => picker.down(),
+                    _ => (),
+                }
+            }
+            c if c.is_ascii_graphic() || c == b' ' => {
+                picker.query.push(c as char);
+                picker.filter();
+            }
+            _ => (),
+        }
+    };
+    drop(raw);
+    eprint!("{CLEAR}");
+    stderr.flush()?;
+    Ok(accepted.then(|| picker.selection()))
+}
@@ -0,0 +1,97 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Running plugins, executables named `seidr-<name>` on PATH, as subcommands
+//!
+//! Like git, `seidr foo --bar` runs `seidr-foo --bar` when foo isn't a command
+//! of seidr itself. The plugin gets the path of the config in `SEIDR_CONFIG`,
+//! and the repos and categories the run is narrowed to in `SEIDR_SCOPE`, one
+//! per line, empty when it isn't narrowed. On stdin it gets the same as json,
+//! with the config as seidr resolved it, see `Input`.
+//!
+//! The json carries a version, which only changes when fields are removed or
+//! change meaning, so plugins keep working as fields are added.
+
+use log::{debug, error, info, trace, warn};
+use serde::Serialize;
+use std::env;
+use std::ffi::OsStr;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+
+use crate::git::Config;
+use crate::utils::dir::is_executable;
+
+/// What the executable of a plugin is named, before the name of the plugin
+pub const PREFIX: &str = "seidr-";
+
+/// The version of `Input`
+pub const INPUT_VERSION: u32 = 1;
+
+/// What a plugin gets as json on stdin
+#[derive(Debug, Serialize)]
+pub struct Input<'a> {
+    pub version: u32,
+    pub config_path: &'a Path,
+    pub scope: &'a [String],
+    /// The config with hosts applied and subcategories flattened
+    pub config: &'a Config,
+}
+
+/// Finds the executable of plugin name in the dirs of search_path, which is
+/// like PATH
+pub fn find(name: &str, search_path: &OsStr) -> Option<PathBuf> {
+    env::split_paths(search_path)
+        .map(|dir| dir.join(format!("{PREFIX}{name}")))
+        .find(|path| is_executable(path))
+}
+
+/// Lists the names of the plugins in the dirs of search_path, sorted
+pub fn list(search_path: &OsStr) -> Vec<String> {
+    let mut names: Vec<String> = env::split_paths(search_path)
+        .filter_map(|dir| dir.read_dir().ok())
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|entry| is_executable(&entry.path()))
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            name.strip_prefix(PREFIX).map(str::to_string)
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Runs the plugin at executable with args, giving it input, and returns how
+/// it exited
+pub fn run(executable: &Path, args: &[String], input: &Input) -> Result<ExitStatus, String> {
+    let json = serde_json::to_vec(input).map_err(|err| format!("failed to write json: {err}"))?;
+    let scope = input.scope.join("\n");
+    debug!("running plugin {}", executable.display());
+    let mut child = Command::new(executable)
+        .args(args)
+        .env("SEIDR_CONFIG", input.config_path)
+        .env("SEIDR_SCOPE", scope)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("failed to run {}: {err}", executable.display()))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    // Written on a thread, so a plugin that doesn't read it all still runs to
+    // the end, instead of both waiting on each other
+    let writer = thread::spawn(move || match stdin.write_all(&json) {
+        Err(err) if err.kind() != io::ErrorKind::BrokenPipe => {
+            warn!("failed to give the plugin its input: {err}")
+        }
+        _ => (),
+    });
+    let status = child
+        .wait()
+        .map_err(|err| format!("failed to wait on {}: {err}", executable.display()))?;
+    let _ = writer.join();
+    Ok(status)
+}
@@ -0,0 +1,136 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Showing the progress of an operation on many repos or links
+//!
+//! By default every repo gets a spinner, leaving a line behind when done. With
+//! `--progress compact`, a single status line is kept up to date instead, with
+//! counts and what is running, and only failures are left behind. When stderr
+//! isn't a terminal, there is nothing to update, so a plain line is written
+//! per result.
+
+use clap::ValueEnum;
+use log::{debug, error, info, trace, warn};
+use std::io::{self, IsTerminal, Write};
+use std::sync::Mutex;
+
+use crate::utils::strings::result_str;
+use crate::utils::term::{fit, Output};
+
+/// The names of running operations shown at most, before the rest are counted
+const SHOWN_RUNNING: usize = 3;
+
+/// How progress is shown
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, ValueEnum)]
+pub enum ProgressStyle {
+    /// A spinner per repo or link
+    #[default]
+    Spinners,
+    /// A single status line with counts
+    Compact,
+}
+
+#[derive(Default)]
+struct Counts {
+    done: usize,
+    failed: usize,
+    running: Vec<String>,
+}
+
+/// A single status line for op on total repos or links
+pub struct Compact {
+    op: String,
+    total: usize,
+    tty: bool,
+    counts: Mutex<Counts>,
+}
+
+impl Compact {
+    pub fn new(op: &str, total: usize) -> Self {
+        Compact {
+            op: op.to_string(),
+            total,
+            tty: io::stderr().is_terminal(),
+            counts: Mutex::new(Counts::default()),
+        }
+    }
+    /// Marks name as running
+    pub fn start(&self, name: &str) {
+        let mut counts = self.counts.lock().expect("progress poisoned");
+        counts.running.push(name.to_string());
+        self.draw(&counts);
+    }
+    /// Marks name as done, leaving a line behind if it failed, or always when
+    /// not on a terminal
+    pub fn finish(&self, name: &str, succeeded: bool) {
+        let mut counts = self.counts.lock().expect("progress poisoned");
+        if let Some(position) = counts.running.iter().position(|running| running == name) {
+            counts.running.remove(position);
+        }
+        counts.done += 1;
+        if !succeeded {
+            counts.failed += 1;
+        }
+        let result = result_str(succeeded);
+        if !self.tty {
+            eprintln!("{result} {name}: {}", self.op);
+        } else if !succeeded {
+            eprintln!("\r\x1b[2K{result} {name}: {}", self.op);
+        }
+        self.draw(&counts);
+    }
+    /// Clears the status line
+    pub fn end(&self) {
+        if self.tty {
+            eprint!("\r\x1b[2K");
+            let _ = io::stderr().flush();
+        }
+    }
+    fn draw(&self, counts: &Counts) {
+        if !self.tty {
+            return;
+        }
+        let line = status_line(
+            &self.op,
+            self.total,
+            counts.done,
+            counts.failed,
+            &counts.running,
+        );
+        // A line wider than the terminal wraps, and \r only goes back to the
+        // start of the last part of it
+        let line = fit(&line, Output::Stderr, 0);
+        eprint!("\r\x1b[2K{line}");
+        let _ = io::stderr().flush();
+    }
+}
+
+/// Renders the status line, like `pull: 3 done, 1 failed, 4 remaining:
+/// dots, wiki`, where done counts the failed too
+pub fn status_line(
+    op: &str,
+    total: usize,
+    done: usize,
+    failed: usize,
+    running: &[String],
+) -> String {
+    let mut line = format!("{op}: {done} done");
+    if failed > 0 {
+        line.push_str(&format!(", {failed} failed"));
+    }
+    line.push_str(&format!(", {} remaining", total.saturating_sub(done)));
+    if !running.is_empty() {
+        let shown: Vec<&str> = running
+            .iter()
+            .take(SHOWN_RUNNING)
+            .map(String::as_str)
+            .collect();
+        line.push_str(&format!(": {}", shown.join(", ")));
+        if running.len() > SHOWN_RUNNING {
+            line.push_str(&format!(" and {} more", running.len() - SHOWN_RUNNING));
+        }
+    }
+    line
+}
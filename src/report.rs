@@ -0,0 +1,142 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Structured results of runs, for tools built on top of seidr
+//!
+//! Config methods that operate on every repo or link return a `RunReport`,
+//! grouped by category, so dashboards and the like don't have to parse what
+//! the CLI prints. The types serialize with serde, and are part of the stable
+//! library API.
+
+use serde::{Deserialize, Serialize};
+
+/// The result of running a single operation on a single repo
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct RepoResult {
+    /// The name of the repo in its category
+    pub name: String,
+    /// The operation that was run, like "pull"
+    pub operation: String,
+    pub succeeded: bool,
+    /// The operation was never started, as the run was out of time
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skipped: bool,
+    /// How long the operation took, None if it was skipped, or recorded
+    /// before durations were
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+}
+
+/// The result of running a single operation on a single link
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct LinkResult {
+    /// The name of the link in its category
+    pub name: String,
+    /// The operation that was run, like "link"
+    pub operation: String,
+    pub rx: String,
+    pub tx: String,
+    /// Whether the link is in place, also true if it already was
+    pub succeeded: bool,
+    /// Why the operation failed, or what was unusual about it succeeding
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// The results of a run in a single category
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct CategoryReport {
+    pub name: String,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub repos: Vec<RepoResult>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub links: Vec<LinkResult>,
+}
+
+/// The results of a run, by category
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct RunReport {
+    /// The categories the run touched, sorted by name
+    pub categories: Vec<CategoryReport>,
+}
+
+impl RunReport {
+    /// Adds the result of an operation on a repo in category
+    pub fn add_repo(&mut self, category: &str, result: RepoResult) {
+        match self
+            .categories
+            .iter_mut()
+            .find(|report| report.name == category)
+        {
+            Some(report) => report.repos.push(result),
+            None => self.categories.push(CategoryReport {
+                name: category.to_string(),
+                repos: vec![result],
+                links: vec![],
+            }),
+        }
+    }
+    /// Sorts categories, and the repos and links in them, by name
+    ///
+    /// The sort is stable, so the operations on a repo stay in order.
+    pub fn sort(&mut self) {
+        self.categories.sort_by(|a, b| a.name.cmp(&b.name));
+        for category in self.categories.iter_mut() {
+            category.repos.sort_by(|a, b| a.name.cmp(&b.name));
+            category.links.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+    }
+    /// Moves the results of repo name in category to to_name in to_category
+    ///
+    /// Returns whether there were any results to move.
+    pub fn rename_repo(
+        &mut self,
+        (category, name): (&str, &str),
+        (to_category, to_name): (&str, &str),
+    ) -> bool {
+        let mut moved = vec![];
+        for report in self.categories.iter_mut().filter(|c| c.name == category) {
+            let (matching, rest) = report.repos.drain(..).partition(|repo| repo.name == name);
+            report.repos = rest;
+            moved.extend(matching);
+        }
+        if moved.is_empty() {
+            return false;
+        }
+        self.categories
+            .retain(|report| !report.repos.is_empty() || !report.links.is_empty());
+        for result in moved {
+            self.add_repo(
+                to_category,
+                RepoResult {
+                    name: to_name.to_string(),
+                    ..result
+                },
+            );
+        }
+        self.sort();
+        true
+    }
+    /// The operations on repos that were skipped, with their category
+    pub fn skipped(&self) -> Vec<(&str, &RepoResult)> {
+        self.categories
+            .iter()
+            .flat_map(|category| {
+                category
+                    .repos
+                    .iter()
+                    .filter(|repo| repo.skipped)
+                    .map(|repo| (category.name.as_str(), repo))
+            })
+            .collect()
+    }
+    /// Whether every operation in the run succeeded
+    pub fn succeeded(&self) -> bool {
+        self.categories.iter().all(|category| {
+            category.repos.iter().all(|repo| repo.succeeded)
+                && category.links.iter().all(|link| link.succeeded)
+        })
+    }
+}
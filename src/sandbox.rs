@@ -0,0 +1,193 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Trying out a run on copies of the repos, with `--sandbox`
+//!
+//! Every repo gets a bare copy of its remote in a temp dir, made from its
+//! checkout if it is cloned, so unpushed commits come along, and a checkout
+//! of that copy if it is cloned. The config is then pointed at the copies,
+//! links are moved into the sandbox, and the state dir is too, so a run
+//! touches nothing outside the sandbox, and pushes land in the copies.
+//!
+//! Archives have no remote to copy, so they keep their url, and what was
+//! extracted is copied into the sandbox along with its hash.
+
+use log::{debug, error, info, trace, warn};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::archive::Extracted;
+use crate::git::{Config, RepoStatus};
+use crate::trash::{copy_path, run_id};
+use crate::utils::dir::{absolute_path, expand_tilde, home_dir};
+use crate::utils::exec::Exec;
+
+/// A temp dir the config is moved into
+pub struct Sandbox {
+    pub dir: PathBuf,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Sandbox::at(std::env::temp_dir().join(format!("seidr-sandbox-{}", run_id())))
+    }
+}
+
+/// Runs git with args, failing with its stderr
+fn git(args: &[&str], envs: Vec<(String, String)>) -> Result<(), String> {
+    let output = Command::new("git")
+        .args(args)
+        .envs(envs)
+        .exec_output()
+        .map_err(|err| format!("git failed to run: {err}"))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+impl Sandbox {
+    /// The sandbox in dir
+    pub fn at(dir: PathBuf) -> Self {
+        Sandbox { dir }
+    }
+    /// Where the state dir of runs in the sandbox is, to be set as
+    /// `XDG_STATE_HOME`
+    pub fn state_home(&self) -> PathBuf {
+        self.dir.join("state")
+    }
+    /// Where path is in the sandbox, under home for paths in the home dir,
+    /// and under root for the rest
+    fn inside(&self, path: &str) -> PathBuf {
+        let path = absolute_path(Path::new(&expand_tilde(path)));
+        match path.strip_prefix(home_dir()) {
+            Ok(rest) => self.dir.join("home").join(rest),
+            Err(_) => self
+                .dir
+                .join("root")
+                .join(path.strip_prefix("/").unwrap_or(&path)),
+        }
+    }
+    /// Copies the repos of config into the sandbox, and points config at
+    /// the copies
+    ///
+    /// Links have their rx moved into the sandbox, and their tx too if it is
+    /// in a repo. The state repo is dropped, so it isn't synced.
+    pub fn enter(&self, config: &mut Config) -> Result<(), String> {
+        let extracted = Extracted::load()
+            .map_err(|err| format!("failed to read the extracted archives: {err}"))?;
+        self.enter_with(config, &extracted)
+    }
+    /// Like `enter`, with extracted as the hashes of the archives extracted
+    /// outside the sandbox
+    pub fn enter_with(&self, config: &mut Config, extracted: &Extracted) -> Result<(), String> {
+        let mut archives =
+            Extracted::load_from(Extracted::path_in(&self.state_home().join("seidr")))
+                .map_err(|err| format!("failed to read the extracted archives: {err}"))?;
+        let mut copies = vec![];
+        for (category_name, category) in config.categories.iter() {
+            for (name, repo) in category.repos.iter().flatten() {
+                let cloned = repo.path.is_some() && repo.status() != RepoStatus::Missing;
+                if repo.extract {
+                    copies.push((category_name.clone(), name.clone(), None, cloned));
+                    continue;
+                }
+                let source = if cloned {
+                    repo.checkout_dir()
+                } else {
+                    match config.remote_url(repo) {
+                        Some(url) => url,
+                        None => continue,
+                    }
+                };
+                let remote = self
+                    .dir
+                    .join("remotes")
+                    .join(category_name)
+                    .join(format!("{name}.git"));
+                debug!("copying {source} to {}", remote.display());
+                git(
+                    &["clone", "-q", "--bare", &source, &remote.to_string_lossy()],
+                    repo.remote_env(),
+                )
+                .map_err(|err| format!("{category_name} {name}: failed to copy {source}: {err}"))?;
+                copies.push((category_name.clone(), name.clone(), Some(remote), cloned));
+            }
+        }
+        // The checkout dir of every repo that moved, to move the tx of links
+        // in them along
+        let mut moved = BTreeMap::new();
+        for (category_name, name, remote, cloned) in copies {
+            let Some(repo) = config
+                .categories
+                .get_mut(&category_name)
+                .and_then(|category| category.repos.as_mut())
+                .and_then(|repos| repos.get_mut(&name))
+            else {
+                continue;
+            };
+            let before = repo.path.as_ref().map(|_| repo.checkout_dir());
+            let path = self.dir.join("checkouts").join(&category_name);
+            std::fs::create_dir_all(&path).map_err(|err| format!("{}: {err}", path.display()))?;
+            repo.path = Some(format!("{}/", path.display()));
+            // Archives keep their url, and what was extracted comes along
+            let Some(remote) = remote else {
+                if let Some(before) = before.as_ref().filter(|_| cloned) {
+                    let after = repo.checkout_dir();
+                    copy_path(Path::new(before), Path::new(&after))
+                        .map_err(|err| format!("{category_name} {name}: failed to copy: {err}"))?;
+                    if let Some(hash) = extracted.get(before) {
+                        archives
+                            .record(&after, hash.to_string())
+                            .map_err(|err| format!("failed to record {after}: {err}"))?;
+                    }
+                    moved.insert(absolute_path(Path::new(before)), after);
+                }
+                continue;
+            };
+            repo.url = Some(remote.to_string_lossy().to_string());
+            repo.pull_remote = None;
+            repo.push_remote = None;
+            repo.mirror_of = None;
+            repo.auth = None;
+            if cloned {
+                git(
+                    &[
+                        "clone",
+                        "-q",
+                        &remote.to_string_lossy(),
+                        &repo.checkout_dir(),
+                    ],
+                    vec![],
+                )
+                .map_err(|err| format!("{category_name} {name}: failed to check out: {err}"))?;
+            }
+            if let Some(before) = before {
+                moved.insert(absolute_path(Path::new(&before)), repo.checkout_dir());
+            }
+        }
+        for category in config.categories.values_mut() {
+            for link in category
+                .links
+                .iter_mut()
+                .flat_map(|links| links.values_mut())
+            {
+                let tx = absolute_path(Path::new(&expand_tilde(&link.tx)));
+                if let Some((rest, checkout)) = moved
+                    .iter()
+                    .find_map(|(before, after)| Some((tx.strip_prefix(before).ok()?, after)))
+                {
+                    link.tx = Path::new(checkout).join(rest).to_string_lossy().to_string();
+                }
+                link.rx = self.inside(&link.rx).to_string_lossy().to_string();
+            }
+        }
+        config.state = None;
+        debug!("entered sandbox {}", self.dir.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Categories for common setups, added to the config by `seidr init`
+//!
+//! Each template is a category with the flags the setup needs, and commented
+//! examples of its repos and links to fill in, so it does nothing until it's
+//! customized. A template is written into the `categories` of the config as
+//! text, so the comments already in the config are kept, and the config is
+//! created if there is none.
+
+use clap::ValueEnum;
+use log::{debug, error, info, trace, warn};
+
+use crate::git::Config;
+
+/// The setups there are templates for
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum Template {
+    /// Dotfiles kept in a repo, and linked into place
+    Dotfiles,
+    /// Mirrors of projects that are only cloned and pulled
+    SrcMirrors,
+    /// Work repos, with their own ssh host, email and trailers
+    Work,
+}
+
+const DOTFILES: &str = r#"  # Dotfiles, kept in a repo and linked into place, where a link points rx,
+  # where programs look for a file, at tx, the file in the repo
+  dotfiles:
+    flags: [Clone, Pull, Add, Commit, Push]
+    # repos:
+    #   dots:
+    #     name: dots
+    #     path: ${env.HOME}/
+    #     url: git@github.com:you/dots.git
+    #     kind: GitRepo
+    # links:
+    #   gitconfig:
+    #     name: gitconfig
+    #     rx: ${env.HOME}/.gitconfig
+    #     tx: ${env.HOME}/dots/gitconfig
+    #   nvim:
+    #     name: nvim
+    #     rx: ${env.HOME}/.config/nvim
+    #     tx: ${env.HOME}/dots/nvim
+"#;
+
+const SRC_MIRRORS: &str = r#"  # Mirrors of projects to read and build, only ever cloned and pulled, in
+  # the root unless a repo has a path of its own
+  src-mirrors:
+    flags: [Clone, Pull]
+    root: ${env.HOME}/src/
+    # Only run the category when it's named, for mirrors too large to pull
+    # on every run
+    # manual: true
+    # repos:
+    #   seidr:
+    #     name: seidr
+    #     url: https://github.com/cafkafk/seidr.git
+    #     kind: GitRepo
+    #   linux:
+    #     name: linux
+    #     url: https://github.com/torvalds/linux.git
+    #     kind: GitRepo
+    #     # Only the recent history, and blobs as they're needed
+    #     shallow_since: 1.year.ago
+    #     filter: blob:none
+"#;
+
+const WORK: &str = r#"  # Work repos, cloned in the root, committed to as a work identity
+  work:
+    flags: [Clone, Pull, Add, Commit, Push]
+    root: ${env.HOME}/work/
+    # Urls on github.com go through an ssh host alias, set up in ~/.ssh/config
+    # with the work key
+    # ssh_host_alias:
+    #   github.com: github-work
+    # git_config:
+    #   user.email: you@work.example
+    # trailers:
+    #   - "Signed-off-by: You <you@work.example>"
+    # repos:
+    #   api:
+    #     name: api
+    #     url: git@github.com:company/api.git
+    #     kind: GitHubRepo
+"#;
+
+impl Template {
+    /// The name of the category the template adds
+    pub fn category(&self) -> &'static str {
+        match self {
+            Template::Dotfiles => "dotfiles",
+            Template::SrcMirrors => "src-mirrors",
+            Template::Work => "work",
+        }
+    }
+    /// The category, as it's written under `categories`
+    fn section(&self) -> &'static str {
+        match self {
+            Template::Dotfiles => DOTFILES,
+            Template::SrcMirrors => SRC_MIRRORS,
+            Template::Work => WORK,
+        }
+    }
+}
+
+/// Adds the category of template to the config existing, returning the new
+/// config, which is just the category if there is no config yet
+///
+/// A config that already has a category of the same name is refused, rather
+/// than having a second one that would be a duplicate key.
+pub fn scaffold(existing: Option<&str>, template: Template) -> Result<String, String> {
+    let name = template.category();
+    let section = template.section();
+    let existing = existing.filter(|yaml| !yaml.trim().is_empty());
+    let scaffolded = match existing {
+        None => format!("categories:\n{section}"),
+        Some(yaml) => {
+            let value: serde_yaml::Value = serde_yaml::from_str(yaml)
+                .map_err(|err| format!("failed to parse config: {err}"))?;
+            if value
+                .get("categories")
+                .and_then(|categories| categories.get(name))
+                .is_some()
+            {
+                return Err(format!("config already has a category {name}"));
+            }
+            // Only a block mapping can be added to as text, and a config
+            // without categories gets them at the end
+            match yaml
+                .lines()
+                .position(|line| line.trim_end() == "categories:")
+            {
+                Some(at) => {
+                    let mut lines: Vec<&str> = yaml.lines().collect();
+                    lines.insert(at + 1, section.trim_end_matches('\n'));
+                    lines.join("\n") + "\n"
+                }
+                None if value.get("categories").is_none() => {
+                    format!("{}\ncategories:\n{section}", yaml.trim_end())
+                }
+                None => return Err(String::from("categories of config aren't a block mapping")),
+            }
+        }
+    };
+    serde_yaml::from_str::<Config>(&scaffolded)
+        .map_err(|err| format!("config with {name} doesn't parse: {err}"))?;
+    debug!("scaffolded {name}");
+    Ok(scaffolded)
+}
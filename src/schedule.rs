@@ -0,0 +1,164 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! The order repos are started in within a run
+//!
+//! By default repos run in alphabetical order. With `schedule: shortest_first`
+//! the repos that took the least time in the last recorded run they were in
+//! start first, so most results show up quickly while large repos take their
+//! time, and with `schedule: config_order` they start in the order the config
+//! lists them. Either way, interactive repos are still run last, and the
+//! results are reported in alphabetical order.
+//!
+//! How long a repo took is the sum of its operations in the history, see the
+//! history module. Repos without timings start after those with them, as
+//! they're likely new, and in need of a clone.
+
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::git::Repo;
+use crate::history::{History, RunRecord};
+
+/// The order repos are started in
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Schedule {
+    /// By how long they took the last time, shortest first
+    ShortestFirst,
+    /// As they are written in the config
+    ConfigOrder,
+    /// By category and name
+    #[default]
+    Alpha,
+}
+
+impl Schedule {
+    pub fn is_alpha(&self) -> bool {
+        *self == Schedule::Alpha
+    }
+}
+
+/// The repos of a config in the order its yaml lists them, as (category,
+/// name), with subcategories named by their path like in
+/// `Config::flatten_categories`
+///
+/// Configs that only list their repos in another order are still the same
+/// config, so these always compare equal.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FileOrder(Vec<(String, String)>);
+
+impl PartialEq for FileOrder {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for FileOrder {}
+
+impl FileOrder {
+    /// Reads the order of the repos in yaml, empty if it isn't a config
+    pub fn of(yaml: &str) -> Self {
+        fn walk(prefix: &str, categories: &serde_yaml::Value, order: &mut Vec<(String, String)>) {
+            let Some(categories) = categories.as_mapping() else {
+                return;
+            };
+            for (name, category) in categories {
+                let Some(name) = name.as_str() else {
+                    continue;
+                };
+                let name = match prefix {
+                    "" => name.to_string(),
+                    prefix => format!("{prefix}/{name}"),
+                };
+                if let Some(repos) = category.get("repos").and_then(|repos| repos.as_mapping()) {
+                    for repo in repos.keys().filter_map(|repo| repo.as_str()) {
+                        order.push((name.clone(), repo.to_string()));
+                    }
+                }
+                if let Some(children) = category.get("categories") {
+                    walk(&name, children, order);
+                }
+            }
+        }
+        let mut order = vec![];
+        if let Ok(config) = serde_yaml::from_str::<serde_yaml::Value>(yaml) {
+            if let Some(categories) = config.get("categories") {
+                walk("", categories, &mut order);
+            }
+        }
+        FileOrder(order)
+    }
+    /// Where the repo is listed, None if it isn't
+    pub fn position(&self, category: &str, name: &str) -> Option<usize> {
+        self.0.iter().position(|(c, n)| c == category && n == name)
+    }
+}
+
+/// How long repos took in the last run they were in, in milliseconds, by
+/// (category, name)
+#[derive(Debug, Default)]
+pub struct Timings(HashMap<(String, String), u64>);
+
+impl Timings {
+    /// Reads the timings from the history, none if it can't be read
+    pub fn load() -> Self {
+        match History::default().list() {
+            Ok(records) => Self::of(&records),
+            Err(err) => {
+                warn!("failed to read the history for timings: {err}");
+                Timings::default()
+            }
+        }
+    }
+    /// Reads the timings from records, oldest first
+    pub fn of(records: &[RunRecord]) -> Self {
+        let mut timings = HashMap::new();
+        for record in records {
+            for category in &record.report.categories {
+                let mut took: HashMap<&String, u64> = HashMap::new();
+                for result in &category.repos {
+                    if let Some(duration_ms) = result.duration_ms {
+                        *took.entry(&result.name).or_default() += duration_ms;
+                    }
+                }
+                for (name, duration_ms) in took {
+                    timings.insert((category.name.clone(), name.clone()), duration_ms);
+                }
+            }
+        }
+        Timings(timings)
+    }
+    /// How long the repo took, None if it has no timings
+    pub fn get(&self, category: &str, name: &str) -> Option<u64> {
+        self.0
+            .get(&(category.to_string(), name.to_string()))
+            .copied()
+    }
+}
+
+/// Sorts repos, as (category, name, repo), in the order schedule starts them,
+/// with interactive repos last
+pub fn order(
+    repos: &mut [(&String, &String, &Repo)],
+    schedule: Schedule,
+    file_order: &FileOrder,
+    timings: &Timings,
+) {
+    repos.sort_by_key(|(category, name, repo)| (repo.interactive, *category, *name));
+    match schedule {
+        Schedule::Alpha => (),
+        Schedule::ConfigOrder => repos.sort_by_key(|(category, name, repo)| {
+            let position = file_order.position(category, name);
+            (repo.interactive, position.is_none(), position)
+        }),
+        Schedule::ShortestFirst => repos.sort_by_key(|(category, name, repo)| {
+            let took = timings.get(category, name);
+            (repo.interactive, took.is_none(), took)
+        }),
+    }
+    debug!("scheduled {} repos {schedule:?}", repos.len());
+}
@@ -0,0 +1,322 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! A JSON Schema of the config, for editors and tools that write configs
+//!
+//! Like the strict module, the schema is asked of serde rather than written
+//! by hand, so it never goes out of sync with the structs. The config is
+//! deserialized from a deserializer that records the type of every value it
+//! is asked for, and hands back a placeholder of that type, so deserializing
+//! goes on to the next field.
+//!
+//! Serde doesn't say which fields have defaults, so no field is required,
+//! and the schema catches unknown fields and values of the wrong type.
+
+use log::{debug, error, info, trace, warn};
+use serde::de::value::Error;
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess, IntoDeserializer, MapAccess,
+    SeqAccess, VariantAccess, Visitor,
+};
+use serde::forward_to_deserialize_any;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+use crate::git::Config;
+
+/// The schemas of structs, by name, None while a struct is being probed
+type Defs = BTreeMap<&'static str, Option<Value>>;
+
+/// A deserializer that writes the schema of what is deserialized from it to
+/// schema, and the structs it meets to defs
+struct SchemaProbe<'a> {
+    schema: &'a mut Value,
+    defs: &'a mut Defs,
+}
+
+impl<'de> Deserializer<'de> for SchemaProbe<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Error> {
+        *self.schema = json!({});
+        Err(de::Error::custom("can't describe this type"))
+    }
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.schema = json!({ "type": "boolean" });
+        visitor.visit_bool(false)
+    }
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_u64(visitor)
+    }
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_u64(visitor)
+    }
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_u64(visitor)
+    }
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.schema = json!({ "type": "integer", "minimum": 0 });
+        visitor.visit_u64(0)
+    }
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.schema = json!({ "type": "integer" });
+        visitor.visit_i64(0)
+    }
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_f64(visitor)
+    }
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.schema = json!({ "type": "number" });
+        visitor.visit_f64(0.0)
+    }
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.schema = json!({ "type": "string", "minLength": 1, "maxLength": 1 });
+        visitor.visit_char(' ')
+    }
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.schema = json!({ "type": "string" });
+        visitor.visit_str("")
+    }
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+    /// Options are described by what they contain, as leaving a field out is
+    /// how a config says None
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let mut items = Value::Null;
+        let value = visitor.visit_seq(Once {
+            done: false,
+            key: None,
+            value: &mut items,
+            defs: self.defs,
+        });
+        *self.schema = json!({ "type": "array", "items": items });
+        value
+    }
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let mut key = Value::Null;
+        let mut values = Value::Null;
+        let value = visitor.visit_map(Once {
+            done: false,
+            key: Some(&mut key),
+            value: &mut values,
+            defs: self.defs,
+        });
+        *self.schema = json!({ "type": "object", "additionalProperties": values });
+        value
+    }
+    /// Structs are described once in the defs, and referred to from there,
+    /// as a category may contain categories
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        *self.schema = json!({ "$ref": format!("#/$defs/{name}") });
+        if let Some(None) = self.defs.get(name) {
+            trace!("{name} contains itself");
+            return visitor.visit_map(Fields {
+                fields: [].iter(),
+                current: "",
+                properties: &mut Map::new(),
+                defs: self.defs,
+            });
+        }
+        self.defs.insert(name, None);
+        let mut properties = Map::new();
+        let value = visitor.visit_map(Fields {
+            fields: fields.iter(),
+            current: "",
+            properties: &mut properties,
+            defs: self.defs,
+        });
+        self.defs.insert(
+            name,
+            Some(json!({
+                "type": "object",
+                "properties": properties,
+                "additionalProperties": false,
+            })),
+        );
+        value
+    }
+    /// Only enums of plain variants, like flags, are in the config
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        *self.schema = json!({ "type": "string", "enum": variants });
+        visitor.visit_enum(Variant(variants.first().copied().unwrap_or_default()))
+    }
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 bytes byte_buf unit unit_struct newtype_struct tuple
+        tuple_struct identifier
+    }
+}
+
+/// Hands the fields of a struct to its visitor, probing each value
+struct Fields<'a> {
+    fields: std::slice::Iter<'static, &'static str>,
+    current: &'static str,
+    properties: &'a mut Map<String, Value>,
+    defs: &'a mut Defs,
+}
+
+impl<'de> MapAccess<'de> for Fields<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        let Some(field) = self.fields.next() else {
+            return Ok(None);
+        };
+        self.current = field;
+        seed.deserialize(IntoDeserializer::<Error>::into_deserializer(*field))
+            .map(Some)
+    }
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let schema = self.properties.entry(self.current).or_insert(Value::Null);
+        seed.deserialize(SchemaProbe {
+            schema,
+            defs: self.defs,
+        })
+    }
+}
+
+/// A map or seq of a single probed entry
+struct Once<'a> {
+    done: bool,
+    /// Where the schema of the key goes, for maps
+    key: Option<&'a mut Value>,
+    value: &'a mut Value,
+    defs: &'a mut Defs,
+}
+
+impl<'de> SeqAccess<'de> for Once<'_> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        if std::mem::replace(&mut self.done, true) {
+            return Ok(None);
+        }
+        seed.deserialize(SchemaProbe {
+            schema: self.value,
+            defs: self.defs,
+        })
+        .map(Some)
+    }
+}
+
+impl<'de> MapAccess<'de> for Once<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        if std::mem::replace(&mut self.done, true) {
+            return Ok(None);
+        }
+        let mut ignored = Value::Null;
+        seed.deserialize(SchemaProbe {
+            schema: self.key.as_deref_mut().unwrap_or(&mut ignored),
+            defs: self.defs,
+        })
+        .map(Some)
+    }
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        seed.deserialize(SchemaProbe {
+            schema: self.value,
+            defs: self.defs,
+        })
+    }
+}
+
+/// The variant an enum is probed with
+struct Variant(&'static str);
+
+impl<'de> EnumAccess<'de> for Variant {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let value = seed.deserialize(IntoDeserializer::<Error>::into_deserializer(self.0))?;
+        Ok((value, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Variant {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, _: T) -> Result<T::Value, Error> {
+        Err(de::Error::custom("can't describe variants with data"))
+    }
+    fn tuple_variant<V: Visitor<'de>>(self, _: usize, _: V) -> Result<V::Value, Error> {
+        Err(de::Error::custom("can't describe variants with data"))
+    }
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _: &'static [&'static str],
+        _: V,
+    ) -> Result<V::Value, Error> {
+        Err(de::Error::custom("can't describe variants with data"))
+    }
+}
+
+/// Returns the schema of T, and the structs it refers to
+fn describe<T: DeserializeOwned>() -> (Value, Defs) {
+    let mut schema = Value::Null;
+    let mut defs = Defs::new();
+    if let Err(err) = T::deserialize(SchemaProbe {
+        schema: &mut schema,
+        defs: &mut defs,
+    }) {
+        warn!("the schema is incomplete: {err}");
+    }
+    (schema, defs)
+}
+
+/// Returns the JSON Schema of the config
+pub fn config_schema() -> Value {
+    let (schema, defs) = describe::<Config>();
+    debug!("described {} structs", defs.len());
+    let defs: Map<String, Value> = defs
+        .into_iter()
+        .map(|(name, schema)| (name.to_string(), schema.unwrap_or_else(|| json!({}))))
+        .collect();
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "seidr config",
+        "$ref": schema["$ref"],
+        "$defs": defs,
+    })
+}
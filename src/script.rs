@@ -0,0 +1,186 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Workflows of several seidr operations, run from a file
+//!
+//! A workflow is a list of steps, each running a seidr operation or a shell
+//! command, optionally narrowed down to a scope like `--repos-from`:
+//!
+//! ```yaml
+//! steps:
+//!   - run: pull
+//!     scope: [utils]
+//!   - exec: make
+//!     scope: [utils/seidr]
+//!   - run: link
+//!   - exec: notify-send "sync failed"
+//!     when: failure
+//! ```
+//!
+//! Every scope is checked before anything runs, so a typo doesn't leave a
+//! workflow half done.
+
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::process::Command;
+
+use crate::git::Config;
+use crate::selection::Selection;
+use crate::settings::Settings;
+use crate::utils::exec::Exec;
+use crate::utils::strings::result_str;
+
+/// A seidr operation a step can run
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Clone,
+    Pull,
+    Push,
+    Add,
+    Commit,
+    Quick,
+    Fast,
+    Link,
+}
+
+/// What a step does
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Run a seidr operation on every repo in scope
+    Run(Operation),
+    /// Run a shell command in every repo in scope
+    Exec(String),
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Run(operation) => write!(f, "{}", format!("{operation:?}").to_lowercase()),
+            Action::Exec(command) => write!(f, "exec {command}"),
+        }
+    }
+}
+
+/// When a step runs, based on the steps before it
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum When {
+    /// Only if every step so far succeeded
+    #[default]
+    Success,
+    /// Only if a step so far failed
+    Failure,
+    /// Regardless of the steps so far
+    Always,
+}
+
+/// A single step of a workflow
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Step {
+    #[serde(flatten)]
+    pub action: Action,
+    /// Targets to narrow the step down to, everything if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scope: Vec<String>,
+    #[serde(default)]
+    pub when: When,
+}
+
+/// A workflow file
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Script {
+    pub steps: Vec<Step>,
+}
+
+impl Script {
+    /// Reads a workflow from a yaml file
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let yaml = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+        serde_yaml::from_str(&yaml).map_err(|err| format!("{path}: {err}"))
+    }
+}
+
+/// Runs command with sh in the checkout of every repo in config
+fn exec_all(config: &Config, command: &str) -> bool {
+    config
+        .report_repos("exec", |repo| {
+            let succeeded = Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .current_dir(repo.checkout_dir())
+                .exec_status()
+                .map_or(false, |status| status.success());
+            let result = result_str(succeeded);
+            eprintln!("{result} {}: {command}", repo.display_name());
+            succeeded
+        })
+        .succeeded()
+}
+
+/// Runs script on the config at config_path with settings, or only prints what
+/// would run if dry_run is set
+///
+/// Returns whether every step that ran succeeded, or an error if a scope
+/// doesn't exist in the config.
+pub fn run(
+    config_path: &String,
+    settings: &Settings,
+    script: &Script,
+    dry_run: bool,
+    message: Option<&str>,
+) -> Result<bool, String> {
+    // Each step gets its own copy of the config, narrowed down to its scope
+    let mut configs = vec![];
+    for (number, step) in script.steps.iter().enumerate() {
+        let mut config = Config::new(config_path);
+        config.settings = settings.clone();
+        if !step.scope.is_empty() {
+            Selection::parse(&step.scope.join("\n"))
+                .and_then(|selection| selection.apply(&mut config))
+                .map_err(|err| format!("step {}: {err}", number + 1))?;
+        }
+        configs.push(config);
+    }
+    let mut succeeded = true;
+    for (number, (step, config)) in script.steps.iter().zip(&configs).enumerate() {
+        let scope = if step.scope.is_empty() {
+            String::from("everything")
+        } else {
+            step.scope.join(", ")
+        };
+        let header = format!("step {}: {} on {scope}", number + 1, step.action);
+        let wanted = match step.when {
+            When::Success => succeeded,
+            When::Failure => !succeeded,
+            When::Always => true,
+        };
+        if !wanted {
+            eprintln!("{header}, skipped");
+            continue;
+        }
+        eprintln!("{header}");
+        if dry_run {
+            continue;
+        }
+        debug!("running {header}");
+        let step_succeeded = match &step.action {
+            Action::Run(Operation::Clone) => config.clone_all().succeeded(),
+            Action::Run(Operation::Pull) => config.pull_all().succeeded(),
+            Action::Run(Operation::Push) => config.push_all(false, false).succeeded(),
+            Action::Run(Operation::Add) => config.add_all().succeeded(),
+            Action::Run(Operation::Commit) => config.commit_all_msg(message).succeeded(),
+            Action::Run(Operation::Quick) => config.quick(message).succeeded(),
+            Action::Run(Operation::Fast) => config.fast(message).succeeded(),
+            Action::Run(Operation::Link) => config.link_all().succeeded(),
+            Action::Exec(command) => exec_all(config, command),
+        };
+        succeeded &= step_succeeded;
+    }
+    Ok(succeeded)
+}
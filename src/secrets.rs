@@ -0,0 +1,155 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Secrets, like access tokens, read from where they are kept
+//!
+//! The config only says where a secret is, never the secret itself:
+//!
+//! ```yaml
+//! auth:
+//!   username: oauth2
+//!   token:
+//!     from: pass
+//!     key: work/gitlab-token
+//! ```
+//!
+//! Secrets can be read from:
+//!
+//! - `env`, the environment variable key
+//! - `pass`, the first line of the entry key in the pass store
+//! - `keychain`, the password with service key in the OS keychain, through
+//!   `security` on macOS, and `secret-tool` elsewhere
+
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt;
+use std::process::Command;
+
+use crate::utils::exec::Exec;
+
+/// Where a secret is kept
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretSource {
+    Env,
+    Pass,
+    Keychain,
+}
+
+impl fmt::Display for SecretSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretSource::Env => write!(f, "env"),
+            SecretSource::Pass => write!(f, "pass"),
+            SecretSource::Keychain => write!(f, "keychain"),
+        }
+    }
+}
+
+/// A reference to a secret, by where it is kept and its key there
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Secret {
+    pub from: SecretSource,
+    pub key: String,
+}
+
+/// Runs command, returning the first line of its stdout
+fn first_line(command: &mut Command) -> Result<String, String> {
+    let output = command.exec_output().map_err(|err| {
+        format!(
+            "failed to run {}: {err}",
+            command.get_program().to_string_lossy()
+        )
+    })?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string())
+}
+
+impl Secret {
+    /// Reads the secret
+    pub fn read(&self) -> Result<String, String> {
+        trace!("reading secret {} from {}", self.key, self.from);
+        let secret = match self.from {
+            SecretSource::Env => env::var(&self.key)
+                .map_err(|_| format!("environment variable {} is not set", self.key))?,
+            SecretSource::Pass => first_line(Command::new("pass").arg("show").arg(&self.key))?,
+            SecretSource::Keychain if cfg!(target_os = "macos") => first_line(
+                Command::new("security")
+                    .arg("find-generic-password")
+                    .arg("-s")
+                    .arg(&self.key)
+                    .arg("-w"),
+            )?,
+            SecretSource::Keychain => first_line(
+                Command::new("secret-tool")
+                    .arg("lookup")
+                    .arg("service")
+                    .arg(&self.key),
+            )?,
+        };
+        if secret.is_empty() {
+            return Err(format!("secret {} in {} is empty", self.key, self.from));
+        }
+        Ok(secret)
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.from, self.key)
+    }
+}
+
+/// Credentials for https remotes
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct HttpsAuth {
+    /// The user to authenticate as, many forges accept anything with a token
+    #[serde(default = "default_username")]
+    pub username: String,
+    pub token: Secret,
+}
+
+fn default_username() -> String {
+    String::from("git")
+}
+
+/// The environment variable the credential helper reads the token from,
+/// which is never transcribed
+pub const TOKEN_VAR: &str = "SEIDR_GIT_TOKEN";
+
+/// The credential helper, answering git with the username and the token
+const CREDENTIAL_HELPER: &str =
+    "!f() { test \"$1\" = get && echo \"username=$SEIDR_GIT_USERNAME\" && echo \"password=$SEIDR_GIT_TOKEN\"; }; f";
+
+impl HttpsAuth {
+    /// Returns the environment for git to authenticate with, by reading the
+    /// token
+    ///
+    /// The token is given to git through the environment, never the command
+    /// line, so it doesn't show up in the process list or in transcripts.
+    pub fn git_env(&self) -> Result<Vec<(String, String)>, String> {
+        let token = self.token.read()?;
+        // The empty helper resets those configured, so they can't answer first
+        Ok([
+            ("GIT_CONFIG_COUNT", "2"),
+            ("GIT_CONFIG_KEY_0", "credential.helper"),
+            ("GIT_CONFIG_VALUE_0", ""),
+            ("GIT_CONFIG_KEY_1", "credential.helper"),
+            ("GIT_CONFIG_VALUE_1", CREDENTIAL_HELPER),
+            ("SEIDR_GIT_USERNAME", &self.username),
+            (TOKEN_VAR, &token),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect())
+    }
+}
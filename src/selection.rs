@@ -0,0 +1,284 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Narrowing down which categories and repos a command operates on
+//!
+//! A selection is a list of targets, each either a whole category or a single
+//! repo in a category. Applying it to a config drops everything not selected,
+//! so commands don't have to know about selections at all.
+//!
+//! Manual categories are dropped the same way, unless they are named, see
+//! `drop_manual`, and so are repos skipped on this machine, see
+//! `drop_skipped`.
+
+use log::{debug, error, info, trace, warn};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::git::Config;
+use crate::utils::dir::{absolute_path, expand_tilde};
+use crate::utils::glob;
+use crate::utils::suggest::with_suggestion;
+
+/// Drops the manual categories that aren't named from config
+///
+/// A manual category is named by naming it, a repo in it, or a manual category
+/// it is a subcategory of, so naming a category that isn't manual doesn't
+/// take its manual subcategories along. Names that aren't categories are
+/// ignored.
+pub fn drop_manual(config: &mut Config, named: &[String]) {
+    let opted_in: Vec<String> = named
+        .iter()
+        .filter(|name| config.categories.get(*name).map_or(false, |cat| cat.manual))
+        .cloned()
+        .collect();
+    config.categories.retain(|name, category| {
+        let keep = !category.manual
+            || opted_in
+                .iter()
+                .any(|opted_in| Config::in_category(name, opted_in));
+        if !keep {
+            debug!("skipping manual category {name}");
+        }
+        keep
+    });
+}
+
+/// The file that, in the checkout of a repo, has commands that change things
+/// skip the repo on this machine
+pub const SKIP_MARKER: &str = ".seidrskip";
+
+/// Drops the repos whose checkouts have a `SKIP_MARKER` from config,
+/// returning them as (category, name), sorted
+///
+/// The marker is local to the machine, so a checkout can be left alone for a
+/// while without changing the config it shares with others.
+pub fn drop_skipped(config: &mut Config) -> Vec<(String, String)> {
+    let mut skipped = vec![];
+    for (category_name, category) in config.categories.iter_mut() {
+        if let Some(repos) = category.repos.as_mut() {
+            repos.retain(|name, repo| {
+                let marked = repo.path.is_some()
+                    && Path::new(&repo.checkout_dir()).join(SKIP_MARKER).exists();
+                if marked {
+                    debug!("skipping {category_name} {name}, it has a {SKIP_MARKER}");
+                    skipped.push((category_name.clone(), name.clone()));
+                }
+                !marked
+            });
+        }
+    }
+    skipped.sort();
+    skipped
+}
+
+/// A single selected category, or repo in a category
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Target {
+    pub category: String,
+    /// The repo, if only a single repo of the category is selected
+    pub repo: Option<String>,
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.repo {
+            Some(repo) => write!(f, "{}/{repo}", self.category),
+            None => write!(f, "{}", self.category),
+        }
+    }
+}
+
+/// A set of targets to operate on
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Selection {
+    pub targets: Vec<Target>,
+}
+
+impl Selection {
+    /// Parses a list of targets, one per line
+    ///
+    /// Lines are either `category`, `category repo` or `category/repo`, where
+    /// category may be the path of a subcategory, like `work/client`. Empty
+    /// lines and lines starting with `#` are ignored.
+    ///
+    /// `work/client` is parsed as the repo client in work, and only turns
+    /// into the subcategory when applied, see `Selection::apply`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut targets = vec![];
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let path = parts.next().unwrap_or_default().trim_matches('/');
+            let (category, repo) = match parts.next() {
+                Some(repo) => (path.to_string(), Some(repo.to_string())),
+                None => match path.rsplit_once('/') {
+                    Some((category, repo)) => (category.to_string(), Some(repo.to_string())),
+                    None => (path.to_string(), None),
+                },
+            };
+            if parts.next().is_some() {
+                return Err(format!(
+                    "line {}: expected category and repo, found {line:?}",
+                    number + 1
+                ));
+            }
+            targets.push(Target { category, repo });
+        }
+        Ok(Selection { targets })
+    }
+    /// Reads a list of targets from a file, or from stdin if path is `-`, see
+    /// `Selection::parse`
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        if path == "-" {
+            return Self::from_reader("stdin", io::stdin().lock());
+        }
+        let text = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+        Self::parse(&text).map_err(|err| format!("{path}: {err}"))
+    }
+    /// Reads a list of targets from reader, named name in errors, see
+    /// `Selection::parse`
+    pub fn from_reader<R: Read>(name: &str, mut reader: R) -> Result<Self, String> {
+        let mut text = String::new();
+        reader
+            .read_to_string(&mut text)
+            .map_err(|err| format!("{name}: {err}"))?;
+        Self::parse(&text).map_err(|err| format!("{name}: {err}"))
+    }
+    /// Selects the repos in config whose checkout dir is under the path
+    /// filter, or matches it if it is a glob, see `utils::glob`
+    ///
+    /// The filter may start with `~`, and is relative to the current dir.
+    /// Fails if no repo matches.
+    pub fn from_path_filter(config: &Config, filter: &str) -> Result<Self, String> {
+        let resolved = absolute_path(Path::new(&expand_tilde(filter)));
+        let resolved = resolved.to_string_lossy();
+        let mut targets: Vec<Target> = config
+            .categories
+            .iter()
+            .flat_map(|(category, cat)| {
+                cat.repos
+                    .iter()
+                    .flatten()
+                    .map(move |(name, repo)| (category, name, repo))
+            })
+            .filter(|(_, _, repo)| {
+                let dir = absolute_path(Path::new(&repo.checkout_dir()));
+                if glob::is_glob(&resolved) {
+                    glob::matches(&resolved, &dir)
+                } else {
+                    dir.starts_with(resolved.as_ref())
+                }
+            })
+            .map(|(category, name, _)| Target {
+                category: category.clone(),
+                repo: Some(name.clone()),
+            })
+            .collect();
+        if targets.is_empty() {
+            return Err(format!("no repos in {filter}"));
+        }
+        targets.sort_by(|a, b| (&a.category, &a.repo).cmp(&(&b.category, &b.repo)));
+        trace!("path filter {filter} selected {targets:?}");
+        Ok(Selection { targets })
+    }
+    /// Adds the targets of other to this selection
+    pub fn extend(&mut self, other: Selection) {
+        self.targets.extend(other.targets);
+    }
+    /// Returns the top level categories the targets are in, like `work` for
+    /// `work/client`, sorted
+    pub fn top_categories(&self) -> Vec<String> {
+        let mut categories: Vec<String> = self
+            .targets
+            .iter()
+            .map(|target| {
+                target
+                    .category
+                    .split('/')
+                    .next()
+                    .unwrap_or(&target.category)
+                    .to_string()
+            })
+            .collect();
+        categories.sort();
+        categories.dedup();
+        categories
+    }
+    /// Drops everything from config that isn't selected
+    ///
+    /// Links are kept for categories that are selected as a whole, and a
+    /// whole category brings its subcategories along. A target naming a
+    /// subcategory, like `work/client`, selects it rather than a repo. Fails
+    /// if a target doesn't exist in config.
+    pub fn apply(&self, config: &mut Config) -> Result<(), String> {
+        let targets: Vec<Target> = self
+            .targets
+            .iter()
+            .map(|target| match &target.repo {
+                Some(repo) if config.categories.contains_key(&target.to_string()) => {
+                    trace!("{target} is a subcategory");
+                    Target {
+                        category: format!("{}/{repo}", target.category),
+                        repo: None,
+                    }
+                }
+                _ => target.clone(),
+            })
+            .collect();
+        // category -> selected repos, None meaning the whole category
+        let mut wanted: HashMap<&str, Option<Vec<&str>>> = HashMap::new();
+        for target in &targets {
+            let Some(category) = config.categories.get(&target.category) else {
+                return Err(with_suggestion(
+                    format!("no category {}", target.category),
+                    &target.category,
+                    config.categories.keys().map(String::as_str),
+                ));
+            };
+            match &target.repo {
+                None => {
+                    wanted.insert(&target.category, None);
+                }
+                Some(repo) => {
+                    let repos = category.repos.iter().flat_map(|repos| repos.keys());
+                    if !repos.clone().any(|name| name == repo) {
+                        return Err(with_suggestion(
+                            format!("no repo {repo} in category {}", target.category),
+                            repo,
+                            repos.map(String::as_str),
+                        ));
+                    }
+                    if let Some(repos) = wanted.entry(&target.category).or_insert(Some(vec![])) {
+                        repos.push(repo);
+                    }
+                }
+            }
+        }
+        debug!("selected {wanted:?}");
+        config
+            .categories
+            .retain(|name, category| match wanted.get(name.as_str()) {
+                None => wanted
+                    .iter()
+                    .any(|(wanted, repos)| repos.is_none() && Config::in_category(name, wanted)),
+                Some(None) => true,
+                Some(Some(repos)) => {
+                    if let Some(category_repos) = category.repos.as_mut() {
+                        category_repos.retain(|name, _| repos.contains(&name.as_str()));
+                    }
+                    category.links = None;
+                    true
+                }
+            });
+        Ok(())
+    }
+}
@@ -3,12 +3,78 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-only
 
-use std::sync::atomic::AtomicBool;
+//! Settings of a run, as given on the command line
+//!
+//! What changes how operations run is kept in `Settings`, which every config
+//! carries, see `Config::settings`, so two configs in the same process, like
+//! in tests or a program using seidr as a library, run independently.
+//!
+//! How output looks is the same for everything a process writes to its
+//! terminal, so that is still process wide, in the statics below, set from
+//! settings with `Settings::install`.
 
-pub static QUIET: AtomicBool = AtomicBool::new(false);
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+/// Write ascii instead of emojis
 pub static EMOJIS: AtomicBool = AtomicBool::new(false);
 
-pub static UNLINK: AtomicBool = AtomicBool::new(false);
+/// Write colors, as resolved from --color and the environment once at start
+pub static COLOR: AtomicBool = AtomicBool::new(false);
 
-pub static FORCE: AtomicBool = AtomicBool::new(false);
+pub static VERBOSITY: AtomicU8 = AtomicU8::new(0);
+
+/// How a run behaves, and writes its output
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Settings {
+    /// Don't show progress, or what was done
+    pub quiet: bool,
+    /// Write ascii instead of emojis
+    pub no_emoji: bool,
+    /// Write colors, as resolved from --color and the environment
+    pub color: bool,
+    pub unlink: bool,
+    /// Do what would otherwise ask first, or fail, like replacing files in
+    /// the way of links
+    pub force: bool,
+    /// Show a single status line instead of spinners, see the progress module
+    pub compact_progress: bool,
+    /// Operate on system links instead of user links
+    pub system: bool,
+    pub verbosity: u8,
+    /// Operations that may run at once, 0 meaning the parallelism of the
+    /// config
+    pub jobs: u16,
+    /// How long a run may start operations for, as given by --max-duration
+    pub max_duration: Option<Duration>,
+    /// When the run stops starting operations, in milliseconds since the unix
+    /// epoch, as set by `Settings::start`
+    pub deadline: Option<u64>,
+}
+
+impl Settings {
+    /// Makes settings the process wide settings of output
+    pub fn install(&self) {
+        EMOJIS.store(self.no_emoji, Ordering::Relaxed);
+        COLOR.store(self.color, Ordering::Relaxed);
+        VERBOSITY.store(self.verbosity, Ordering::Relaxed);
+    }
+    /// Starts a run, which has until max_duration from now, if given
+    ///
+    /// A process doing several runs, like the daemon, starts each of them.
+    pub fn start(&mut self) {
+        self.deadline = self.max_duration.map(|max_duration| {
+            (SystemTime::now() + max_duration)
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |deadline| deadline.as_millis() as u64)
+        });
+    }
+    /// Whether the run is past its deadline
+    pub fn out_of_time(&self) -> bool {
+        self.deadline.map_or(false, |deadline| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(false, |now| now.as_millis() as u64 >= deadline)
+        })
+    }
+}
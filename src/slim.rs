@@ -0,0 +1,252 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Advising how to make large repos take less disk
+//!
+//! The objects of every checkout are measured, and for the large ones, what
+//! cloning shallow, cloning with a blob filter, or moving large files to LFS
+//! would save is estimated, from what git says the objects that would be kept
+//! take on disk. Shallow and filtered clones are written as the repo config
+//! that gets them, LFS as a comment, as moving files there rewrites history
+//! and is left to `git lfs migrate`.
+//!
+//! The estimates only count objects, a checkout also takes the size of its
+//! working tree, which none of this changes.
+
+use log::{debug, error, info, trace, warn};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::git::{Config, Repo, RepoStatus};
+
+/// The filter suggested for partial clones, leaving out every blob not
+/// checked out
+pub const BLOB_FILTER: &str = "blob:none";
+
+/// Blobs at least this large are suggested for LFS
+pub const LFS_BLOB_SIZE: u64 = 1024 * 1024;
+
+/// How much of the objects a suggestion has to save to be made, in percent
+const WORTHWHILE: u64 = 10;
+
+/// A change that would make a repo take less disk
+#[derive(PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum Suggestion {
+    /// Set `shallow_since`
+    Shallow { since: String, saves: u64 },
+    /// Set `filter`
+    Filter { filter: String, saves: u64 },
+    /// Move the files matching pattern to LFS
+    Lfs {
+        pattern: String,
+        blobs: usize,
+        saves: u64,
+    },
+}
+
+impl Suggestion {
+    /// The bytes the suggestion is estimated to save
+    pub fn saves(&self) -> u64 {
+        match self {
+            Suggestion::Shallow { saves, .. }
+            | Suggestion::Filter { saves, .. }
+            | Suggestion::Lfs { saves, .. } => *saves,
+        }
+    }
+}
+
+/// What a repo takes, and how to make it take less
+#[derive(PartialEq, Eq, Debug, Serialize)]
+pub struct Advice {
+    pub category: String,
+    pub name: String,
+    pub objects: u64,
+    /// The bytes the objects of the repo take on disk
+    pub bytes: u64,
+    /// Largest saving first
+    pub suggestions: Vec<Suggestion>,
+}
+
+/// What was measured of a repo, in bytes on disk
+#[derive(PartialEq, Eq, Debug, Default)]
+pub struct Measured {
+    /// Every object
+    pub all: u64,
+    /// What a shallow clone would keep, None if the repo already is one
+    pub shallow: Option<u64>,
+    /// What a filtered clone would keep, None if the repo already is one
+    pub filtered: Option<u64>,
+    /// The paths of blobs of at least `LFS_BLOB_SIZE`, with their bytes
+    pub large_blobs: Vec<(String, u64)>,
+}
+
+/// The pattern LFS would track path with, by its extension, or its file name
+/// when it has none
+pub fn lfs_pattern(path: &str) -> String {
+    let path = Path::new(path);
+    match path.extension() {
+        Some(extension) => format!("*.{}", extension.to_string_lossy()),
+        None => path
+            .file_name()
+            .map_or(String::new(), |name| name.to_string_lossy().to_string()),
+    }
+}
+
+/// Suggests what saves at least a tenth of what was measured, largest saving
+/// first
+pub fn suggest(measured: &Measured, since: &str) -> Vec<Suggestion> {
+    let worthwhile = |saves: u64| saves > 0 && saves * 100 >= measured.all * WORTHWHILE;
+    let mut suggestions = vec![];
+    if let Some(kept) = measured.shallow {
+        let saves = measured.all.saturating_sub(kept);
+        if worthwhile(saves) {
+            suggestions.push(Suggestion::Shallow {
+                since: since.to_string(),
+                saves,
+            });
+        }
+    }
+    if let Some(kept) = measured.filtered {
+        let saves = measured.all.saturating_sub(kept);
+        if worthwhile(saves) {
+            suggestions.push(Suggestion::Filter {
+                filter: BLOB_FILTER.to_string(),
+                saves,
+            });
+        }
+    }
+    let mut patterns: BTreeMap<String, (usize, u64)> = BTreeMap::new();
+    for (path, bytes) in measured.large_blobs.iter() {
+        let pattern = patterns.entry(lfs_pattern(path)).or_default();
+        pattern.0 += 1;
+        pattern.1 += bytes;
+    }
+    for (pattern, (blobs, saves)) in patterns {
+        if worthwhile(saves) {
+            suggestions.push(Suggestion::Lfs {
+                pattern,
+                blobs,
+                saves,
+            });
+        }
+    }
+    suggestions.sort_by_key(|suggestion| Reverse(suggestion.saves()));
+    suggestions
+}
+
+/// Measures the objects of repo, and what shallow cloning since, and
+/// filtering, would keep of them
+fn measure(repo: &Repo, since: &str) -> Result<Measured, String> {
+    let all = repo.disk_usage(&["--all"])?;
+    let shallow = if repo.is_shallow() || repo.shallow_since.is_some() {
+        None
+    } else {
+        // A shallow clone keeps at least the commit checked out
+        let since = format!("--since={since}");
+        let after = repo.disk_usage(&[&since, "HEAD"])?;
+        Some(after.max(repo.disk_usage(&["--no-walk", "HEAD"])?))
+    };
+    let filtered = if repo.is_partial() || repo.filter.is_some() {
+        None
+    } else {
+        // A filtered clone keeps every commit and tree, and the blobs checked
+        // out
+        let history = repo.disk_usage(&["--all", "--filter=blob:none"])?;
+        Some(history + repo.disk_usage(&["--no-walk", "HEAD"])?)
+    };
+    Ok(Measured {
+        all,
+        shallow,
+        filtered,
+        large_blobs: repo.large_blobs(LFS_BLOB_SIZE)?,
+    })
+}
+
+/// Advises on the repos of config with objects taking at least min_size
+/// bytes, sorted by category and name
+///
+/// Repos that aren't cloned, and those failing to be measured, are left out.
+pub fn run(config: &Config, min_size: u64, since: &str) -> Vec<Advice> {
+    let mut advice = vec![];
+    for (category_name, category) in config.categories.iter() {
+        for (name, repo) in category.repos.iter().flatten() {
+            if repo.status() == RepoStatus::Missing {
+                continue;
+            }
+            let measured = match measure(repo, since) {
+                Ok(measured) => measured,
+                Err(err) => {
+                    warn!("{category_name} {name}: {err}");
+                    continue;
+                }
+            };
+            if measured.all < min_size {
+                debug!("{category_name} {name} takes {} bytes", measured.all);
+                continue;
+            }
+            advice.push(Advice {
+                category: category_name.clone(),
+                name: name.clone(),
+                objects: repo.object_count().unwrap_or_default(),
+                bytes: measured.all,
+                suggestions: suggest(&measured, since),
+            });
+        }
+    }
+    advice.sort_by(|a, b| (&a.category, &a.name).cmp(&(&b.category, &b.name)));
+    advice
+}
+
+/// Renders bytes in the largest binary unit under them, like `3.2 GiB`
+pub fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Renders advice as the repo config to add, with what it saves as comments
+pub fn report(advice: &[Advice]) -> String {
+    let mut out = String::new();
+    for repo in advice {
+        out.push_str(&format!(
+            "# {} {}: {} in {} objects\n",
+            repo.category,
+            repo.name,
+            human_bytes(repo.bytes),
+            repo.objects
+        ));
+        if repo.suggestions.is_empty() {
+            out.push_str("# nothing to suggest\n");
+        }
+        for suggestion in repo.suggestions.iter() {
+            let saves = human_bytes(suggestion.saves());
+            match suggestion {
+                Suggestion::Shallow { since, .. } => {
+                    out.push_str(&format!("shallow_since: {since} # saves {saves}\n"))
+                }
+                Suggestion::Filter { filter, .. } => {
+                    out.push_str(&format!("filter: {filter} # saves {saves}\n"))
+                }
+                Suggestion::Lfs { pattern, blobs, .. } => out.push_str(&format!(
+                    "# {pattern} in LFS, {blobs} blobs over {}, saves {saves}, see git lfs migrate\n",
+                    human_bytes(LFS_BLOB_SIZE)
+                )),
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,127 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Finds work that only exists on this disk
+//!
+//! Commits that were never pushed, and changes to the tx of links that were
+//! never committed, are lost with the disk. Both are reported once they are
+//! older than a threshold, as a nudge to push or commit them.
+
+use log::{debug, error, info, trace, warn};
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crate::git::{Config, PathOwner, RepoStatus};
+use crate::utils::dir::absolute_path;
+
+const DAY: u64 = 86400;
+
+/// Work that has been left for too long
+#[derive(PartialEq, Eq, Debug)]
+pub struct Stale {
+    /// The repo or link the work is in
+    pub subject: String,
+    /// What the work is
+    pub problem: String,
+    /// The age of the oldest of the work, in days
+    pub days: u64,
+}
+
+/// Returns the modification time of path, in seconds since the unix epoch
+fn modified(path: &Path) -> Option<u64> {
+    let time = fs::symlink_metadata(path).ok()?.modified().ok()?;
+    Some(time.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+/// Finds repos with commits that haven't been pushed, the oldest of them
+/// older than days
+fn stale_repos(config: &Config, days: u64, now: u64) -> Vec<Stale> {
+    let mut stale = vec![];
+    for (category_name, category) in config.categories.iter() {
+        for (name, repo) in category.repos.iter().flatten() {
+            if repo.archived || repo.status() == RepoStatus::Missing {
+                continue;
+            }
+            let times = match repo.unpushed_commit_times() {
+                Ok(times) => times,
+                Err(err) => {
+                    warn!("{category_name} {name}: {err}");
+                    continue;
+                }
+            };
+            let Some(oldest) = times.iter().min() else {
+                continue;
+            };
+            let age = now.saturating_sub(*oldest) / DAY;
+            if age >= days {
+                stale.push(Stale {
+                    subject: format!("repo {category_name} {name}"),
+                    problem: format!("{} unpushed commits", times.len()),
+                    days: age,
+                });
+            }
+        }
+    }
+    stale
+}
+
+/// Finds links whose tx has changes that aren't committed in the repo it is
+/// in, the oldest of them older than days
+fn stale_links(config: &Config, days: u64, now: u64) -> Vec<Stale> {
+    let mut stale = vec![];
+    for (category_name, category) in config.categories.iter() {
+        for (name, link) in category.links.iter().flatten() {
+            let tx = absolute_path(Path::new(&link.tx));
+            let Some(repo) = config.which(&tx).into_iter().find_map(|owner| match owner {
+                PathOwner::Repo { category, name } => config.find_repo(category, name).ok(),
+                PathOwner::Link { .. } => None,
+            }) else {
+                continue;
+            };
+            if repo.status() == RepoStatus::Missing {
+                continue;
+            }
+            let paths = match repo.uncommitted_paths(&tx) {
+                Ok(paths) => paths,
+                Err(err) => {
+                    warn!("{category_name} {name}: {err}");
+                    continue;
+                }
+            };
+            let checkout = Path::new(&repo.checkout_dir()).to_path_buf();
+            // Deleted files have no age, so they count as changed now
+            let Some(oldest) = paths
+                .iter()
+                .map(|path| modified(&checkout.join(path)).unwrap_or(now))
+                .min()
+            else {
+                continue;
+            };
+            let age = now.saturating_sub(oldest) / DAY;
+            if age >= days {
+                stale.push(Stale {
+                    subject: format!("link {category_name} {name}"),
+                    problem: format!(
+                        "{} uncommitted changes in {}",
+                        paths.len(),
+                        repo.display_name()
+                    ),
+                    days: age,
+                });
+            }
+        }
+    }
+    stale
+}
+
+/// Finds the work in config older than days, where now is the time in
+/// seconds since the unix epoch, sorted by subject
+pub fn run(config: &Config, days: u64, now: u64) -> Vec<Stale> {
+    let mut stale = stale_repos(config, days, now);
+    stale.append(&mut stale_links(config, days, now));
+    stale.sort_by(|a, b| a.subject.cmp(&b.subject));
+    stale
+}
@@ -0,0 +1,154 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Sharing the state dir between hosts through a git repo
+//!
+//! When configured, the state dir is itself a git working tree. It is pulled
+//! before a run, and committed and pushed after, so every host sees the same
+//! state. What only makes sense on the host that made it, like the trash, the
+//! cached config or the daemon socket, is ignored, see `GITIGNORE`.
+
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::utils::dir::{hostname, state_dir};
+use crate::utils::exec::Exec;
+
+/// Things in the state dir that only make sense on the host that made them:
+/// the trash, the cached configs, the backups and lock of the config, the
+/// daemon socket and the machine state
+const GITIGNORE: &str = "/trash/\n/config-cache/\n/config/\n/daemon.sock\n/machine.json\n";
+
+fn default_branch() -> String {
+    String::from("main")
+}
+
+/// Configures the git repo the state dir is synced with
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct StateConfig {
+    /// The remote to sync the state with
+    pub url: String,
+    /// The branch on the remote to sync with
+    #[serde(default = "default_branch")]
+    pub branch: String,
+}
+
+/// Runs git in dir, returning its stdout, or what it wrote to stderr if it
+/// failed
+fn git_output(dir: &Path, args: &[&str]) -> Result<String, String> {
+    debug!("state: git {}", args.join(" "));
+    match Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .exec_output()
+    {
+        Ok(output) if output.status.success() => {
+            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+        }
+        Ok(output) => {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            debug!("state: git {} failed: {stderr}", args.join(" "));
+            Err(stderr)
+        }
+        Err(err) => {
+            error!("state: failed to run git: {err}");
+            Err(format!("failed to run git: {err}"))
+        }
+    }
+}
+
+/// Runs git in dir, returning whether it succeeded
+fn git(dir: &Path, args: &[&str]) -> bool {
+    git_output(dir, args).is_ok()
+}
+
+impl StateConfig {
+    /// Makes sure dir is a git repo with the remote set up, ignoring what
+    /// only makes sense on this host
+    ///
+    /// The ignores are kept up to date in repos made by an older seidr, and
+    /// what they ignore is untracked, so it's no longer synced.
+    fn init(&self, dir: &Path) -> Result<(), String> {
+        if !dir.join(".git").exists() {
+            info!("initializing state repo in {}", dir.display());
+            fs::create_dir_all(dir).map_err(|err| format!("{err}"))?;
+            if !git(dir, &["init"]) || !git(dir, &["remote", "add", "origin", &self.url]) {
+                return Err(format!("failed to initialize state repo {}", dir.display()));
+            }
+        }
+        let gitignore = dir.join(".gitignore");
+        if fs::read_to_string(&gitignore).ok().as_deref() != Some(GITIGNORE) {
+            fs::write(&gitignore, GITIGNORE).map_err(|err| format!("{err}"))?;
+            let mut untrack = vec!["rm", "-r", "-q", "--cached", "--ignore-unmatch", "--"];
+            untrack.extend(GITIGNORE.lines().map(|ignored| ignored.trim_matches('/')));
+            git_output(dir, &untrack)
+                .map_err(|err| format!("failed to untrack host local state: {err}"))?;
+        }
+        Ok(())
+    }
+    /// Pulls the state from the remote
+    ///
+    /// A remote without the branch is fine, it will be created on push.
+    pub fn pull(&self) -> Result<(), String> {
+        self.pull_in(&state_dir())
+    }
+    /// Pulls the state in dir from the remote
+    pub fn pull_in(&self, dir: &Path) -> Result<(), String> {
+        self.init(dir)?;
+        if !git(dir, &["fetch", "origin", &self.branch]) {
+            warn!("state: no branch {} on {} yet", self.branch, self.url);
+            return Ok(());
+        }
+        // Commit first, so local state is rebased instead of overwritten
+        self.commit(dir)?;
+        if git(dir, &["rev-parse", "--verify", "HEAD"]) {
+            if !git(dir, &["rebase", &format!("origin/{}", self.branch)]) {
+                git(dir, &["rebase", "--abort"]);
+                return Err(format!(
+                    "failed to rebase state onto {}, resolve it in {}",
+                    self.url,
+                    dir.display()
+                ));
+            }
+        } else if !git(
+            dir,
+            &["reset", "--mixed", &format!("origin/{}", self.branch)],
+        ) {
+            return Err(format!("failed to check out state from {}", self.url));
+        }
+        Ok(())
+    }
+    /// Commits all changes to the state in dir, returning whether there was
+    /// anything to commit
+    fn commit(&self, dir: &Path) -> Result<bool, String> {
+        git_output(dir, &["add", "--all"]).map_err(|err| format!("failed to add state: {err}"))?;
+        if git(dir, &["diff", "--cached", "--quiet"]) {
+            debug!("state: nothing to commit");
+            return Ok(false);
+        }
+        git_output(
+            dir,
+            &["commit", "-m", &format!("seidr: state from {}", hostname())],
+        )
+        .map_err(|err| format!("failed to commit state: {err}"))?;
+        Ok(true)
+    }
+    /// Commits the state and pushes it to the remote
+    pub fn push(&self) -> Result<(), String> {
+        self.push_in(&state_dir())
+    }
+    /// Commits the state in dir and pushes it to the remote
+    pub fn push_in(&self, dir: &Path) -> Result<(), String> {
+        self.init(dir)?;
+        self.commit(dir)?;
+        if !git(dir, &["push", "origin", &format!("HEAD:{}", self.branch)]) {
+            return Err(format!("failed to push state to {}", self.url));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! The status of every repo as a table, once or refreshed periodically
+//!
+//! With `--watch`, the table is redrawn every few seconds, highlighting the
+//! rows that changed since the draw before, for keeping an eye on repos from a
+//! second monitor. Unlike the tui, nothing is read from the keyboard, and
+//! ctrl-c stops it.
+//!
+//! With `--stale`, repos with remote-tracking refs of branches gone from
+//! origin say how many, as found by asking origin, which `seidr branch prune`
+//! or `fetch_prune` clean up.
+
+use log::{debug, error, info, trace, warn};
+use std::io::{self, Write};
+use std::thread;
+use std::time::Duration;
+
+use crate::git::{Config, RepoStatus};
+use crate::history::format_time;
+use crate::tui::status_color;
+use crate::utils::term::{color, pad, width, BOLD, CLEAR, RESET, REVERSE};
+
+/// The status of a single repo
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Row {
+    pub category: String,
+    pub name: String,
+    pub status: RepoStatus,
+    /// The branch checked out, None if detached or missing
+    pub branch: Option<String>,
+    /// How many remote-tracking refs are of branches gone from origin, None
+    /// if not asked for, or unknown
+    pub stale: Option<usize>,
+}
+
+/// Returns the status of every repo in config, sorted by category and name,
+/// with the remote-tracking refs gone from origin counted if stale is set
+pub fn rows(config: &Config, stale: bool) -> Vec<Row> {
+    let mut rows: Vec<Row> = config
+        .categories
+        .iter()
+        .flat_map(|(category, cat)| {
+            cat.repos.iter().flatten().map(move |(name, repo)| {
+                let status = repo.status();
+                Row {
+                    category: category.clone(),
+                    name: name.clone(),
+                    status,
+                    branch: match status {
+                        RepoStatus::Missing => None,
+                        _ => repo.head_branch(),
+                    },
+                    stale: match status {
+                        RepoStatus::Missing => None,
+                        _ if stale => repo.stale_remote_refs().ok().map(|refs| refs.len()),
+                        _ => None,
+                    },
+                }
+            })
+        })
+        .collect();
+    rows.sort_by(|a, b| (&a.category, &a.name).cmp(&(&b.category, &b.name)));
+    rows
+}
+
+/// Renders rows as a table, marking those that changed since previous with a
+/// `*`, highlighted
+///
+/// Rows not in previous count as changed, unless previous is empty, as it is
+/// on the first draw.
+pub fn render(rows: &[Row], previous: &[Row]) -> String {
+    let columns = rows
+        .iter()
+        .map(|row| width(&row.category) + width(&row.name) + 1)
+        .max()
+        .unwrap_or(0);
+    let mut table = String::new();
+    for row in rows {
+        let changed = !previous.is_empty() && !previous.contains(row);
+        let (mark, highlight) = if changed {
+            ("*", color(REVERSE))
+        } else {
+            (" ", "")
+        };
+        let repo = pad(&format!("{} {}", row.category, row.name), columns);
+        table.push_str(&format!(
+            "{mark} {highlight}{repo}{reset}  {}{:9}{reset} {}{}\n",
+            status_color(row.status),
+            row.status.to_string(),
+            row.branch.as_deref().unwrap_or("-"),
+            match row.stale {
+                Some(0) | None => String::new(),
+                Some(stale) => format!("  {stale} stale refs"),
+            },
+            reset = color(RESET),
+        ));
+    }
+    if rows.is_empty() {
+        table.push_str("no repos in config\n");
+    }
+    table
+}
+
+/// Redraws the table every interval, until interrupted
+pub fn watch(config: &Config, interval: Duration, stale: bool) -> io::Result<()> {
+    let mut previous: Vec<Row> = vec![];
+    let mut stdout = io::stdout();
+    loop {
+        let current = rows(config, stale);
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs());
+        write!(
+            stdout,
+            "{CLEAR}{}every {}s, {}{}\n\n{}",
+            color(BOLD),
+            interval.as_secs(),
+            format_time(now),
+            color(RESET),
+            render(&current, &previous)
+        )?;
+        stdout.flush()?;
+        debug!("status of {} repos drawn", current.len());
+        previous = current;
+        thread::sleep(interval);
+    }
+}
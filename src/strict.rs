@@ -0,0 +1,187 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Strict checking of the config, for fields serde silently ignores
+//!
+//! A typo like `flgs:` deserializes fine, and is then never read. Denying
+//! unknown fields in serde itself would make every config with an old or
+//! misspelled field fail to load, so instead the config is checked separately,
+//! with the `--strict-config` flag, and always by `config validate`.
+//!
+//! The valid fields of each struct are asked of serde, by deserializing it from
+//! a deserializer that only records the fields, so they never go out of sync
+//! with the structs.
+
+use log::{debug, error, info, trace, warn};
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use serde_yaml::{Mapping, Value};
+
+use crate::condition::Condition;
+use crate::git::{
+    Category, CommitDateConfig, CommitTimeout, Config, HostProfile, Link, PushConfig, Repo,
+};
+use crate::parallel::Parallelism;
+use crate::secrets::{HttpsAuth, Secret};
+use crate::state::StateConfig;
+use crate::utils::suggest::with_suggestion;
+
+/// A deserializer that records the fields of the struct deserialized from it
+struct FieldProbe<'a>(&'a mut &'static [&'static str]);
+
+impl<'de> Deserializer<'de> for FieldProbe<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        fields: &'static [&'static str],
+        _: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = fields;
+        Err(de::Error::custom("probed"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// Returns the fields of T, as they are named in the config
+pub fn fields<T: DeserializeOwned>() -> &'static [&'static str] {
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldProbe(&mut fields));
+    fields
+}
+
+/// Checks the fields of value, which should be a T, at path
+///
+/// Returns the mapping, so its known fields can be checked in turn.
+fn check<'a, T: DeserializeOwned>(
+    value: &'a Value,
+    path: &str,
+    problems: &mut Vec<String>,
+) -> Option<&'a Mapping> {
+    let mapping = value.as_mapping()?;
+    let known = fields::<T>();
+    for key in mapping.keys() {
+        let Some(key) = key.as_str() else {
+            continue;
+        };
+        if !known.contains(&key) {
+            trace!("unknown field {key} in {path}");
+            problems.push(with_suggestion(
+                format!("{path}: unknown field {key}"),
+                key,
+                known.iter().copied(),
+            ));
+        }
+    }
+    Some(mapping)
+}
+
+/// Returns the path of field in the struct at path
+fn join(path: &str, field: &str) -> String {
+    if path.is_empty() {
+        field.to_string()
+    } else {
+        format!("{path}.{field}")
+    }
+}
+
+/// Checks every entry of the map at field of mapping, which should be Ts
+fn check_entries<T: DeserializeOwned>(
+    mapping: &Mapping,
+    field: &str,
+    path: &str,
+    problems: &mut Vec<String>,
+    mut each: impl FnMut(&Mapping, &str, &mut Vec<String>),
+) {
+    let Some(entries) = mapping.get(field).and_then(Value::as_mapping) else {
+        return;
+    };
+    for (name, value) in entries {
+        let Some(name) = name.as_str() else {
+            continue;
+        };
+        let path = format!("{}.{name}", join(path, field));
+        if let Some(entry) = check::<T>(value, &path, problems) {
+            each(entry, &path, problems);
+        }
+    }
+}
+
+/// Checks the single struct at field of mapping, which should be a T
+fn check_field<T: DeserializeOwned>(
+    mapping: &Mapping,
+    field: &str,
+    path: &str,
+    problems: &mut Vec<String>,
+) {
+    if let Some(value) = mapping.get(field) {
+        check::<T>(value, &join(path, field), problems);
+    }
+}
+
+/// Checks the condition at field of mapping, and the conditions it combines
+fn check_condition(mapping: &Mapping, field: &str, path: &str, problems: &mut Vec<String>) {
+    let path = join(path, field);
+    let conditions: Vec<(String, &Value)> = match mapping.get(field) {
+        Some(Value::Sequence(conditions)) => conditions
+            .iter()
+            .enumerate()
+            .map(|(i, condition)| (format!("{path}.{i}"), condition))
+            .collect(),
+        Some(condition) => vec![(path, condition)],
+        None => return,
+    };
+    for (path, condition) in conditions {
+        if let Some(condition) = check::<Condition>(condition, &path, problems) {
+            for combined in ["all", "any", "not"] {
+                check_condition(condition, combined, &path, problems);
+            }
+        }
+    }
+}
+
+/// Returns a message for every field in the config yaml that isn't a field of
+/// the config, sorted
+pub fn unknown_fields(yaml: &str) -> Result<Vec<String>, serde_yaml::Error> {
+    let value: Value = serde_yaml::from_str(yaml)?;
+    let mut problems = vec![];
+    let Some(config) = check::<Config>(&value, "config", &mut problems) else {
+        return Ok(problems);
+    };
+    check_field::<StateConfig>(config, "state", "", &mut problems);
+    check_field::<Parallelism>(config, "parallelism", "", &mut problems);
+    check_entries::<HostProfile>(config, "hosts", "", &mut problems, |_, _, _| ());
+    check_entries::<Category>(
+        config,
+        "categories",
+        "",
+        &mut problems,
+        |category, path, problems| {
+            check_entries::<Link>(category, "links", path, problems, |link, path, problems| {
+                check_condition(link, "condition", path, problems);
+            });
+            check_entries::<Repo>(category, "repos", path, problems, |repo, path, problems| {
+                check_field::<PushConfig>(repo, "push", path, problems);
+                check_field::<CommitDateConfig>(repo, "commit_date", path, problems);
+                check_field::<CommitTimeout>(repo, "commit_timeout", path, problems);
+                check_field::<HttpsAuth>(repo, "auth", path, problems);
+                if let Some(auth) = repo.get("auth").and_then(Value::as_mapping) {
+                    check_field::<Secret>(auth, "token", &join(path, "auth"), problems);
+                }
+            });
+        },
+    );
+    problems.sort();
+    Ok(problems)
+}
@@ -0,0 +1,121 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Summarizes what was committed in the repos over a stretch of time
+//!
+//! The commits of every repo are gathered into one document, for reports like
+//! what changed on this machine this month. The stretch starts at a date, or
+//! at a run in the history, so it can be what happened since the last pull.
+
+use clap::ValueEnum;
+use log::{debug, error, info, trace, warn};
+use serde::Serialize;
+
+use crate::git::{Config, RepoStatus};
+use crate::history::{format_time, RunRecord};
+
+/// The format the summary is written in
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, ValueEnum)]
+pub enum SummaryFormat {
+    #[default]
+    Markdown,
+    Json,
+}
+
+/// A single commit
+#[derive(PartialEq, Eq, Debug, Serialize)]
+pub struct Commit {
+    pub hash: String,
+    pub subject: String,
+}
+
+/// The commits of a repo, newest first
+#[derive(PartialEq, Eq, Debug, Serialize)]
+pub struct RepoSummary {
+    pub category: String,
+    pub name: String,
+    pub commits: Vec<Commit>,
+}
+
+/// Resolves since to something git understands as a date
+///
+/// The id of a run in records stands for when the run started, anything else
+/// is given to git as is.
+pub fn resolve_since(since: &str, records: &[RunRecord]) -> String {
+    match records.iter().find(|record| record.id == since) {
+        Some(record) => {
+            debug!("{since} started at {}", format_time(record.started));
+            format!("@{}", record.started)
+        }
+        None => since.to_string(),
+    }
+}
+
+/// Gathers the commits of repos in config made since, and before until,
+/// leaving out repos without any, sorted by category and name
+pub fn run(config: &Config, since: &str, until: Option<&str>) -> Vec<RepoSummary> {
+    let mut summaries = vec![];
+    for (category_name, category) in config.categories.iter() {
+        for (name, repo) in category.repos.iter().flatten() {
+            if repo.status() == RepoStatus::Missing {
+                continue;
+            }
+            let commits = match repo.commits_between(since, until) {
+                Ok(commits) => commits,
+                Err(err) => {
+                    warn!("{category_name} {name}: {err}");
+                    continue;
+                }
+            };
+            if commits.is_empty() {
+                continue;
+            }
+            summaries.push(RepoSummary {
+                category: category_name.clone(),
+                name: name.clone(),
+                commits: commits
+                    .into_iter()
+                    .map(|(hash, subject)| Commit { hash, subject })
+                    .collect(),
+            });
+        }
+    }
+    summaries.sort_by(|a, b| (&a.category, &a.name).cmp(&(&b.category, &b.name)));
+    summaries
+}
+
+/// Renders summaries as markdown, a section per repo, titled with what the
+/// user asked for
+pub fn markdown(summaries: &[RepoSummary], since: &str, until: Option<&str>) -> String {
+    let mut out = match until {
+        Some(until) => format!("# Changes from {since} until {until}\n"),
+        None => format!("# Changes since {since}\n"),
+    };
+    if summaries.is_empty() {
+        out.push_str("\nNothing was committed.\n");
+    }
+    for summary in summaries {
+        out.push_str(&format!("\n## {} {}\n\n", summary.category, summary.name));
+        for commit in &summary.commits {
+            out.push_str(&format!("- {} {}\n", commit.hash, commit.subject));
+        }
+    }
+    out
+}
+
+/// Renders summaries in format
+pub fn render(
+    summaries: &[RepoSummary],
+    format: SummaryFormat,
+    since: &str,
+    until: Option<&str>,
+) -> Result<String, String> {
+    match format {
+        SummaryFormat::Markdown => Ok(markdown(summaries, since, until)),
+        SummaryFormat::Json => serde_json::to_string_pretty(summaries)
+            .map(|json| json + "\n")
+            .map_err(|err| err.to_string()),
+    }
+}
@@ -0,0 +1,81 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! A minimal template engine for template links
+//!
+//! Templates are plain text with `{{ name }}` replaced by the variable name.
+//! Variables come from `vars` in the config, overridden by `vars` of the host
+//! profile, and additionally:
+//!
+//! - `hostname`, the hostname of the machine
+//! - `env.NAME`, the environment variable NAME
+//!
+//! A literal `{{` is written as `{{ "{{" }}`. Using a variable that isn't set
+//! is an error, so a typo never renders silently.
+//!
+//! The same variables are interpolated into paths in the config, written as
+//! `${name}`, see `interpolate`.
+
+use log::{debug, error, info, trace, warn};
+use std::collections::HashMap;
+use std::env;
+
+use crate::utils::dir::hostname;
+
+/// Looks up a single variable, see the module docs for where they come from
+fn lookup(name: &str, vars: &HashMap<String, String>) -> Result<String, String> {
+    if let Some(value) = vars.get(name) {
+        return Ok(value.clone());
+    }
+    if name == "hostname" {
+        return Ok(hostname());
+    }
+    if let Some(var) = name.strip_prefix("env.") {
+        return env::var(var).map_err(|_| format!("environment variable {var} is not set"));
+    }
+    if name == "\"{{\"" {
+        return Ok(String::from("{{"));
+    }
+    Err(format!("variable {name} is not set"))
+}
+
+/// Replaces `${name}` in path with the variable name, so a path like
+/// `${code_root}/seidr` can differ per host
+pub fn interpolate(path: &str, vars: &HashMap<String, String>) -> Result<String, String> {
+    let mut interpolated = String::with_capacity(path.len());
+    let mut rest = path;
+    while let Some(start) = rest.find("${") {
+        interpolated.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find('}') else {
+            return Err(format!("{path}: unclosed ${{"));
+        };
+        let name = after[..end].trim();
+        interpolated.push_str(&lookup(name, vars).map_err(|err| format!("{path}: {err}"))?);
+        rest = &after[end + 1..];
+    }
+    interpolated.push_str(rest);
+    Ok(interpolated)
+}
+
+/// Renders template with vars
+pub fn render(template: &str, vars: &HashMap<String, String>) -> Result<String, String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            let line = template[..template.len() - rest.len() + start].lines().count().max(1);
+            return Err(format!("line {line}: unclosed {{{{"));
+        };
+        let name = after[..end].trim();
+        trace!("rendering {name}");
+        rendered.push_str(&lookup(name, vars)?);
+        rest = &after[end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
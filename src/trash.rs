@@ -0,0 +1,234 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Safe deletion of files seidr replaces
+//!
+//! Instead of deleting files, they are moved into a per-run directory under
+//! the trash in the state dir, alongside an index of where they came from, so
+//! they can be restored later.
+
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::utils::dir::{absolute_path, state_dir};
+
+const INDEX_FILE: &str = "index.yaml";
+
+/// Held while an index is read and written again, as files are trashed in
+/// parallel, and would otherwise get the same stored name
+static INDEX: Mutex<()> = Mutex::new(());
+
+/// A single file that has been moved to the trash
+#[derive(Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct TrashedFile {
+    /// Where the file was before it was trashed
+    pub original: String,
+    /// The name of the file inside the run directory
+    pub stored: String,
+}
+
+/// The trash directory, containing a directory for each run of seidr
+pub struct Trash {
+    dir: PathBuf,
+}
+
+/// Returns the id of the current run, used as the name of its trash directory
+/// and history record
+///
+/// Ids sort chronologically.
+pub fn run_id() -> &'static str {
+    static RUN_ID: OnceLock<String> = OnceLock::new();
+    RUN_ID.get_or_init(|| {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        format!("{secs:012}-{}", std::process::id())
+    })
+}
+
+/// Copies path to dest, recursing into directories, and copying symlinks as
+/// symlinks
+pub fn copy_path(path: &Path, dest: &Path) -> io::Result<()> {
+    if path.is_symlink() {
+        symlink(path.read_link()?, dest)
+    } else if path.is_dir() {
+        fs::create_dir(dest)?;
+        fs::set_permissions(dest, fs::metadata(path)?.permissions())?;
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            copy_path(&entry.path(), &dest.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(path, dest).map(|_| ())
+    }
+}
+
+/// Moves path to dest by copying it and then removing path, like a move
+/// between filesystems has to
+///
+/// If the copy fails halfway, what was copied is removed again, and path is
+/// left alone. dest mustn't exist.
+pub fn copy_then_remove(path: &Path, dest: &Path) -> io::Result<()> {
+    if dest.exists() || dest.is_symlink() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", dest.display()),
+        ));
+    }
+    if let Err(err) = copy_path(path, dest) {
+        let cleanup = if dest.is_dir() && !dest.is_symlink() {
+            fs::remove_dir_all(dest)
+        } else {
+            fs::remove_file(dest)
+        };
+        if let Err(cleanup) = cleanup.or_else(|err| match err.kind() {
+            io::ErrorKind::NotFound => Ok(()),
+            _ => Err(err),
+        }) {
+            warn!(
+                "failed to remove partial copy {}: {cleanup}",
+                dest.display()
+            );
+        }
+        return Err(err);
+    }
+    if path.is_dir() && !path.is_symlink() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// The errno of renaming across filesystems, the same on Linux and macOS
+const EXDEV: i32 = 18;
+
+/// Moves path to dest, falling back to copying if they're on different
+/// filesystems
+fn move_path(path: &Path, dest: &Path) -> io::Result<()> {
+    match fs::rename(path, dest) {
+        Err(err) if err.raw_os_error() == Some(EXDEV) => {
+            debug!("{} is on another filesystem, copying", path.display());
+            copy_then_remove(path, dest)
+        }
+        result => result,
+    }
+}
+
+impl Default for Trash {
+    fn default() -> Self {
+        Trash::at(state_dir().join("trash"))
+    }
+}
+
+impl Trash {
+    /// Uses dir as the trash directory
+    pub fn at(dir: PathBuf) -> Self {
+        Trash { dir }
+    }
+    fn read_index(run_dir: &Path) -> io::Result<Vec<TrashedFile>> {
+        match fs::read_to_string(run_dir.join(INDEX_FILE)) {
+            Ok(yaml) => serde_yaml::from_str(&yaml)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+            Err(err) => Err(err),
+        }
+    }
+    fn write_index(run_dir: &Path, index: &Vec<TrashedFile>) -> io::Result<()> {
+        let yaml = serde_yaml::to_string(index)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        fs::write(run_dir.join(INDEX_FILE), yaml)
+    }
+    /// Moves path into the trash directory of the current run
+    pub fn put(&self, path: &Path) -> io::Result<TrashedFile> {
+        let run_dir = self.dir.join(run_id());
+        fs::create_dir_all(&run_dir)?;
+        let _index = INDEX.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut index = Self::read_index(&run_dir)?;
+        let trashed = TrashedFile {
+            original: absolute_path(path).to_string_lossy().to_string(),
+            stored: format!("{}", index.len()),
+        };
+        move_path(path, &run_dir.join(&trashed.stored))?;
+        debug!("trashed {} in run {}", trashed.original, run_id());
+        index.push(trashed);
+        Self::write_index(&run_dir, &index)?;
+        Ok(index.pop().expect("index can't be empty"))
+    }
+    /// Lists all trashed files, grouped by the run that trashed them
+    pub fn list(&self) -> io::Result<Vec<(String, Vec<TrashedFile>)>> {
+        let mut runs = vec![];
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(runs),
+            Err(err) => return Err(err),
+        };
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                let index = Self::read_index(&entry.path())?;
+                runs.push((entry.file_name().to_string_lossy().to_string(), index));
+            }
+        }
+        runs.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(runs)
+    }
+    /// Restores files trashed by run to where they were
+    ///
+    /// If original is given, only that file is restored. Files are never
+    /// restored on top of existing files.
+    pub fn restore(&self, run: &str, original: Option<&Path>) -> io::Result<Vec<TrashedFile>> {
+        let run_dir = self.dir.join(run);
+        if !run_dir.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no trash for run {run}"),
+            ));
+        }
+        let original = original.map(absolute_path);
+        let _index = INDEX.lock().unwrap_or_else(PoisonError::into_inner);
+        let (restore, keep): (Vec<TrashedFile>, Vec<TrashedFile>) = Self::read_index(&run_dir)?
+            .into_iter()
+            .partition(|trashed| {
+                original
+                    .as_ref()
+                    .map_or(true, |original| Path::new(&trashed.original) == original)
+            });
+        let mut restored = vec![];
+        let mut keep = keep;
+        for trashed in restore {
+            let dest = Path::new(&trashed.original);
+            if dest.exists() || dest.is_symlink() {
+                warn!("not restoring {}: file exists", trashed.original);
+                keep.push(trashed);
+                continue;
+            }
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            move_path(&run_dir.join(&trashed.stored), dest)?;
+            restored.push(trashed);
+        }
+        if keep.is_empty() {
+            fs::remove_dir_all(&run_dir)?;
+        } else {
+            Self::write_index(&run_dir, &keep)?;
+        }
+        Ok(restored)
+    }
+    /// Permanently deletes everything in the trash
+    pub fn empty(&self) -> io::Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,233 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Interactive dashboard of categories and repos
+
+use log::{debug, error, info, trace, warn};
+use std::io::{self, Read, Write};
+
+use crate::git::{Config, Repo, RepoStatus};
+use crate::utils::exec;
+use crate::utils::strings::result_str;
+use crate::utils::term::{
+    color, pad, RawMode, BLUE, BOLD, CLEAR, GREEN, RED, RESET, REVERSE, YELLOW,
+};
+
+const HELP: &str = "j/k: move  p: pull  c: commit  m: commit with msg  P: push  l: link category  r: refresh  q: quit";
+
+/// A single repo row on the dashboard
+struct Entry<'a> {
+    category: &'a str,
+    name: &'a str,
+    repo: &'a Repo,
+    status: RepoStatus,
+    /// Output of the last thing done to this repo
+    output: String,
+}
+
+impl Entry<'_> {
+    fn refresh(&mut self) {
+        self.status = self.repo.status();
+        self.output = match self.status {
+            RepoStatus::Missing => String::from("working tree missing, try cloning"),
+            _ => self.repo.status_short(),
+        };
+    }
+}
+
+/// The color a status is shown in
+pub fn status_color(status: RepoStatus) -> &'static str {
+    match status {
+        RepoStatus::Clean => color(GREEN),
+        RepoStatus::Dirty | RepoStatus::Diverged | RepoStatus::Detached => color(YELLOW),
+        RepoStatus::Missing | RepoStatus::Unknown => color(RED),
+    }
+}
+
+struct Dashboard<'a> {
+    config: &'a Config,
+    entries: Vec<Entry<'a>>,
+    selected: usize,
+}
+
+impl<'a> Dashboard<'a> {
+    fn new(config: &'a Config) -> Self {
+        let mut entries: Vec<Entry> = config
+            .categories
+            .iter()
+            .flat_map(|(category, cat)| {
+                cat.repos.iter().flatten().map(|(name, repo)| Entry {
+                    category,
+                    name,
+                    repo,
+                    status: RepoStatus::Unknown,
+                    output: String::new(),
+                })
+            })
+            .collect();
+        entries.sort_by_key(|entry| (entry.category, entry.name));
+        entries.iter_mut().for_each(Entry::refresh);
+        Dashboard {
+            config,
+            entries,
+            selected: 0,
+        }
+    }
+
+    /// Draws the whole dashboard
+    ///
+    /// In raw mode "\n" doesn't return the carriage, so lines end in "\r\n".
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        write!(out, "{CLEAR}{BOLD}seidr dashboard{RESET}\r\n\r\n")?;
+        let mut category = "";
+        for (i, entry) in self.entries.iter().enumerate() {
+            if entry.category != category {
+                category = entry.category;
+                write!(out, "{BOLD}{}{category}{RESET}\r\n", color(BLUE))?;
+            }
+            let selected = if i == self.selected { REVERSE } else { "" };
+            let archived = if entry.repo.archived {
+                ", archived"
+            } else {
+                ""
+            };
+            write!(
+                out,
+                "  {selected}{}{RESET} {}{}{archived}{RESET}\r\n",
+                pad(entry.name, 24),
+                status_color(entry.status),
+                entry.status
+            )?;
+        }
+        if self.entries.is_empty() {
+            write!(out, "  no repos in config\r\n")?;
+        }
+        write!(out, "\r\n{HELP}\r\n\r\n")?;
+        if let Some(entry) = self.entries.get(self.selected) {
+            write!(out, "{BOLD}{} {}{RESET}\r\n", entry.category, entry.name)?;
+            for line in entry.output.lines() {
+                write!(out, "{line}\r\n")?;
+            }
+        }
+        out.flush()
+    }
+
+    /// Runs an operation on the selected repo, recording the result along
+    /// with what the commands it ran wrote
+    fn run<F>(&mut self, op: &str, f: F)
+    where
+        F: Fn(&Repo) -> bool,
+    {
+        if let Some(entry) = self.entries.get_mut(self.selected) {
+            if entry.repo.skips(op) {
+                entry.output = format!("{} is archived, not running {op}", entry.name);
+                return;
+            }
+            let (success, captured) = exec::capture(|| f(entry.repo));
+            entry.refresh();
+            entry.output = format!("{} {op}\n{captured}{}", result_str(success), entry.output);
+        }
+    }
+
+    /// Shows a note in the output of the selected repo
+    fn note(&mut self, note: &str) {
+        if let Some(entry) = self.entries.get_mut(self.selected) {
+            entry.output = note.to_string();
+        }
+    }
+
+    /// Links every link in the category of the selected repo
+    fn link(&mut self) {
+        let Some(entry) = self.entries.get_mut(self.selected) else {
+            return;
+        };
+        let mut output = String::new();
+        if let Some(links) = self
+            .config
+            .categories
+            .get(entry.category)
+            .and_then(|category| category.links.as_ref())
+        {
+            for link in links
+                .values()
+                .filter(|link| self.config.is_link_enabled(link))
+            {
+                match link.link_with(&self.config.template_vars(), self.config.settings.force) {
+                    Ok(_) => {
+                        output.push_str(&format!("{} {}: link\n", result_str(true), link.name))
+                    }
+                    Err(e) => output.push_str(&format!("{} {e}\n", result_str(false))),
+                }
+            }
+        }
+        if output.is_empty() {
+            output = format!("no links in category {}", entry.category);
+        }
+        entry.output = output;
+    }
+}
+
+/// Runs the interactive dashboard until the user quits
+pub fn run(config: &Config) -> io::Result<()> {
+    let mut dashboard = Dashboard::new(config);
+    let mut raw = Some(RawMode::enable()?);
+    let mut stdout = io::stdout();
+    let mut stdin = io::stdin();
+    // A key the terminal sends as a sequence, like an arrow key, arrives in
+    // one read, so a bare ESC is never waited on for more
+    let mut keys = [0u8; 16];
+    loop {
+        dashboard.draw(&mut stdout)?;
+        let read = stdin.read(&mut keys)?;
+        if read == 0 {
+            break;
+        }
+        trace!("dashboard got keys {:?}", &keys[..read]);
+        match keys[..read] {
+            // Arrow keys are sent as ESC [ A/B
+            [0x1b, b'[', b'A', ..] => dashboard.selected = dashboard.selected.saturating_sub(1),
+            [0x1b, b'[', b'B', ..] if dashboard.selected + 1 < dashboard.entries.len() => {
+                dashboard.selected += 1
+            }
+            [0x1b, ..] => (),
+            [b'q' | 3, ..] => break,
+            [b'j', ..] => {
+                if dashboard.selected + 1 < dashboard.entries.len() {
+                    dashboard.selected += 1;
+                }
+            }
+            [b'k', ..] => dashboard.selected = dashboard.selected.saturating_sub(1),
+            [b'p', ..] => dashboard.run("pull", Repo::pull),
+            [b'P', ..] => dashboard.run("push", Repo::push),
+            // The message is read as a line, so we leave raw mode meanwhile
+            [b'm', ..] => {
+                drop(raw.take());
+                print!("{CLEAR}commit message (empty to cancel): ");
+                stdout.flush()?;
+                let mut msg = String::new();
+                stdin.read_line(&mut msg)?;
+                raw = Some(RawMode::enable()?);
+                match msg.trim() {
+                    "" => dashboard.note("no message given, not committing"),
+                    msg => dashboard.run("commit", |repo| config.commit_repo(repo, Some(msg), msg)),
+                }
+            }
+            // The editor needs a sane terminal, so we leave raw mode meanwhile
+            [b'c', ..] => {
+                drop(raw.take());
+                print!("{CLEAR}");
+                stdout.flush()?;
+                dashboard.run("commit", |repo| config.commit_editor(repo));
+                raw = Some(RawMode::enable()?);
+            }
+            [b'l', ..] => dashboard.link(),
+            [b'r', ..] => dashboard.entries.iter_mut().for_each(Entry::refresh),
+            _ => (),
+        }
+    }
+    drop(raw);
+    print!("{CLEAR}");
+    stdout.flush()
+}
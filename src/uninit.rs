@@ -0,0 +1,213 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Tearing down everything seidr manages on a machine
+//!
+//! First a plan of what would be removed is made, so it can be shown to the
+//! user before anything happens. Only things seidr is sure it manages are in
+//! the plan: links that point at their tx, checkouts that are the top level of
+//! their own repository, and the state dir.
+
+use log::{debug, error, info, trace, warn};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::git::{Config, RepoStatus};
+use crate::utils::dir::{absolute_path, home_dir, state_dir};
+use crate::utils::exec::Exec;
+use crate::utils::i18n::{tr, Msg};
+use crate::utils::strings::result_str;
+
+/// A single thing to remove
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Removal<'a> {
+    Link {
+        category: &'a str,
+        name: &'a str,
+        rx: &'a str,
+    },
+    Repo {
+        category: &'a str,
+        name: &'a str,
+        dir: String,
+        status: RepoStatus,
+    },
+    State(PathBuf),
+}
+
+impl fmt::Display for Removal<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Removal::Link { category, name, rx } => write!(f, "link {category} {name}: {rx}"),
+            Removal::Repo {
+                category,
+                name,
+                dir,
+                status,
+            } => write!(f, "repo {category} {name}: {dir} ({status})"),
+            Removal::State(dir) => write!(f, "state: {}", dir.display()),
+        }
+    }
+}
+
+/// Plans the teardown of config, including cloned repos if repos is set
+pub fn plan(config: &Config, repos: bool) -> Vec<Removal> {
+    let mut links = vec![];
+    let mut checkouts = vec![];
+    for (category, cat) in config.categories.iter() {
+        for (name, link) in cat.links.iter().flatten() {
+            // System links are torn down separately, with --system
+            if link.system != config.settings.system {
+                continue;
+            }
+            if link.is_linked() {
+                links.push(Removal::Link {
+                    category,
+                    name,
+                    rx: &link.rx,
+                });
+            }
+        }
+        if !repos {
+            continue;
+        }
+        for (name, repo) in cat.repos.iter().flatten() {
+            if repo.status() == RepoStatus::Missing {
+                continue;
+            }
+            if let Err(err) = repo.verify_toplevel() {
+                warn!("not removing {category} {name}: {err}");
+                continue;
+            }
+            // A repo tracking the home dir is checked out there, but removing
+            // it would remove everything
+            if Path::new(&home_dir()).starts_with(absolute_path(Path::new(&repo.checkout_dir()))) {
+                warn!("not removing {category} {name}, it contains the home dir");
+                continue;
+            }
+            checkouts.push(Removal::Repo {
+                category,
+                name,
+                dir: repo.checkout_dir(),
+                status: repo.status(),
+            });
+        }
+    }
+    links.sort_by_key(ToString::to_string);
+    checkouts.sort_by_key(ToString::to_string);
+    let mut plan = links;
+    plan.extend(checkouts);
+    let state = state_dir();
+    if state.exists() {
+        plan.push(Removal::State(state));
+    }
+    plan
+}
+
+/// Archives the checkout in dir as a tarball in archive
+fn archive_repo(category: &str, name: &str, dir: &str, archive: &Path) -> Result<(), String> {
+    let dir = Path::new(dir);
+    let (Some(parent), Some(base)) = (dir.parent(), dir.file_name()) else {
+        return Err(format!("can't archive {}", dir.display()));
+    };
+    fs::create_dir_all(archive).map_err(|err| format!("{}: {err}", archive.display()))?;
+    let tarball = archive.join(format!("{category}-{name}.tar.gz"));
+    let output = Command::new("tar")
+        .arg("-czf")
+        .arg(&tarball)
+        .arg("-C")
+        .arg(parent)
+        .arg(base)
+        .exec_output()
+        .map_err(|err| format!("tar failed to run: {err}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "failed to archive to {}: {}",
+            tarball.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    info!("archived {} to {}", dir.display(), tarball.display());
+    Ok(())
+}
+
+/// Removes a single planned thing, archiving repos to archive if given
+pub fn remove(config: &Config, removal: &Removal, archive: Option<&Path>) -> Result<(), String> {
+    debug!("removing {removal}");
+    match removal {
+        Removal::Link { category, name, .. } => {
+            let Some(link) = config.categories[*category]
+                .links
+                .as_ref()
+                .and_then(|links| links.get(*name))
+            else {
+                return Err(String::from("no such link"));
+            };
+            link.unlink().map(|_| ()).map_err(|err| format!("{err}"))
+        }
+        Removal::Repo {
+            category,
+            name,
+            dir,
+            ..
+        } => {
+            if let Some(archive) = archive {
+                archive_repo(category, name, dir, archive)?;
+            }
+            fs::remove_dir_all(dir).map_err(|err| format!("{dir}: {err}"))
+        }
+        Removal::State(dir) => {
+            fs::remove_dir_all(dir).map_err(|err| format!("{}: {err}", dir.display()))
+        }
+    }
+}
+
+/// Tears down config, printing the plan and what was removed
+///
+/// Unless settings force it, confirm is asked before removing anything.
+/// Returns whether everything in the plan was removed.
+pub fn run<F>(
+    config: &Config,
+    repos: bool,
+    archive: Option<&Path>,
+    confirm: F,
+) -> Result<bool, String>
+where
+    F: Fn(&str) -> bool,
+{
+    let archive = archive.map(absolute_path);
+    if archive
+        .as_ref()
+        .map_or(false, |archive| archive.starts_with(state_dir()))
+    {
+        return Err(String::from(
+            "the archive can't be in the state dir, as it is removed",
+        ));
+    }
+    let plan = plan(config, repos);
+    if plan.is_empty() {
+        eprintln!("{}", tr(Msg::NothingToRemove));
+        return Ok(true);
+    }
+    for removal in &plan {
+        eprintln!("{removal}");
+    }
+    if !config.settings.force && !confirm(tr(Msg::RemoveAll)) {
+        return Ok(false);
+    }
+    let mut removed_all = true;
+    for removal in &plan {
+        match remove(config, removal, archive.as_deref()) {
+            Ok(()) => eprintln!("{} removed {removal}", result_str(true)),
+            Err(err) => {
+                removed_all = false;
+                eprintln!("{} {removal}: {err}", result_str(false));
+            }
+        }
+    }
+    Ok(removed_all)
+}
@@ -6,4 +6,13 @@
 //! Sublibrary for useful functions
 
 pub mod dir;
+pub mod exec;
+pub mod glob;
+pub mod i18n;
+pub mod prompt;
+pub mod sha256;
 pub mod strings;
+pub mod suggest;
+pub mod term;
+pub mod url;
+pub mod yaml;
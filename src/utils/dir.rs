@@ -9,7 +9,8 @@
 use log::{debug, error, info, trace, warn};
 
 use std::env;
-use std::path::Path;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
 
 /// Returns the users current dir
 ///
@@ -35,6 +36,72 @@ pub fn home_dir() -> String {
         .expect("Failed to turn home_dir into a valid string")
 }
 
+/// Returns the hostname of the machine
+///
+/// Falls back to "localhost" if it can't be determined.
+pub fn hostname() -> String {
+    match env::var("HOSTNAME") {
+        Ok(host) if !host.is_empty() => host,
+        _ => std::fs::read_to_string("/etc/hostname")
+            .map(|host| host.trim().to_string())
+            .ok()
+            .filter(|host| !host.is_empty())
+            .unwrap_or_else(|| String::from("localhost")),
+    }
+}
+
+/// Whether path is a file anyone may execute
+pub fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .map_or(false, |meta| meta.is_file() && meta.mode() & 0o111 != 0)
+}
+
+/// Whether seidr runs as root
+pub fn is_root() -> bool {
+    // /proc/self is owned by the effective user of the process
+    std::fs::metadata("/proc/self").map_or(false, |meta| meta.uid() == 0)
+}
+
+/// Returns the directory seidr keeps its state in
+///
+/// This is `$XDG_STATE_HOME/seidr`, falling back to `~/.local/state/seidr`.
+pub fn state_dir() -> PathBuf {
+    match env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("seidr"),
+        _ => PathBuf::from(home_dir()).join(".local/state/seidr"),
+    }
+}
+
+/// Expands a leading `~` in path to the home dir
+pub fn expand_tilde(path: &str) -> String {
+    match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => home_dir() + rest,
+        _ => path.to_string(),
+    }
+}
+
+/// Returns an absolute version of path, relative to the current dir
+///
+/// Unlike `canonicalize`, this is purely lexical, so symlinks are not followed
+/// and the path doesn't have to exist.
+pub fn absolute_path(path: &Path) -> PathBuf {
+    let mut absolute = if path.is_absolute() {
+        PathBuf::new()
+    } else {
+        PathBuf::from(current_dir())
+    };
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            component => absolute.push(component),
+        }
+    }
+    absolute
+}
+
 /// Changes working directory into a repository.
 ///
 /// WARNING: NOT THREAD SAFE
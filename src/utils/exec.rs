@@ -0,0 +1,217 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Running external commands
+//!
+//! Every command seidr spawns should go through `Exec`, so that on `-vv` the
+//! exact command line is printed before it runs, along with its exit code
+//! after, letting a failing operation be reproduced by hand.
+
+use log::{debug, error, info, trace, warn};
+use std::cell::RefCell;
+use std::io;
+use std::process::{Command, ExitStatus, Output};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::secrets;
+use crate::settings;
+
+/// The verbosity at which commands are transcribed
+pub const TRANSCRIPT_VERBOSITY: u8 = 2;
+
+/// Quotes s for a POSIX shell, if it needs quoting
+pub fn shell_quote(s: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c);
+    if !s.is_empty() && s.chars().all(safe) {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+/// Renders command as a line that can be pasted into a shell
+pub fn command_line(command: &Command) -> String {
+    let mut words = vec![];
+    if let Some(dir) = command.get_current_dir() {
+        words.push(format!("cd {} &&", shell_quote(&dir.to_string_lossy())));
+    }
+    for (key, value) in command.get_envs() {
+        // Removed variables have no value, and can't be set like this
+        if key == secrets::TOKEN_VAR {
+            words.push(format!("{}=<redacted>", key.to_string_lossy()));
+        } else if let Some(value) = value {
+            words.push(format!(
+                "{}={}",
+                key.to_string_lossy(),
+                shell_quote(&value.to_string_lossy())
+            ));
+        }
+    }
+    words.push(shell_quote(&command.get_program().to_string_lossy()));
+    words.extend(
+        command
+            .get_args()
+            .map(|arg| shell_quote(&arg.to_string_lossy())),
+    );
+    words.join(" ")
+}
+
+thread_local! {
+    /// What commands wrote while capturing, if capturing
+    static CAPTURED: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// Runs f, returning what it returned along with a transcript of the
+/// commands it ran through `Exec::exec_output`, each command line followed by
+/// what it wrote to stdout and stderr
+///
+/// Commands run through `exec_status` inherit the terminal, like an editor
+/// needs, and aren't captured.
+pub fn capture<R>(f: impl FnOnce() -> R) -> (R, String) {
+    let outer = CAPTURED.with(|captured| captured.replace(Some(String::new())));
+    let result = f();
+    let output = CAPTURED.with(|captured| captured.replace(outer));
+    (result, output.unwrap_or_default())
+}
+
+fn record(command: &Command, output: &Output) {
+    CAPTURED.with(|captured| {
+        if let Some(captured) = captured.borrow_mut().as_mut() {
+            captured.push_str(&format!("$ {}\n", command_line(command)));
+            captured.push_str(&String::from_utf8_lossy(&output.stdout));
+            captured.push_str(&String::from_utf8_lossy(&output.stderr));
+        }
+    });
+}
+
+fn transcribing() -> bool {
+    settings::VERBOSITY.load(Ordering::Relaxed) >= TRANSCRIPT_VERBOSITY
+}
+
+fn transcribe_exit(status: Result<ExitStatus, &io::Error>) {
+    match status {
+        Ok(status) => match status.code() {
+            Some(code) => eprintln!("exit {code}"),
+            None => eprintln!("exit {status}"),
+        },
+        Err(err) => eprintln!("failed to run: {err}"),
+    }
+}
+
+/// Kills every process descended from pid, deepest first
+fn kill_descendants(pid: u32) {
+    let Ok(output) = Command::new("pgrep").arg("-P").arg(pid.to_string()).output() else {
+        return;
+    };
+    for child in String::from_utf8_lossy(&output.stdout).split_whitespace() {
+        if let Ok(child) = child.parse() {
+            kill_descendants(child);
+            debug!("killing {child}");
+            let _ = Command::new("kill").arg(child.to_string()).status();
+        }
+    }
+}
+
+/// How often a command with a timeout is checked on
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a command gets to exit on its own, after what it spawned is killed
+const GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Running a command, transcribing it on high verbosity
+pub trait Exec {
+    /// Like `Command::output`
+    fn exec_output(&mut self) -> io::Result<Output>;
+    /// Like `Command::status`
+    fn exec_status(&mut self) -> io::Result<ExitStatus>;
+    /// Like `Command::status`, but gives up after timeout, returning None
+    ///
+    /// On timeout, what the command spawned, like the editor of git commit, is
+    /// killed first, so the command can clean up after itself, and only then
+    /// the command itself, if it doesn't exit within a grace period.
+    fn exec_status_timeout(&mut self, timeout: Duration) -> io::Result<Option<ExitStatus>>;
+}
+
+impl Exec for Command {
+    fn exec_output(&mut self) -> io::Result<Output> {
+        debug!("running {}", command_line(self));
+        if transcribing() {
+            eprintln!("$ {}", command_line(self));
+        }
+        let output = self.output();
+        if transcribing() {
+            transcribe_exit(output.as_ref().map(|output| output.status));
+        }
+        if let Ok(output) = &output {
+            record(self, output);
+        }
+        output
+    }
+    fn exec_status(&mut self) -> io::Result<ExitStatus> {
+        debug!("running {}", command_line(self));
+        if !transcribing() {
+            return self.status();
+        }
+        eprintln!("$ {}", command_line(self));
+        let status = self.status();
+        transcribe_exit(status.as_ref().copied());
+        status
+    }
+    fn exec_status_timeout(&mut self, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+        debug!("running {} with timeout {timeout:?}", command_line(self));
+        if transcribing() {
+            eprintln!("$ {}", command_line(self));
+        }
+        let mut child = self.spawn()?;
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            if let Some(status) = child.try_wait()? {
+                if transcribing() {
+                    transcribe_exit(Ok(status));
+                }
+                return Ok(Some(status));
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        warn!("{} timed out after {timeout:?}", command_line(self));
+        kill_descendants(child.id());
+        let killed = Instant::now();
+        while killed.elapsed() < GRACE_PERIOD {
+            if child.try_wait()?.is_some() {
+                return Ok(None);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        child.kill()?;
+        child.wait()?;
+        Ok(None)
+    }
+}
+
+/// Returns the arguments to rerun seidr with under sudo, given the arguments
+/// it was run with
+///
+/// --sudo is dropped so sudo isn't run again, and the config is passed
+/// explicitly, as sudo may reset the home dir it defaults to.
+pub fn sudo_args<I>(args: I, config: &str) -> Vec<String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut rerun = vec![String::from("--config"), config.to_string()];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--sudo" => (),
+            "-c" | "--config" => {
+                args.next();
+            }
+            _ if arg.starts_with("--config=") => (),
+            _ => rerun.push(arg),
+        }
+    }
+    rerun
+}
@@ -0,0 +1,61 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Matching paths against shell style globs
+//!
+//! Globs are matched a path segment at a time. In a segment, `*` matches any
+//! run of characters and `?` any single character, while a `**` segment
+//! matches any number of whole segments, including none.
+
+use std::path::Path;
+
+/// Whether s has any glob characters
+pub fn is_glob(s: &str) -> bool {
+    s.contains(['*', '?'])
+}
+
+/// Whether the single segment name matches pattern
+fn segment_matches(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => {
+            segment_matches(&pattern[1..], name)
+                || (!name.is_empty() && segment_matches(pattern, &name[1..]))
+        }
+        (Some('?'), Some(_)) => segment_matches(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => segment_matches(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// Whether the segments of path match the segments of pattern
+fn segments_match(pattern: &[&str], path: &[&str]) -> bool {
+    match (pattern.first(), path.first()) {
+        (None, None) => true,
+        (Some(&"**"), _) => {
+            segments_match(&pattern[1..], path)
+                || (!path.is_empty() && segments_match(pattern, &path[1..]))
+        }
+        (Some(p), Some(s)) => {
+            let p: Vec<char> = p.chars().collect();
+            let s: Vec<char> = s.chars().collect();
+            segment_matches(&p, &s) && segments_match(&pattern[1..], &path[1..])
+        }
+        _ => false,
+    }
+}
+
+/// Splits s into its path segments, ignoring empty ones
+fn segments(s: &str) -> Vec<&str> {
+    s.split('/').filter(|segment| !segment.is_empty()).collect()
+}
+
+/// Whether path matches the glob pattern
+///
+/// Both are split on `/`, ignoring empty segments, so trailing slashes don't
+/// matter.
+pub fn matches(pattern: &str, path: &Path) -> bool {
+    segments_match(&segments(pattern), &segments(&path.to_string_lossy()))
+}
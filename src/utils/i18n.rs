@@ -0,0 +1,164 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Message catalog, for translating what seidr says to the user
+//!
+//! Every message has a key in `Msg`, and every locale a table from keys to
+//! text. Text can have `{name}` placeholders, filled in by `tr_with`. A locale
+//! that lacks a message falls back to English, so translations can be added a
+//! message at a time.
+//!
+//! To add a locale, add it to `Locale`, give it a table, and match its
+//! language code in `Locale::parse`.
+
+use std::env;
+use std::sync::OnceLock;
+
+/// A message seidr can show to the user
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Msg {
+    NothingToCommit,
+    NothingToRemove,
+    /// {op}
+    RunOp,
+    RemoveAll,
+    /// {category} {name}
+    CleanRepo,
+    /// {path}
+    NotManaged,
+    /// {dir} {category} {name}
+    NotCloned,
+    /// {config}
+    NotFormatted,
+    /// {config}
+    ConfigHasComments,
+    /// {config}
+    Formatted,
+}
+
+impl Msg {
+    /// Every message, for checking the tables
+    pub const ALL: &[Msg] = &[
+        Msg::NothingToCommit,
+        Msg::NothingToRemove,
+        Msg::RunOp,
+        Msg::RemoveAll,
+        Msg::CleanRepo,
+        Msg::NotManaged,
+        Msg::NotCloned,
+        Msg::NotFormatted,
+        Msg::ConfigHasComments,
+        Msg::Formatted,
+    ];
+}
+
+/// A language seidr can speak
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Locale {
+    En,
+    Da,
+}
+
+const EN: &[(Msg, &str)] = &[
+    (Msg::NothingToCommit, "nothing to commit"),
+    (Msg::NothingToRemove, "nothing to remove"),
+    (Msg::RunOp, "Run {op}?"),
+    (Msg::RemoveAll, "Remove all of the above?"),
+    (Msg::CleanRepo, "Clean {category} {name}?"),
+    (Msg::NotManaged, "{path} is not managed by seidr"),
+    (
+        Msg::NotCloned,
+        "{dir} doesn't exist, is {category} {name} cloned?",
+    ),
+    (Msg::NotFormatted, "{config} isn't formatted"),
+    (
+        Msg::ConfigHasComments,
+        "{config} has comments, which would be lost, use --force to format anyway",
+    ),
+    (Msg::Formatted, "formatted {config}"),
+];
+
+const DA: &[(Msg, &str)] = &[
+    (Msg::NothingToCommit, "intet at committe"),
+    (Msg::NothingToRemove, "intet at fjerne"),
+    (Msg::RunOp, "Kør {op}?"),
+    (Msg::RemoveAll, "Fjern alt ovenstående?"),
+    (Msg::CleanRepo, "Ryd op i {category} {name}?"),
+    (Msg::NotManaged, "{path} styres ikke af seidr"),
+    (
+        Msg::NotCloned,
+        "{dir} findes ikke, er {category} {name} klonet?",
+    ),
+    (Msg::NotFormatted, "{config} er ikke formateret"),
+    (
+        Msg::ConfigHasComments,
+        "{config} har kommentarer, som ville gå tabt, brug --force for at formatere alligevel",
+    ),
+    (Msg::Formatted, "formaterede {config}"),
+];
+
+impl Locale {
+    /// Parses a locale like `da_DK.UTF-8`, by its language code
+    pub fn parse(locale: &str) -> Option<Self> {
+        let language = locale.split(['_', '.', '@']).next()?;
+        match language {
+            "en" | "C" | "POSIX" => Some(Locale::En),
+            "da" => Some(Locale::Da),
+            _ => None,
+        }
+    }
+    /// The locale of the environment, from LC_ALL, LC_MESSAGES or LANG, in
+    /// that order, falling back to English
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Self::parse(&value))
+            .unwrap_or(Locale::En)
+    }
+    /// The table of the locale
+    pub fn table(&self) -> &'static [(Msg, &'static str)] {
+        match self {
+            Locale::En => EN,
+            Locale::Da => DA,
+        }
+    }
+    /// The text of msg in this locale, or in English if it isn't translated
+    pub fn text(&self, msg: Msg) -> &'static str {
+        let find = |table: &'static [(Msg, &'static str)]| {
+            table
+                .iter()
+                .find(|(key, _)| *key == msg)
+                .map(|(_, text)| *text)
+        };
+        find(self.table())
+            .or_else(|| find(EN))
+            .unwrap_or_else(|| panic!("{msg:?} has no English text"))
+    }
+}
+
+/// The locale of this process
+fn locale() -> Locale {
+    static LOCALE: OnceLock<Locale> = OnceLock::new();
+    *LOCALE.get_or_init(Locale::from_env)
+}
+
+/// The text of msg, in the locale of the environment
+pub fn tr(msg: Msg) -> &'static str {
+    locale().text(msg)
+}
+
+/// Fills in the `{name}` placeholders of text
+pub fn fill(text: &str, args: &[(&str, &str)]) -> String {
+    args.iter().fold(text.to_string(), |text, (name, value)| {
+        text.replace(&format!("{{{name}}}"), value)
+    })
+}
+
+/// The text of msg, with its placeholders filled in from args
+pub fn tr_with(msg: Msg, args: &[(&str, &str)]) -> String {
+    fill(tr(msg), args)
+}
@@ -0,0 +1,21 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Asking the user things on the terminal
+
+use std::io::{self, BufRead, Write};
+
+/// Asks a yes/no question, defaulting to no
+pub fn confirm(question: &str) -> bool {
+    eprint!("{question} [y/N] ");
+    if io::stderr().flush().is_err() {
+        return false;
+    }
+    let mut answer = String::new();
+    match io::stdin().lock().read_line(&mut answer) {
+        Ok(_) => matches!(answer.trim(), "y" | "Y" | "yes"),
+        Err(_) => false,
+    }
+}
@@ -5,12 +5,13 @@
 
 //! Module for chunk of text
 //!
-//! Ideally, at a VERY long term scale, this should be a nice pattern for
-//! possible translations.
+//! Messages that can be translated live in the catalog in `utils::i18n`, this
+//! is for text that stays the same in every locale.
 
 use std::sync::atomic::Ordering;
 
 use crate::settings;
+use crate::utils::term::{pad, width};
 
 /// Contains the notice for interactive programs from the GPLv3's "How to Apply
 /// These Terms to Your New Programs"
@@ -53,6 +54,9 @@ pub const QUICK_COMMIT: &str = "git: quick commit";
 /// Contains the message for fast commit subcommand
 pub const FAST_COMMIT: &str = "git: fast commit";
 
+/// Contains the message template for split commits, see `Repo::commit_split`
+pub const SPLIT_COMMIT: &str = "{dir}: quick commit";
+
 /// Success emoji
 pub const SUCCESS_EMOJI: &str = "✔";
 
@@ -80,3 +84,21 @@ pub fn failure_str() -> &'static str {
         FAILURE_EMOJI
     }
 }
+
+/// How many columns the wider of `success_str` and `failure_str` takes
+pub fn result_width() -> usize {
+    width(success_str()).max(width(failure_str()))
+}
+
+/// Returns `success_str` if succeeded, otherwise `failure_str`, padded to
+/// `result_width`, so what follows it lines up either way
+pub fn result_str(succeeded: bool) -> String {
+    pad(
+        if succeeded {
+            success_str()
+        } else {
+            failure_str()
+        },
+        result_width(),
+    )
+}
@@ -0,0 +1,53 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Suggesting what the user might have meant, when they name something that
+//! doesn't exist
+
+/// Returns the levenshtein distance between a and b
+///
+/// That is, the number of single character insertions, deletions and
+/// substitutions needed to turn a into b.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // Distances from the prefix of a seen so far, to each prefix of b
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a != *b);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Returns the candidate closest to name, if any is close enough to be a typo
+pub fn did_you_mean<'a, I>(name: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    // Allow about one typo per three characters, but always a transposition
+    let max = (name.chars().count() / 3).max(2);
+    candidates
+        .into_iter()
+        .map(|candidate| (levenshtein(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Adds a suggestion of what name might have meant to message, if any
+pub fn with_suggestion<'a, I>(message: String, name: &str, candidates: I) -> String
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    match did_you_mean(name, candidates) {
+        Some(suggestion) => format!("{message}, did you mean {suggestion}?"),
+        None => message,
+    }
+}
@@ -0,0 +1,320 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Helpers for drawing interactive interfaces on the terminal
+//!
+//! Deliberately dependency free, the terminal is put in raw mode with `stty`,
+//! and drawing is done with plain ANSI escape codes. In raw mode "\n" doesn't
+//! return the carriage, so lines must end in "\r\n".
+
+use clap::ValueEnum;
+use log::error;
+use std::env;
+use std::fs::File;
+use std::io::{self, IsTerminal};
+use std::process::{Command, Stdio};
+use std::sync::atomic::Ordering;
+use std::sync::OnceLock;
+
+use crate::settings;
+
+pub const CLEAR: &str = "\x1b[2J\x1b[H";
+pub const RESET: &str = "\x1b[0m";
+pub const BOLD: &str = "\x1b[1m";
+pub const REVERSE: &str = "\x1b[7m";
+pub const RED: &str = "\x1b[31m";
+pub const GREEN: &str = "\x1b[32m";
+pub const YELLOW: &str = "\x1b[33m";
+pub const BLUE: &str = "\x1b[34m";
+
+/// When to write colors
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, ValueEnum)]
+pub enum ColorChoice {
+    /// When writing to a terminal, unless the environment says otherwise
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Whether to write colors, for choice, where var reads the environment, and
+/// tty is whether output goes to a terminal
+///
+/// With auto, the conventions are followed in this order: a non-empty
+/// `NO_COLOR` disables colors, `CLICOLOR_FORCE` other than 0 forces them, and
+/// `CLICOLOR=0` disables them.
+pub fn use_color<F>(choice: ColorChoice, var: F, tty: bool) -> bool
+where
+    F: Fn(&str) -> Option<String>,
+{
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto if var("NO_COLOR").map_or(false, |value| !value.is_empty()) => false,
+        ColorChoice::Auto if var("CLICOLOR_FORCE").map_or(false, |value| value != "0") => true,
+        ColorChoice::Auto if var("CLICOLOR").as_deref() == Some("0") => false,
+        ColorChoice::Auto => tty,
+    }
+}
+
+/// Returns code if colors are written, see `settings::COLOR`, and nothing if
+/// they aren't
+pub fn color(code: &'static str) -> &'static str {
+    if settings::COLOR.load(Ordering::Relaxed) {
+        code
+    } else {
+        ""
+    }
+}
+
+/// Where human-facing output goes, for fitting it to the terminal
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Output {
+    Stdout,
+    Stderr,
+}
+
+/// Returns the width of the terminal, from `COLUMNS`, or asked of `stty`
+fn terminal_width() -> Option<usize> {
+    static WIDTH: OnceLock<Option<usize>> = OnceLock::new();
+    *WIDTH.get_or_init(|| {
+        if let Some(columns) = env::var("COLUMNS").ok().and_then(|cols| cols.parse().ok()) {
+            return Some(columns);
+        }
+        let tty = File::open("/dev/tty").ok()?;
+        let size = Command::new("stty")
+            .arg("size")
+            .stdin(Stdio::from(tty))
+            .output()
+            .ok()?;
+        let size = String::from_utf8_lossy(&size.stdout);
+        size.split_whitespace().nth(1)?.parse().ok()
+    })
+}
+
+/// Returns how many columns output has to fit in, None if it doesn't have to
+/// fit, as it isn't a terminal, or -v asks for full values
+pub fn columns(output: Output) -> Option<usize> {
+    let tty = match output {
+        Output::Stdout => io::stdout().is_terminal(),
+        Output::Stderr => io::stderr().is_terminal(),
+    };
+    if !tty || settings::VERBOSITY.load(Ordering::Relaxed) > 0 {
+        return None;
+    }
+    terminal_width().filter(|width| *width > 0)
+}
+
+/// Characters that take two columns, CJK and most emoji, as ranges of code
+/// points, sorted
+const WIDE: &[(u32, u32)] = &[
+    (0x1100, 0x115F),
+    (0x231A, 0x231B),
+    (0x2329, 0x232A),
+    (0x23E9, 0x23EC),
+    (0x23F0, 0x23F0),
+    (0x23F3, 0x23F3),
+    (0x25FD, 0x25FE),
+    (0x2614, 0x2615),
+    (0x2648, 0x2653),
+    (0x267F, 0x267F),
+    (0x2693, 0x2693),
+    (0x26A1, 0x26A1),
+    (0x26AA, 0x26AB),
+    (0x26BD, 0x26BE),
+    (0x26C4, 0x26C5),
+    (0x26CE, 0x26CE),
+    (0x26D4, 0x26D4),
+    (0x26EA, 0x26EA),
+    (0x26F2, 0x26F3),
+    (0x26F5, 0x26F5),
+    (0x26FA, 0x26FA),
+    (0x26FD, 0x26FD),
+    (0x2705, 0x2705),
+    (0x270A, 0x270B),
+    (0x2728, 0x2728),
+    (0x274C, 0x274C),
+    (0x274E, 0x274E),
+    (0x2753, 0x2755),
+    (0x2757, 0x2757),
+    (0x2795, 0x2797),
+    (0x27B0, 0x27B0),
+    (0x27BF, 0x27BF),
+    (0x2B1B, 0x2B1C),
+    (0x2B50, 0x2B50),
+    (0x2B55, 0x2B55),
+    (0x2E80, 0x303E),
+    (0x3041, 0x33FF),
+    (0x3400, 0x4DBF),
+    (0x4E00, 0x9FFF),
+    (0xA000, 0xA4CF),
+    (0xA960, 0xA97F),
+    (0xAC00, 0xD7A3),
+    (0xF900, 0xFAFF),
+    (0xFE10, 0xFE19),
+    (0xFE30, 0xFE6F),
+    (0xFF00, 0xFF60),
+    (0xFFE0, 0xFFE6),
+    (0x1F004, 0x1F004),
+    (0x1F0CF, 0x1F0CF),
+    (0x1F18E, 0x1F18E),
+    (0x1F191, 0x1F19A),
+    (0x1F200, 0x1F251),
+    (0x1F300, 0x1F64F),
+    (0x1F680, 0x1F6FF),
+    (0x1F7E0, 0x1F7EB),
+    (0x1F900, 0x1F9FF),
+    (0x1FA70, 0x1FAFF),
+    (0x20000, 0x3FFFD),
+];
+
+/// Characters that take no columns, combining marks, zero width spaces and
+/// joiners, and variation selectors, as ranges of code points, sorted
+const ZERO_WIDTH: &[(u32, u32)] = &[
+    (0x0300, 0x036F),
+    (0x0483, 0x0489),
+    (0x0591, 0x05BD),
+    (0x0610, 0x061A),
+    (0x064B, 0x065F),
+    (0x0E31, 0x0E31),
+    (0x0E34, 0x0E3A),
+    (0x0E47, 0x0E4E),
+    (0x1AB0, 0x1AFF),
+    (0x1DC0, 0x1DFF),
+    (0x200B, 0x200F),
+    (0x20D0, 0x20FF),
+    (0xFE00, 0xFE0F),
+    (0xFE20, 0xFE2F),
+    (0xE0100, 0xE01EF),
+];
+
+fn in_ranges(c: char, ranges: &[(u32, u32)]) -> bool {
+    let c = c as u32;
+    ranges
+        .binary_search_by(|(start, end)| {
+            if *end < c {
+                std::cmp::Ordering::Less
+            } else if *start > c {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        })
+        .is_ok()
+}
+
+/// Returns how many columns c takes on the terminal
+pub fn char_width(c: char) -> usize {
+    if c.is_control() || in_ranges(c, ZERO_WIDTH) {
+        0
+    } else if in_ranges(c, WIDE) {
+        2
+    } else {
+        1
+    }
+}
+
+/// Returns how many columns text takes on the terminal, leaving out ANSI
+/// escape codes like those of `color`
+pub fn width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Up to and including the letter ending the code
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+            continue;
+        }
+        width += char_width(c);
+    }
+    width
+}
+
+/// Pads text with spaces to columns, like `{:<columns}` does for text where
+/// every character takes a column, see `width`
+pub fn pad(text: &str, columns: usize) -> String {
+    format!("{text}{}", " ".repeat(columns.saturating_sub(width(text))))
+}
+
+/// Shortens text to at most max columns by cutting out its middle, which
+/// keeps the start and, favored, the end of a path or url, like
+/// `~/src/…/seidr/config.yaml`
+pub fn truncate_middle(text: &str, max: usize) -> String {
+    if width(text) <= max {
+        return text.to_string();
+    }
+    if max == 0 {
+        return String::new();
+    }
+    // Takes characters from chars while they fit in room
+    let take = |chars: &mut dyn Iterator<Item = char>, room: usize| -> (Vec<char>, usize) {
+        let mut taken = vec![];
+        let mut used = 0;
+        for c in chars {
+            if used + char_width(c) > room {
+                break;
+            }
+            used += char_width(c);
+            taken.push(c);
+        }
+        (taken, used)
+    };
+    let kept = max - 1;
+    let (head, used) = take(&mut text.chars(), kept / 3);
+    let (tail, _) = take(&mut text.chars().rev(), kept - used);
+    let mut truncated: String = head.into_iter().collect();
+    truncated.push('…');
+    truncated.extend(tail.into_iter().rev());
+    truncated
+}
+
+/// Fits text to the terminal output goes to, less used columns already taken
+/// on the line, see `columns`
+pub fn fit(text: &str, output: Output, used: usize) -> String {
+    match columns(output) {
+        Some(width) => truncate_middle(text, width.saturating_sub(used)),
+        None => text.to_string(),
+    }
+}
+
+/// Puts the terminal in raw mode, and restores it again when dropped
+pub struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    pub fn enable() -> io::Result<Self> {
+        let saved = Command::new("stty")
+            .arg("-g")
+            .stdin(Stdio::inherit())
+            .output()?;
+        let saved = String::from_utf8_lossy(&saved.stdout).trim().to_string();
+        Command::new("stty")
+            .arg("raw")
+            .arg("-echo")
+            .stdin(Stdio::inherit())
+            .status()?;
+        // Hide the cursor while we're drawing
+        eprint!("\x1b[?25l");
+        Ok(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        eprint!("\x1b[?25h");
+        if let Err(err) = Command::new("stty")
+            .arg(&self.saved)
+            .stdin(Stdio::inherit())
+            .status()
+        {
+            error!("failed to restore terminal: {err}");
+        }
+    }
+}
@@ -0,0 +1,186 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Parsing of git remote urls
+//!
+//! Git accepts a few different shapes of urls, this handles the common ones:
+//!
+//! - `https://host/owner/name.git`
+//! - `ssh://git@host:22/owner/name.git`
+//! - `git@host:owner/name.git` (the scp-like syntax)
+//! - `file:///srv/git/name.git` and local paths like `/srv/git/name.git`
+//!
+//! Like git, anything without a scheme that has a `/` before the first `:`, or
+//! no `:` at all, is a local path.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// How the remote is accessed
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UrlScheme {
+    Https,
+    Http,
+    Ssh,
+    Git,
+    File,
+}
+
+impl fmt::Display for UrlScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UrlScheme::Https => write!(f, "https"),
+            UrlScheme::Http => write!(f, "http"),
+            UrlScheme::Ssh => write!(f, "ssh"),
+            UrlScheme::Git => write!(f, "git"),
+            UrlScheme::File => write!(f, "file"),
+        }
+    }
+}
+
+/// A parsed git remote url
+#[derive(PartialEq, Eq, Debug)]
+pub struct GitUrl {
+    pub scheme: UrlScheme,
+    /// The user, if any, e.g. `git` in `git@github.com:cafkafk/seidr.git`
+    pub user: Option<String>,
+    /// The host, without port. Empty for file urls
+    pub host: String,
+    /// The path segments, with any `.git` suffix stripped from the last one
+    pub segments: Vec<String>,
+}
+
+impl GitUrl {
+    /// Parses a git remote url
+    pub fn parse(url: &str) -> Result<Self, String> {
+        if url.is_empty() {
+            return Err(String::from("url is empty"));
+        }
+        let (scheme, rest) = match url.split_once("://") {
+            Some(("https", rest)) => (UrlScheme::Https, rest),
+            Some(("http", rest)) => (UrlScheme::Http, rest),
+            Some(("ssh", rest)) => (UrlScheme::Ssh, rest),
+            Some(("git", rest)) => (UrlScheme::Git, rest),
+            Some(("file", rest)) => (UrlScheme::File, rest),
+            Some((scheme, _)) => return Err(format!("unsupported scheme {scheme}")),
+            None => return Self::parse_scp_like(url),
+        };
+        if scheme == UrlScheme::File {
+            return Ok(GitUrl {
+                scheme,
+                user: None,
+                host: String::new(),
+                segments: Self::segments(rest),
+            });
+        }
+        let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+        let (user, host) = match authority.rsplit_once('@') {
+            Some((user, host)) => (Some(user.to_string()), host),
+            None => (None, authority),
+        };
+        let host = host.split(':').next().unwrap_or_default();
+        if host.is_empty() {
+            return Err(format!("{url} has no host"));
+        }
+        Ok(GitUrl {
+            scheme,
+            user,
+            host: host.to_string(),
+            segments: Self::segments(path),
+        })
+    }
+    /// Parses the `user@host:path` syntax git uses for ssh, or a local path
+    fn parse_scp_like(url: &str) -> Result<Self, String> {
+        let (authority, path) = match url.split_once(':') {
+            Some((authority, path)) if !authority.contains('/') => (authority, path),
+            _ => {
+                return Ok(GitUrl {
+                    scheme: UrlScheme::File,
+                    user: None,
+                    host: String::new(),
+                    segments: Self::segments(url),
+                })
+            }
+        };
+        let (user, host) = match authority.split_once('@') {
+            Some((user, host)) => (Some(user.to_string()), host),
+            None => (None, authority),
+        };
+        if host.is_empty() {
+            return Err(format!("{url} has no host"));
+        }
+        Ok(GitUrl {
+            scheme: UrlScheme::Ssh,
+            user,
+            host: host.to_string(),
+            segments: Self::segments(path),
+        })
+    }
+    fn segments(path: &str) -> Vec<String> {
+        let mut segments: Vec<String> = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(str::to_string)
+            .collect();
+        if let Some(last) = segments.last_mut() {
+            if let Some(stripped) = last.strip_suffix(".git") {
+                *last = stripped.to_string();
+            }
+        }
+        segments
+    }
+    /// Whether the remote is on the local filesystem
+    pub fn is_local(&self) -> bool {
+        self.scheme == UrlScheme::File
+    }
+    /// Returns the name of the repository, the last path segment
+    pub fn repo_name(&self) -> Option<&str> {
+        self.segments.last().map(String::as_str)
+    }
+    /// Returns what identifies the repository regardless of how it's accessed,
+    /// its host and path, like `github.com/cafkafk/seidr`
+    ///
+    /// The scheme, user and port are left out, so the https and ssh urls of a
+    /// repository have the same identity. Local paths have no host, and are
+    /// identified by their absolute path, like `/srv/git/name`.
+    pub fn identity(&self) -> String {
+        format!("{}/{}", self.host.to_lowercase(), self.segments.join("/"))
+    }
+}
+
+/// Returns the identity of url, see `GitUrl::identity`, or None if it can't be
+/// parsed
+pub fn identity(url: &str) -> Option<String> {
+    GitUrl::parse(url).ok().map(|url| url.identity())
+}
+
+/// Rewrites the host of an ssh url to its alias in aliases, like
+/// `git@github.com:owner/name` to `git@github-work:owner/name`, matching a
+/// `Host` alias in `~/.ssh/config`
+///
+/// Returns None if url isn't an ssh url, or its host has no alias.
+pub fn alias_ssh_host(url: &str, aliases: &BTreeMap<String, String>) -> Option<String> {
+    let parsed = GitUrl::parse(url).ok()?;
+    if parsed.scheme != UrlScheme::Ssh {
+        return None;
+    }
+    let alias = aliases.get(&parsed.host)?;
+    let user = parsed.user.map_or(String::new(), |user| format!("{user}@"));
+    match url.strip_prefix("ssh://") {
+        Some(rest) => {
+            let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+            let port = authority
+                .rsplit_once('@')
+                .map_or(authority, |(_, host)| host)
+                .split_once(':')
+                .map_or(String::new(), |(_, port)| format!(":{port}"));
+            Some(format!("ssh://{user}{alias}{port}/{path}"))
+        }
+        None => {
+            let (_, path) = url.split_once(':')?;
+            Some(format!("{user}{alias}:{path}"))
+        }
+    }
+}
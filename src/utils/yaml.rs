@@ -0,0 +1,221 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Finding duplicate keys in yaml, and editing it in place
+//!
+//! serde_yaml keeps the last of two equal keys in a mapping, so a copy-pasted
+//! repo silently shadows the one it was copied from. Deserializing never sees
+//! where a key was, so the keys are found in the text itself instead, by
+//! following the indentation of block mappings. Flow mappings, like
+//! `{a: 1}`, are left to serde_yaml.
+//!
+//! Editing the config the same way, rather than writing it anew from what was
+//! deserialized, keeps its comments and the order of its keys, see
+//! `set_scalar`.
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// A key that appears twice in the same mapping
+#[derive(PartialEq, Eq, Debug)]
+pub struct Duplicate {
+    /// The keys leading to the mapping, like `categories.work.repos`
+    pub path: String,
+    pub key: String,
+    /// The line the key is first on, which the second one shadows
+    pub first: usize,
+    pub second: usize,
+}
+
+impl fmt::Display for Duplicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "duplicate key {}", self.key)?;
+        if !self.path.is_empty() {
+            write!(f, " in {}", self.path)?;
+        }
+        write!(f, ", on line {} and line {}", self.first, self.second)
+    }
+}
+
+/// A block mapping being read
+struct Mapping {
+    indent: usize,
+    /// The key the mapping is the value of
+    name: Option<String>,
+    /// The keys seen so far, with the line they are on
+    keys: HashMap<String, usize>,
+    last_key: Option<String>,
+}
+
+impl Mapping {
+    fn new(indent: usize, name: Option<String>) -> Self {
+        Mapping {
+            indent,
+            name,
+            keys: HashMap::new(),
+            last_key: None,
+        }
+    }
+}
+
+/// Splits the key off line, returning it and the value after it
+///
+/// Returns None if line isn't a key, like a scalar in a sequence, or a line
+/// of a multi-line scalar.
+fn split_key(line: &str) -> Option<(String, &str)> {
+    let (key, rest) = match line.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let end = line[1..].find(quote)? + 1;
+            (line[1..end].to_string(), line[end + 1..].trim_start())
+        }
+        '{' | '[' | '#' | '|' | '>' => return None,
+        _ => {
+            let end = line
+                .find(": ")
+                .or_else(|| line.strip_suffix(':').map(str::len))?;
+            (line[..end].trim_end().to_string(), &line[end..])
+        }
+    };
+    let value = rest.strip_prefix(':')?;
+    if !value.is_empty() && !value.starts_with([' ', '\t']) {
+        return None;
+    }
+    Some((key, value.trim()))
+}
+
+/// Finds the keys in yaml that appear twice in the same mapping, in the order
+/// the second of them appear
+pub fn duplicate_keys(yaml: &str) -> Vec<Duplicate> {
+    let mut duplicates = vec![];
+    let mut mappings: Vec<Mapping> = vec![Mapping::new(0, None)];
+    // Lines of a block scalar are indented further than its key
+    let mut block_scalar: Option<usize> = None;
+    for (number, line) in yaml.lines().enumerate() {
+        let content = line.trim_start_matches(' ');
+        let mut indent = line.len() - content.len();
+        let mut content = content.trim_end();
+        if let Some(key_indent) = block_scalar {
+            if content.is_empty() || indent > key_indent {
+                continue;
+            }
+            block_scalar = None;
+        }
+        if content.is_empty() || content.starts_with('#') {
+            continue;
+        }
+        if content.starts_with("---") {
+            mappings = vec![Mapping::new(0, None)];
+            continue;
+        }
+        // Each item of a sequence is a mapping of its own
+        let mut item = false;
+        while let Some(rest) = content.strip_prefix('-') {
+            if !rest.is_empty() && !rest.starts_with(' ') {
+                break;
+            }
+            let rest = rest.trim_start();
+            indent += content.len() - rest.len();
+            content = rest;
+            item = true;
+        }
+        if item {
+            mappings.retain(|mapping| mapping.indent < indent);
+        }
+        let Some((key, value)) = split_key(content) else {
+            continue;
+        };
+        mappings.retain(|mapping| mapping.indent <= indent);
+        if mappings
+            .last()
+            .map_or(true, |mapping| mapping.indent < indent)
+        {
+            let name = mappings.last().and_then(|mapping| mapping.last_key.clone());
+            mappings.push(Mapping::new(indent, name));
+        }
+        let path = mappings
+            .iter()
+            .filter_map(|mapping| mapping.name.as_deref())
+            .collect::<Vec<_>>()
+            .join(".");
+        let mapping = mappings.last_mut().expect("there is always a mapping");
+        // Merge keys may repeat, they don't shadow anything
+        if key != "<<" {
+            if let Some(first) = mapping.keys.insert(key.clone(), number + 1) {
+                duplicates.push(Duplicate {
+                    path,
+                    key: key.clone(),
+                    first,
+                    second: number + 1,
+                });
+            }
+        }
+        mapping.last_key = Some(key);
+        if value.starts_with(['|', '>']) {
+            block_scalar = Some(indent);
+        }
+    }
+    duplicates
+}
+
+/// Replaces the scalar at path, like `categories.dots.links.vim.rx`, with
+/// value, leaving the rest of yaml as it is
+///
+/// Returns None if path isn't a scalar in block mappings of yaml.
+pub fn set_scalar(yaml: &str, path: &[&str], value: &str) -> Option<String> {
+    let (last, parents) = path.split_last()?;
+    let value = serde_yaml::to_string(value).ok()?;
+    let mut lines: Vec<String> = yaml.lines().map(str::to_string).collect();
+    // The indent of the key of the mapping being searched, and of the keys in
+    // it, which is only known once the first of them is
+    let mut parent: Option<usize> = None;
+    let mut indent: Option<usize> = Some(0);
+    let mut depth = 0;
+    for line in lines.iter_mut() {
+        let content = line.trim_start_matches(' ');
+        let line_indent = line.len() - content.len();
+        let content = content.trim_end();
+        if content.is_empty() || content.starts_with('#') {
+            continue;
+        }
+        if parent.map_or(false, |parent| line_indent <= parent) {
+            return None;
+        }
+        if *indent.get_or_insert(line_indent) != line_indent {
+            continue;
+        }
+        let Some((key, rest)) = split_key(content) else {
+            continue;
+        };
+        if depth < parents.len() {
+            if key == parents[depth] {
+                depth += 1;
+                parent = Some(line_indent);
+                indent = None;
+            }
+            continue;
+        }
+        if key != *last || rest.is_empty() || rest.starts_with(['|', '>', '{', '[', '&', '*']) {
+            continue;
+        }
+        // A comment after a plain scalar is kept
+        let comment = match rest.starts_with(['"', '\'']) {
+            false => rest.find(" #").map_or("", |at| &rest[at..]),
+            true => "",
+        };
+        let end = content.len() - rest.len();
+        *line = format!(
+            "{}{} {}{comment}",
+            " ".repeat(line_indent),
+            content[..end].trim_end(),
+            value.trim_end()
+        );
+        let mut edited = lines.join("\n");
+        if yaml.ends_with('\n') {
+            edited.push('\n');
+        }
+        return Some(edited);
+    }
+    None
+}
@@ -0,0 +1,187 @@
+// SPDX-FileCopyrightText: 2023 Christina Sørensen
+// SPDX-FileContributor: Christina Sørensen
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Verifying that what links point at is committed
+//!
+//! A link to a file that was never committed works just fine, until the disk
+//! it's on dies. The files of the tx of every link, all of them for a dir, are
+//! hashed the way git would hash them, and compared to the blobs committed
+//! at HEAD of the repo they're in, whether or not the config has that repo.
+//! Symlinks inside a tx, and repos nested in it, are left out.
+
+use log::{debug, error, info, trace, warn};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::git::{Config, Link};
+use crate::utils::exec::Exec;
+
+/// How many paths are hashed by a single git, keeping command lines short
+const HASH_BATCH: usize = 512;
+
+/// What isn't committed about the tx of a link
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Problem {
+    /// There is nothing at tx
+    Missing,
+    /// tx isn't in a git repo
+    NotInRepo,
+    /// A file, relative to its repo, isn't committed at all
+    Uncommitted(PathBuf),
+    /// A file, relative to its repo, changed since it was committed
+    Changed(PathBuf),
+    /// The repo couldn't be read
+    Failed(String),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Missing => write!(f, "tx doesn't exist"),
+            Problem::NotInRepo => write!(f, "tx isn't in a git repo"),
+            Problem::Uncommitted(path) => write!(f, "{} was never committed", path.display()),
+            Problem::Changed(path) => {
+                write!(f, "{} changed since it was committed", path.display())
+            }
+            Problem::Failed(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+/// A problem with the tx of a link
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Unverified {
+    pub category: String,
+    pub name: String,
+    pub problem: Problem,
+}
+
+/// Runs git in dir, returning its stdout
+fn git(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .exec_output()
+        .map_err(|err| format!("failed to run git: {err}"))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Lists the files in path, or path itself if it is one, leaving out symlinks
+/// and nested repos
+fn files(path: &Path, into: &mut Vec<PathBuf>) {
+    let Ok(metadata) = path.symlink_metadata() else {
+        return;
+    };
+    if metadata.is_file() {
+        into.push(path.to_path_buf());
+    } else if metadata.is_dir() && !path.join(".git").exists() {
+        for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+            files(&entry.path(), into);
+        }
+    }
+}
+
+/// Checks that the files of the tx of link are committed, as they are, in
+/// the repo they are in
+pub fn check_link(link: &Link) -> Vec<Problem> {
+    // The tx itself may be a symlink, into the repo it's really in
+    let Ok(tx) = fs::canonicalize(&link.tx) else {
+        return vec![Problem::Missing];
+    };
+    let dir = if tx.is_dir() {
+        tx.as_path()
+    } else {
+        tx.parent().unwrap_or(&tx)
+    };
+    let toplevel = match git(dir, &["rev-parse", "--show-toplevel"]) {
+        Ok(toplevel) => PathBuf::from(toplevel.trim()),
+        Err(err) => {
+            debug!("{}: {err}", link.tx);
+            return vec![Problem::NotInRepo];
+        }
+    };
+    let mut found = vec![];
+    if tx.is_dir() {
+        for entry in fs::read_dir(&tx).into_iter().flatten().flatten() {
+            files(&entry.path(), &mut found);
+        }
+    } else {
+        found.push(tx.clone());
+    }
+    found.sort();
+    let relative: Vec<String> = found
+        .iter()
+        .filter_map(|file| file.strip_prefix(&toplevel).ok())
+        .map(|file| file.to_string_lossy().to_string())
+        .collect();
+    let tx_relative = tx
+        .strip_prefix(&toplevel)
+        .map_or(String::new(), |tx| tx.to_string_lossy().to_string());
+    // A repo without commits has nothing committed
+    let mut committed = HashMap::new();
+    if git(&toplevel, &["rev-parse", "--verify", "--quiet", "HEAD"]).is_ok() {
+        let mut ls_tree = vec!["ls-tree", "-r", "-z", "--full-tree", "HEAD", "--"];
+        if !tx_relative.is_empty() {
+            ls_tree.push(&tx_relative);
+        }
+        match git(&toplevel, &ls_tree) {
+            Ok(tree) => {
+                for line in tree.split('\0') {
+                    // Like `100644 blob <hash>\t<path>`, with path unquoted
+                    if let Some((meta, path)) = line.split_once('\t') {
+                        if let Some(hash) = meta.split(' ').nth(2) {
+                            committed.insert(path.to_string(), hash.to_string());
+                        }
+                    }
+                }
+            }
+            Err(err) => return vec![Problem::Failed(err)],
+        }
+    }
+    let mut problems = vec![];
+    for batch in relative.chunks(HASH_BATCH) {
+        let mut hash_object = vec!["hash-object", "--"];
+        hash_object.extend(batch.iter().map(String::as_str));
+        let hashes = match git(&toplevel, &hash_object) {
+            Ok(hashes) => hashes,
+            Err(err) => return vec![Problem::Failed(err)],
+        };
+        for (path, hash) in batch.iter().zip(hashes.lines()) {
+            match committed.get(path) {
+                None => problems.push(Problem::Uncommitted(PathBuf::from(path))),
+                Some(committed) if committed != hash => {
+                    problems.push(Problem::Changed(PathBuf::from(path)))
+                }
+                Some(_) => (),
+            }
+        }
+    }
+    problems
+}
+
+/// Checks the tx of every link in config, returning what isn't committed,
+/// sorted by category and name
+pub fn links(config: &Config) -> Vec<Unverified> {
+    let mut unverified = vec![];
+    for (category, cat) in config.categories.iter() {
+        for (name, link) in cat.links.iter().flatten() {
+            for problem in check_link(link) {
+                unverified.push(Unverified {
+                    category: category.clone(),
+                    name: name.clone(),
+                    problem,
+                });
+            }
+        }
+    }
+    unverified.sort_by(|a, b| (&a.category, &a.name).cmp(&(&b.category, &b.name)));
+    unverified
+}
@@ -3,7 +3,90 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-only
 
+use seidr::git::Config;
+use seidr::report::RunReport;
+
 #[test]
+#[allow(clippy::assertions_on_constants)]
 fn main() {
     assert!(true);
 }
+
+#[test]
+fn report_repos_by_category() {
+    let config = Config::new(&"./src/test/config.yaml".to_string());
+    let report = config.report_repos("noop", |repo| repo.name.is_some());
+    let names: Vec<&str> = report.categories.iter().map(|c| c.name.as_str()).collect();
+    let mut sorted = names.clone();
+    sorted.sort();
+    assert_eq!(names, sorted);
+    assert!(report.succeeded());
+    let yaml = serde_yaml::to_string(&report).expect("failed to serialize report");
+    let parsed: RunReport = serde_yaml::from_str(&yaml).expect("failed to deserialize report");
+    assert_eq!(parsed, report);
+}
+
+#[test]
+fn settings_are_per_config() {
+    let mut quiet = Config::new(&"./src/test/config.yaml".to_string());
+    quiet.settings.quiet = true;
+    quiet.settings.deadline = Some(1);
+    let config = Config::new(&"./src/test/config.yaml".to_string());
+    // Past its deadline, the quiet config starts nothing, and the other isn't
+    // affected by that
+    let report = quiet.report_repos("noop", |_| true);
+    assert!(report
+        .categories
+        .iter()
+        .flat_map(|category| &category.repos)
+        .all(|repo| repo.skipped));
+    assert!(!config.settings.out_of_time());
+    assert!(config.report_repos("noop", |_| true).succeeded());
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn async_engine_runs() {
+    use seidr::async_engine::AsyncEngine;
+    use std::future::Future;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake};
+    use std::thread::{self, Thread};
+
+    /// Wakes by unparking the thread blocking on the future
+    struct Unpark(Thread);
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Arc::new(Unpark(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    let config = Config::new(&"./src/test/config.yaml".to_string());
+    let engine = AsyncEngine::new(config, 1);
+    let runs: Vec<_> = (0..3)
+        .map(|_| engine.run(|config| config.report_repos("noop", |repo| repo.name.is_some())))
+        .collect();
+    for run in runs {
+        let report = block_on(run);
+        assert!(report.succeeded());
+        assert!(!report.categories.is_empty());
+    }
+
+    // A run that panics panics where it is awaited, and gives its turn back
+    let panicked = engine.run(|_| -> bool { panic!("run panicked") });
+    let after = engine.run(|config| !config.categories.is_empty());
+    let caught = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| block_on(panicked)));
+    assert!(caught.is_err());
+    assert!(block_on(after));
+}